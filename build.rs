@@ -1,6 +1,7 @@
 use std::fs::{read_dir, File};
 use std::io::{Result, Write};
-use std::path::PathBuf;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
 use toml_edit::{DocumentMut, Item, Table};
 
@@ -9,69 +10,88 @@ fn main() {
     println!("cargo:rerun-if-changed=./apps/rust/src");
     println!("cargo:rerun-if-changed=.makeargs");
     let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap();
-    link_app_data(&arch).unwrap();
+    pack_initramfs(&arch).unwrap();
     gen_kernel_config(&arch).unwrap();
 }
 
-fn link_app_data(arch: &str) -> Result<()> {
+/// Pack the apps into a cpio archive in the "newc" format, which the kernel
+/// unpacks into its root filesystem at boot.
+fn pack_initramfs(arch: &str) -> Result<()> {
     let testcase = option_env!("AX_TESTCASE").unwrap_or("nimbos");
 
     let app_path = PathBuf::from(format!("apps/{}/build/{}", testcase, arch));
-    let link_app_path = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("link_app.S");
+    let archive_path = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("initramfs.cpio");
+    println!("cargo:rerun-if-changed={}", app_path.display());
 
-    if let Ok(dir) = read_dir(&app_path) {
-        let mut apps = dir
-            .into_iter()
-            .map(|dir_entry| dir_entry.unwrap().file_name().into_string().unwrap())
-            .collect::<Vec<_>>();
-        apps.sort();
+    let mut archive = CpioWriter::default();
+    if app_path.is_dir() {
+        archive.add_dir(&app_path, "")?;
+    }
+    archive.add_entry("TRAILER!!!", 0, &[])?;
+    std::fs::write(archive_path, archive.data)
+}
+
+/// A writer of cpio archives in the "newc" format.
+#[derive(Default)]
+struct CpioWriter {
+    data: Vec<u8>,
+    next_ino: u32,
+}
 
-        let mut f = File::create(link_app_path)?;
-        writeln!(
-            f,
-            "
-.section .data
-.balign 8
-.global _app_count
-_app_count:
-    .quad {}",
-            apps.len()
+impl CpioWriter {
+    /// Append an entry with the full `mode` including the file type bits.
+    fn add_entry(&mut self, name: &str, mode: u32, content: &[u8]) -> Result<()> {
+        self.next_ino += 1;
+        write!(
+            self.data,
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            self.next_ino,  // ino
+            mode,           // mode
+            0,              // uid
+            0,              // gid
+            1,              // nlink
+            0,              // mtime
+            content.len(),  // filesize
+            0,              // devmajor
+            0,              // devminor
+            0,              // rdevmajor
+            0,              // rdevminor
+            name.len() + 1, // namesize
+            0,              // check
         )?;
-        for i in 0..apps.len() {
-            writeln!(f, "    .quad app_{}_name", i)?;
-            writeln!(f, "    .quad app_{}_start", i)?;
-        }
-        writeln!(f, "    .quad app_{}_end", apps.len() - 1)?;
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.align();
+        self.data.extend_from_slice(content);
+        self.align();
+        Ok(())
+    }
 
-        for (idx, app) in apps.iter().enumerate() {
-            println!("app_{}: {}", idx, app_path.join(app).display());
-            writeln!(
-                f,
-                "
-app_{0}_name:
-    .string \"{1}\"
-.balign 8
-app_{0}_start:
-    .incbin \"{2}\"
-app_{0}_end:",
-                idx,
-                app,
-                app_path.join(app).display()
-            )?;
+    /// Append the contents of `dir` recursively, with names prefixed by
+    /// `prefix`.
+    fn add_dir(&mut self, dir: &Path, prefix: &str) -> Result<()> {
+        let mut entries = read_dir(dir)?.collect::<Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = format!("{}{}", prefix, entry.file_name().into_string().unwrap());
+            let metadata = std::fs::metadata(entry.path())?;
+            let mode = metadata.permissions().mode();
+            if metadata.is_dir() {
+                println!("initramfs: {}/", name);
+                self.add_entry(&name, mode, &[])?;
+                self.add_dir(&entry.path(), &format!("{}/", name))?;
+            } else if metadata.is_file() {
+                println!("initramfs: {}", name);
+                self.add_entry(&name, mode, &std::fs::read(entry.path())?)?;
+            }
         }
-    } else {
-        let mut f = File::create(link_app_path)?;
-        writeln!(
-            f,
-            "
-.section .data
-.balign 8
-.global _app_count
-_app_count:
-    .quad 0"
-        )?;
+        Ok(())
+    }
+
+    /// Pad the archive to a multiple of 4 bytes.
+    fn align(&mut self) {
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
     }
-    Ok(())
 }
 
 fn gen_kernel_config(arch: &str) -> Result<()> {
@@ -108,6 +128,13 @@ fn gen_kernel_config(arch: &str) -> Result<()> {
                 toml_edit::Value::String(s) => {
                     writeln!(f, "pub const {}: &str = \"{}\";", key_name, s)?;
                 }
+                toml_edit::Value::Array(array) => {
+                    let strs = array
+                        .iter()
+                        .map(|value| value.as_str().expect("Unsupported array item type"))
+                        .collect::<Vec<_>>();
+                    writeln!(f, "pub const {}: &[&str] = &{:?};", key_name, strs)?;
+                }
                 _ => {
                     panic!("Unsupported value type");
                 }
@@ -0,0 +1,216 @@
+//! The device filesystem, mounted at `/dev`.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::any::Any;
+
+use axerrno::{LinuxError, LinuxResult};
+
+use super::{
+    file::{FileLike, OpenFlags},
+    initramfs,
+    tty::Tty,
+    vfs::{DirEntry, NodeStat, NodeType, VfsNode},
+};
+use crate::{ptr::UserPtr, random};
+
+/// Get the entropy count of the random pool, for `/dev/random`.
+const RNDGETENTCNT: u32 = 0x8004_5200;
+/// Get the size of a block device in 512-byte sectors.
+const BLKGETSIZE: u32 = 0x1260;
+/// Get the logical block size of a block device.
+const BLKSSZGET: u32 = 0x1268;
+/// Get the size of a block device in bytes.
+const BLKGETSIZE64: u32 = 0x8008_1272;
+
+/// The sector size of block devices.
+const SECTOR_SIZE: u64 = 512;
+
+/// What a device node does.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DeviceKind {
+    /// Reads return end of file, and writes are discarded.
+    Null,
+    /// Reads return zeros, and writes are discarded.
+    Zero,
+    /// Reads return zeros, and writes fail with `ENOSPC`.
+    Full,
+    /// Reads return random bytes, and writes are discarded.
+    Random,
+    /// The terminal on the console.
+    Tty,
+    /// The initramfs, as a read-only block device.
+    Initrd,
+}
+
+/// A device node in `/dev`.
+struct Device {
+    kind: DeviceKind,
+    /// The device number, i.e. `(major << 8) | minor`.
+    rdev: u64,
+    mode: u32,
+}
+
+/// The device nodes, with the numbers and permissions of Linux.
+const DEVICES: &[(&str, Device)] = &[
+    ("console", Device::new(DeviceKind::Tty, 5, 1, 0o600)),
+    ("full", Device::new(DeviceKind::Full, 1, 7, 0o666)),
+    ("initrd", Device::new(DeviceKind::Initrd, 1, 250, 0o400)),
+    ("null", Device::new(DeviceKind::Null, 1, 3, 0o666)),
+    ("random", Device::new(DeviceKind::Random, 1, 8, 0o666)),
+    ("tty", Device::new(DeviceKind::Tty, 5, 0, 0o666)),
+    ("urandom", Device::new(DeviceKind::Random, 1, 9, 0o666)),
+    ("zero", Device::new(DeviceKind::Zero, 1, 5, 0o666)),
+];
+
+impl Device {
+    const fn new(kind: DeviceKind, major: u64, minor: u64, mode: u32) -> Self {
+        Self {
+            kind,
+            rdev: (major << 8) | minor,
+            mode,
+        }
+    }
+
+    fn node_type(&self) -> NodeType {
+        match self.kind {
+            DeviceKind::Initrd => NodeType::BlockDevice,
+            _ => NodeType::CharDevice,
+        }
+    }
+}
+
+/// A node of devfs, which refers to an entry of [`DEVICES`].
+#[derive(Clone, Copy)]
+struct DevNode(&'static Device);
+
+impl VfsNode for DevNode {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        let size = match self.0.kind {
+            DeviceKind::Initrd => initramfs::archive().len() as u64,
+            _ => 0,
+        };
+        Ok(NodeStat {
+            // The device numbers are unique among the nodes.
+            ino: self.0.rdev,
+            node_type: self.0.node_type(),
+            mode: self.0.mode,
+            nlink: 1,
+            size,
+            rdev: self.0.rdev,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        match self.0.kind {
+            DeviceKind::Null => Ok(0),
+            DeviceKind::Zero | DeviceKind::Full => {
+                buf.fill(0);
+                Ok(buf.len())
+            }
+            DeviceKind::Random => {
+                random::fill_bytes(buf);
+                Ok(buf.len())
+            }
+            DeviceKind::Tty => Tty::new(OpenFlags::O_RDWR).read(buf),
+            DeviceKind::Initrd => {
+                let data = initramfs::archive();
+                let start = (offset as usize).min(data.len());
+                let len = buf.len().min(data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                Ok(len)
+            }
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        match self.0.kind {
+            DeviceKind::Null | DeviceKind::Zero | DeviceKind::Random => Ok(buf.len()),
+            DeviceKind::Full => Err(LinuxError::ENOSPC),
+            DeviceKind::Tty => Tty::new(OpenFlags::O_RDWR).write(buf),
+            DeviceKind::Initrd => Err(LinuxError::EROFS),
+        }
+    }
+
+    fn truncate(&self, _size: u64) -> LinuxResult {
+        // Truncating a device is a no-op, e.g. for `O_TRUNC`.
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        match (self.0.kind, cmd) {
+            (DeviceKind::Random, RNDGETENTCNT) => {
+                // The pool is always full.
+                UserPtr::<i32>::from(arg).write(256)?;
+            }
+            (DeviceKind::Initrd, BLKGETSIZE) => {
+                let sectors = (initramfs::archive().len() as u64).div_ceil(SECTOR_SIZE);
+                UserPtr::<usize>::from(arg).write(sectors as usize)?;
+            }
+            (DeviceKind::Initrd, BLKSSZGET) => {
+                UserPtr::<i32>::from(arg).write(SECTOR_SIZE as i32)?;
+            }
+            (DeviceKind::Initrd, BLKGETSIZE64) => {
+                UserPtr::<u64>::from(arg).write(initramfs::archive().len() as u64)?;
+            }
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+
+    fn open(&self, flags: OpenFlags) -> Option<Arc<dyn FileLike>> {
+        match self.0.kind {
+            DeviceKind::Tty => Some(Arc::new(Tty::new(flags))),
+            _ => None,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The root directory of devfs, which holds the device nodes.
+pub struct DevDir;
+
+impl DevDir {
+    /// The root directory of devfs.
+    pub fn root() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+impl VfsNode for DevDir {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        Ok(NodeStat {
+            ino: 1,
+            node_type: NodeType::Dir,
+            mode: 0o755,
+            nlink: 2,
+            size: 0,
+            rdev: 0,
+        })
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+        DEVICES
+            .iter()
+            .find(|(device, _)| *device == name)
+            .map(|(_, device)| Arc::new(DevNode(device)) as _)
+            .ok_or(LinuxError::ENOENT)
+    }
+
+    fn read_dir(&self) -> LinuxResult<Vec<DirEntry>> {
+        Ok(DEVICES
+            .iter()
+            .map(|(name, device)| DirEntry {
+                name: (*name).into(),
+                ino: device.rdev,
+                node_type: device.node_type(),
+            })
+            .collect())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
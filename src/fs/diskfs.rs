@@ -0,0 +1,155 @@
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::{
+    api,
+    fops::{File, OpenOptions},
+};
+
+use super::vfs::{DirEntry, NodeStat, NodeType, VfsNode};
+
+/// The inode number of a file on the disk, derived from its path since the
+/// filesystem API of ArceOS does not expose one.
+fn ino_of(path: &str) -> u64 {
+    // FNV-1a
+    path.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+/// The type of a node with the given file type on the disk.
+fn node_type_of(file_type: api::FileType) -> NodeType {
+    if file_type.is_dir() {
+        NodeType::Dir
+    } else if file_type.is_file() {
+        NodeType::File
+    } else {
+        NodeType::CharDevice
+    }
+}
+
+/// A file or directory of the filesystem on the block device, which ArceOS
+/// mounts at its own root.
+///
+/// Nodes are identified by their paths on the disk, and each operation goes
+/// through the path-based filesystem API of ArceOS.
+pub struct DiskNode {
+    path: String,
+}
+
+impl DiskNode {
+    /// The root directory of the disk.
+    pub fn root() -> Arc<Self> {
+        Arc::new(Self { path: "/".into() })
+    }
+
+    fn child_path(&self, name: &str) -> String {
+        if self.path == "/" {
+            format!("/{}", name)
+        } else {
+            format!("{}/{}", self.path, name)
+        }
+    }
+
+    fn child(&self, name: &str) -> Arc<Self> {
+        Arc::new(Self {
+            path: self.child_path(name),
+        })
+    }
+
+    fn open(&self, write: bool) -> LinuxResult<File> {
+        let mut opts = OpenOptions::new();
+        opts.read(true);
+        opts.write(write);
+        Ok(File::open(&self.path, &opts)?)
+    }
+}
+
+impl VfsNode for DiskNode {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        let metadata = api::metadata(&self.path)?;
+        let node_type = node_type_of(metadata.file_type());
+        Ok(NodeStat {
+            ino: ino_of(&self.path),
+            node_type,
+            mode: metadata.permissions().bits() as u32,
+            nlink: if node_type == NodeType::Dir { 2 } else { 1 },
+            size: metadata.len(),
+            rdev: 0,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        Ok(self.open(false)?.read_at(offset, buf)?)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        Ok(self.open(true)?.write_at(offset, buf)?)
+    }
+
+    fn truncate(&self, size: u64) -> LinuxResult {
+        Ok(self.open(true)?.truncate(size)?)
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+        let node = self.child(name);
+        api::metadata(&node.path)?;
+        Ok(node)
+    }
+
+    fn create(&self, name: &str, node_type: NodeType, _mode: u32) -> LinuxResult<Arc<dyn VfsNode>> {
+        let node = self.child(name);
+        match node_type {
+            NodeType::Dir => api::create_dir(&node.path)?,
+            NodeType::File => {
+                let mut opts = OpenOptions::new();
+                opts.write(true);
+                opts.create_new(true);
+                File::open(&node.path, &opts)?;
+            }
+            _ => return Err(LinuxError::EPERM),
+        }
+        Ok(node)
+    }
+
+    fn unlink(&self, name: &str) -> LinuxResult {
+        let path = self.child_path(name);
+        if api::metadata(&path)?.is_dir() {
+            api::remove_dir(&path)?;
+        } else {
+            api::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&self, old_name: &str, new_parent: &Arc<dyn VfsNode>, new_name: &str) -> LinuxResult {
+        let new_parent = new_parent
+            .as_any()
+            .downcast_ref::<DiskNode>()
+            .ok_or(LinuxError::EXDEV)?;
+        api::rename(&self.child_path(old_name), &new_parent.child_path(new_name))?;
+        Ok(())
+    }
+
+    fn read_dir(&self) -> LinuxResult<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in api::read_dir(&self.path)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name == "." || name == ".." {
+                continue;
+            }
+            entries.push(DirEntry {
+                ino: ino_of(&self.child_path(&name)),
+                node_type: node_type_of(entry.file_type()),
+                name,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
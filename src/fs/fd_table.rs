@@ -0,0 +1,164 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use axerrno::{LinuxError, LinuxResult};
+
+use super::{
+    file::{FileLike, OpenFlags},
+    lock,
+    tty::Tty,
+};
+use crate::task::Pid;
+
+/// The maximum number of file descriptors of a process.
+pub const MAX_FDS: usize = 1024;
+
+#[derive(Clone)]
+struct FdEntry {
+    file: Arc<dyn FileLike>,
+    cloexec: bool,
+}
+
+impl FdEntry {
+    /// Close the descriptor in the process `pid`, which releases the record
+    /// locks of the process on the file, even if other descriptors still
+    /// refer to it.
+    fn close(self, pid: Pid) {
+        if let Ok(key) = self.file.lock_key() {
+            lock::unlock(key, pid, 0, u64::MAX);
+        }
+    }
+}
+
+/// The file descriptor table of a process, which may be shared with other
+/// processes created with `CLONE_FILES`.
+#[derive(Clone, Default)]
+pub struct FdTable {
+    entries: Vec<Option<FdEntry>>,
+}
+
+impl FdTable {
+    /// Create a table with the standard input, output and error, which are
+    /// the console.
+    pub fn new_with_stdio() -> Self {
+        let stdin: Arc<dyn FileLike> = Arc::new(Tty::new(OpenFlags::empty()));
+        let stdout: Arc<dyn FileLike> = Arc::new(Tty::new(OpenFlags::O_WRONLY));
+        let entry = |file: &Arc<dyn FileLike>| {
+            Some(FdEntry {
+                file: file.clone(),
+                cloexec: false,
+            })
+        };
+        Self {
+            entries: vec![entry(&stdin), entry(&stdout), entry(&stdout)],
+        }
+    }
+
+    fn entry(&self, fd: i32) -> LinuxResult<&FdEntry> {
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| self.entries.get(fd))
+            .and_then(Option::as_ref)
+            .ok_or(LinuxError::EBADF)
+    }
+
+    /// The file that `fd` refers to.
+    pub fn get(&self, fd: i32) -> LinuxResult<Arc<dyn FileLike>> {
+        Ok(self.entry(fd)?.file.clone())
+    }
+
+    /// The open file descriptors and the files that they refer to, in
+    /// ascending order.
+    pub fn files(&self) -> impl Iterator<Item = (i32, &Arc<dyn FileLike>)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, entry)| Some((fd as i32, &entry.as_ref()?.file)))
+    }
+
+    /// Add `file` with the lowest free file descriptor, and return it.
+    pub fn add(&mut self, file: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<i32> {
+        self.add_from(0, file, cloexec)
+    }
+
+    /// Add `file` with the lowest free file descriptor not less than
+    /// `min_fd`, and return it.
+    pub fn add_from(
+        &mut self,
+        min_fd: usize,
+        file: Arc<dyn FileLike>,
+        cloexec: bool,
+    ) -> LinuxResult<i32> {
+        let free = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(min_fd)
+            .position(|(_, entry)| entry.is_none());
+        let fd = match free {
+            Some(pos) => min_fd + pos,
+            None if self.entries.len().max(min_fd) < MAX_FDS => self.entries.len().max(min_fd),
+            None => return Err(LinuxError::EMFILE),
+        };
+        self.entries.resize(self.entries.len().max(fd + 1), None);
+        self.entries[fd] = Some(FdEntry { file, cloexec });
+        Ok(fd as i32)
+    }
+
+    /// Make `fd` refer to `file`, closing the file that it referred to in
+    /// the process `pid`.
+    pub fn set(
+        &mut self,
+        fd: i32,
+        file: Arc<dyn FileLike>,
+        cloexec: bool,
+        pid: Pid,
+    ) -> LinuxResult {
+        let fd = usize::try_from(fd)
+            .ok()
+            .filter(|&fd| fd < MAX_FDS)
+            .ok_or(LinuxError::EBADF)?;
+        if fd >= self.entries.len() {
+            self.entries.resize(fd + 1, None);
+        }
+        if let Some(old) = self.entries[fd].replace(FdEntry { file, cloexec }) {
+            old.close(pid);
+        }
+        Ok(())
+    }
+
+    /// Whether `fd` has the close-on-exec flag.
+    pub fn cloexec(&self, fd: i32) -> LinuxResult<bool> {
+        Ok(self.entry(fd)?.cloexec)
+    }
+
+    /// Set or clear the close-on-exec flag of `fd`.
+    pub fn set_cloexec(&mut self, fd: i32, cloexec: bool) -> LinuxResult {
+        self.entry(fd)?;
+        if let Some(entry) = &mut self.entries[fd as usize] {
+            entry.cloexec = cloexec;
+        }
+        Ok(())
+    }
+
+    /// Close `fd` in the process `pid`.
+    pub fn close(&mut self, fd: i32, pid: Pid) -> LinuxResult {
+        self.entry(fd)?;
+        self.entries[fd as usize].take().unwrap().close(pid);
+        Ok(())
+    }
+
+    /// Close the file descriptors with the close-on-exec flag, when the
+    /// process `pid` calls `execve`.
+    pub fn close_on_exec(&mut self, pid: Pid) {
+        for entry in self.entries.iter_mut() {
+            if entry.as_ref().is_some_and(|entry| entry.cloexec) {
+                entry.take().unwrap().close(pid);
+            }
+        }
+    }
+
+    /// Close all file descriptors, when the process exits.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
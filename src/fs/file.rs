@@ -0,0 +1,311 @@
+use alloc::{string::String, sync::Arc};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axsync::Mutex;
+
+use super::{
+    lock::LockKey,
+    vfs::{DirEntry, NodeStat, NodeType, VfsNode},
+};
+use crate::mm::MappedFile;
+
+bitflags::bitflags! {
+    /// Flags for `openat`, of which the access mode and the status flags are
+    /// kept by the opened files.
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/fcntl.h>
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        /// Open for writing only.
+        const O_WRONLY = 0o1;
+        /// Open for reading and writing.
+        const O_RDWR = 0o2;
+        /// Create the file if it does not exist.
+        const O_CREAT = 0o100;
+        /// Fail if the file exists, with `O_CREAT`.
+        const O_EXCL = 0o200;
+        /// Do not make the terminal the controlling terminal.
+        const O_NOCTTY = 0o400;
+        /// Truncate the file to zero length.
+        const O_TRUNC = 0o1000;
+        /// Write at the end of the file.
+        const O_APPEND = 0o2000;
+        /// Do not block on reads and writes.
+        const O_NONBLOCK = 0o4000;
+        /// Fail if the path is not a directory.
+        #[cfg(not(target_arch = "aarch64"))]
+        const O_DIRECTORY = 0o200000;
+        /// Fail if the path is not a directory.
+        #[cfg(target_arch = "aarch64")]
+        const O_DIRECTORY = 0o40000;
+        /// Close the file descriptor on `execve`.
+        const O_CLOEXEC = 0o2000000;
+    }
+}
+
+impl OpenFlags {
+    /// Whether the file is opened for reading.
+    pub fn readable(self) -> bool {
+        !self.contains(Self::O_WRONLY)
+    }
+
+    /// Whether the file is opened for writing.
+    pub fn writable(self) -> bool {
+        self.intersects(Self::O_WRONLY | Self::O_RDWR)
+    }
+
+    /// The status flags that `fcntl` can change.
+    pub const SETTABLE: Self = Self::O_APPEND.union(Self::O_NONBLOCK);
+
+    /// Replace the settable status flags of `self` with those in `flags`.
+    pub fn with_settable(self, flags: Self) -> Self {
+        (self - Self::SETTABLE) | (flags & Self::SETTABLE)
+    }
+}
+
+/// Where `lseek` starts from.
+#[derive(Debug, Clone, Copy)]
+pub enum SeekFrom {
+    /// From the start of the file.
+    Start(u64),
+    /// From the current position.
+    Current(i64),
+    /// From the end of the file.
+    End(i64),
+}
+
+/// An opened file, which a file descriptor refers to.
+///
+/// Besides regular files and directories, it can be anything that can be
+/// read or written, such as the console. The default implementations fail
+/// as the operations of an unseekable stream would.
+pub trait FileLike: Send + Sync {
+    /// Read from the current position into `buf`, and return the number of
+    /// bytes read.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize>;
+
+    /// Write `buf` at the current position, and return the number of bytes
+    /// written.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize>;
+
+    /// The attributes of the file.
+    fn stat(&self) -> LinuxResult<NodeStat>;
+
+    /// The access mode and the status flags.
+    fn status_flags(&self) -> OpenFlags;
+
+    /// Change the status flags in [`OpenFlags::SETTABLE`] to those in
+    /// `flags`, for `fcntl`. They are ignored by default.
+    fn set_status_flags(&self, _flags: OpenFlags) {}
+
+    /// Read at `offset` into `buf` without changing the current position.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Write `buf` at `offset` without changing the current position.
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Move the current position, and return the new one.
+    fn seek(&self, _pos: SeekFrom) -> LinuxResult<u64> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Pass the directory entries from the current position to `f` until it
+    /// returns `false`, and move the position past the accepted entries.
+    fn read_dir(&self, _f: &mut dyn FnMut(&DirEntry) -> bool) -> LinuxResult {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// The absolute path of the file, if it is in the filesystem.
+    fn path(&self) -> Option<&str> {
+        None
+    }
+
+    /// The file to map into memory, if it supports `mmap`.
+    fn mapped_file(&self) -> Option<Arc<dyn MappedFile>> {
+        None
+    }
+
+    /// The key of the record locks on the file. Files outside the mounted
+    /// filesystems, like pipes, are told apart by their inode numbers.
+    fn lock_key(&self) -> LinuxResult<LockKey> {
+        Ok((0, self.stat()?.ino))
+    }
+
+    /// Handle the device-specific request `cmd` of `ioctl` with the argument
+    /// `arg`. Only terminals support requests, so it fails with `ENOTTY` by
+    /// default.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<isize> {
+        Err(LinuxError::ENOTTY)
+    }
+}
+
+/// A regular file or a directory opened from the filesystem.
+pub struct File {
+    node: Arc<dyn VfsNode>,
+    path: String,
+    /// The identifier of the filesystem that the node is in.
+    fs_id: u64,
+    flags: AtomicU32,
+    /// The current position, which is the index of the next entry for
+    /// directories.
+    offset: Mutex<u64>,
+}
+
+impl File {
+    /// Create a file opened at `path` with `flags`, whose node is in the
+    /// filesystem `fs_id`.
+    pub fn new(node: Arc<dyn VfsNode>, path: String, fs_id: u64, flags: OpenFlags) -> Self {
+        Self {
+            node,
+            path,
+            fs_id,
+            flags: AtomicU32::new(flags.bits()),
+            offset: Mutex::new(0),
+        }
+    }
+
+    fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.flags.load(Ordering::Relaxed))
+    }
+
+    fn check_readable(&self) -> LinuxResult {
+        if !self.flags().readable() {
+            return Err(LinuxError::EBADF);
+        }
+        if self.node.stat()?.node_type == NodeType::Dir {
+            return Err(LinuxError::EISDIR);
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> LinuxResult {
+        if !self.flags().writable() {
+            return Err(LinuxError::EBADF);
+        }
+        Ok(())
+    }
+}
+
+impl FileLike for File {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.check_readable()?;
+        let mut offset = self.offset.lock();
+        let len = self.node.read_at(*offset, buf)?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.check_writable()?;
+        let mut offset = self.offset.lock();
+        if self.flags().contains(OpenFlags::O_APPEND) {
+            *offset = self.node.stat()?.size;
+        }
+        let len = self.node.write_at(*offset, buf)?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        self.node.stat()
+    }
+
+    fn status_flags(&self) -> OpenFlags {
+        self.flags()
+    }
+
+    fn set_status_flags(&self, flags: OpenFlags) {
+        let flags = self.flags().with_settable(flags);
+        self.flags.store(flags.bits(), Ordering::Relaxed);
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.check_readable()?;
+        self.node.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        self.check_writable()?;
+        self.node.write_at(offset, buf)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut offset = self.offset.lock();
+        let new_offset = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.node.stat()?.size.checked_add_signed(delta),
+        };
+        *offset = new_offset.ok_or(LinuxError::EINVAL)?;
+        Ok(*offset)
+    }
+
+    fn read_dir(&self, f: &mut dyn FnMut(&DirEntry) -> bool) -> LinuxResult {
+        let stat = self.node.stat()?;
+        if stat.node_type != NodeType::Dir {
+            return Err(LinuxError::ENOTDIR);
+        }
+        let dots = [".", ".."].map(|name| DirEntry {
+            name: name.into(),
+            ino: stat.ino,
+            node_type: NodeType::Dir,
+        });
+        let mut offset = self.offset.lock();
+        let entries = self.node.read_dir()?;
+        for entry in dots.iter().chain(entries.iter()).skip(*offset as usize) {
+            if !f(entry) {
+                break;
+            }
+            *offset += 1;
+        }
+        Ok(())
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn mapped_file(&self) -> Option<Arc<dyn MappedFile>> {
+        Some(Arc::new(MappedNode {
+            node: self.node.clone(),
+            path: self.path.clone(),
+        }))
+    }
+
+    fn lock_key(&self) -> LinuxResult<LockKey> {
+        Ok((self.fs_id, self.node.stat()?.ino))
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        self.node.ioctl(cmd, arg)
+    }
+}
+
+/// A node of the filesystem mapped into memory.
+struct MappedNode {
+    node: Arc<dyn VfsNode>,
+    path: String,
+}
+
+impl MappedFile for MappedNode {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        self.node.read_at(offset, buf).map_err(|_| AxError::Io)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        self.node.write_at(offset, buf).map_err(|_| AxError::Io)
+    }
+
+    fn size(&self) -> AxResult<u64> {
+        Ok(self.node.stat().map_err(|_| AxError::Io)?.size)
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+}
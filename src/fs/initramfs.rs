@@ -0,0 +1,94 @@
+//! The cpio archive of apps embedded in the kernel by `build.rs`.
+
+use core::str;
+
+use super::vfs::NodeType;
+
+/// The archive in the "newc" format.
+static ARCHIVE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.cpio"));
+
+/// The size of the header of each entry.
+const HEADER_SIZE: usize = 110;
+/// The bits of `mode` that select the file type.
+const S_IFMT: u32 = 0o170000;
+/// The file type of directories.
+const S_IFDIR: u32 = 0o040000;
+/// The file type of regular files.
+const S_IFREG: u32 = 0o100000;
+
+/// An entry of the archive.
+pub struct Entry {
+    /// The path relative to the root, without a leading `/`.
+    pub name: &'static str,
+    /// The file type and permission bits.
+    pub mode: u32,
+    /// The content of a regular file.
+    pub data: &'static [u8],
+}
+
+impl Entry {
+    /// The type of the entry, or `None` for the types that the VFS does not
+    /// support, such as symbolic links.
+    pub fn node_type(&self) -> Option<NodeType> {
+        match self.mode & S_IFMT {
+            S_IFDIR => Some(NodeType::Dir),
+            S_IFREG => Some(NodeType::File),
+            _ => None,
+        }
+    }
+}
+
+/// An iterator over the entries of an archive, which stops at the trailer
+/// or at the first malformed entry.
+struct Entries {
+    rest: &'static [u8],
+}
+
+impl Entries {
+    fn parse_next(&mut self) -> Option<Entry> {
+        let header = self.rest.get(..HEADER_SIZE)?;
+        if &header[..6] != b"070701" {
+            return None;
+        }
+        let field = |index: usize| {
+            let hex = str::from_utf8(&header[6 + index * 8..][..8]).ok()?;
+            usize::from_str_radix(hex, 16).ok()
+        };
+        let mode = field(1)? as u32;
+        let file_size = field(6)?;
+        let name_size = field(11)?;
+
+        let name = self.rest.get(HEADER_SIZE..HEADER_SIZE + name_size)?;
+        let name = str::from_utf8(name.strip_suffix(b"\0")?).ok()?;
+        let data_start = (HEADER_SIZE + name_size).next_multiple_of(4);
+        let data = self.rest.get(data_start..data_start + file_size)?;
+        let next = (data_start + file_size).next_multiple_of(4);
+        self.rest = self.rest.get(next..).unwrap_or_default();
+        if name == "TRAILER!!!" {
+            return None;
+        }
+        Some(Entry { name, mode, data })
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let entry = self.parse_next();
+        if entry.is_none() {
+            self.rest = &[];
+        }
+        entry
+    }
+}
+
+/// The embedded archive itself.
+pub fn archive() -> &'static [u8] {
+    ARCHIVE
+}
+
+/// The entries of the embedded archive.
+pub fn entries() -> impl Iterator<Item = Entry> {
+    Entries { rest: ARCHIVE }
+}
@@ -0,0 +1,142 @@
+//! POSIX advisory record locks, as set by `fcntl(F_SETLK)`.
+//!
+//! Locks are owned by processes and identified by the filesystems and the
+//! inode numbers of the locked files. They are released when the owner
+//! closes any descriptor of the file or exits.
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+
+use super::wait::WaitList;
+use crate::task::Pid;
+
+/// The kind of a record lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// A shared lock for reading.
+    Read,
+    /// An exclusive lock for writing.
+    Write,
+}
+
+/// A lock of the bytes in `[start, end)` of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordLock {
+    /// The process that owns the lock.
+    pub pid: Pid,
+    /// The first byte locked.
+    pub start: u64,
+    /// The end of the locked bytes, which is `u64::MAX` for the locks that
+    /// extend to the end of the file however it grows.
+    pub end: u64,
+    /// The kind of the lock.
+    pub kind: LockKind,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    /// Whether `self` prevents `other` from being set.
+    fn conflicts_with(&self, other: &RecordLock) -> bool {
+        self.pid != other.pid
+            && self.overlaps(other.start, other.end)
+            && (self.kind == LockKind::Write || other.kind == LockKind::Write)
+    }
+}
+
+/// The file that a lock is on: the identifier of its filesystem, which is 0
+/// outside the mounted filesystems, and its inode number.
+pub type LockKey = (u64, u64);
+
+/// The locks of all files.
+static LOCKS: Mutex<BTreeMap<LockKey, Vec<RecordLock>>> = Mutex::new(BTreeMap::new());
+
+/// The threads waiting in `F_SETLKW`.
+static LOCK_WAITERS: WaitList = WaitList::new();
+
+/// Find a lock of the file `key` that prevents `lock` from being set.
+pub fn find_conflict(key: LockKey, lock: &RecordLock) -> Option<RecordLock> {
+    LOCKS
+        .lock()
+        .get(&key)?
+        .iter()
+        .find(|other| other.conflicts_with(lock))
+        .copied()
+}
+
+/// Remove the parts in `[start, end)` of the locks of `pid` in `locks`.
+fn remove_range(locks: &mut Vec<RecordLock>, pid: Pid, start: u64, end: u64) {
+    let mut rest = Vec::new();
+    locks.retain(|lock| {
+        if lock.pid != pid || !lock.overlaps(start, end) {
+            return true;
+        }
+        if lock.start < start {
+            rest.push(RecordLock {
+                end: start,
+                ..*lock
+            });
+        }
+        if end < lock.end {
+            rest.push(RecordLock {
+                start: end,
+                ..*lock
+            });
+        }
+        false
+    });
+    locks.extend(rest);
+}
+
+/// Set `lock` on the file `key`, replacing the locks of the same process in
+/// the range.
+///
+/// If another process holds a conflicting lock, `EAGAIN` is returned, or
+/// the current thread waits for it to be released if `wait` is true.
+pub fn set_lock(key: LockKey, lock: RecordLock, wait: bool) -> LinuxResult {
+    loop {
+        let mut all_locks = LOCKS.lock();
+        let locks = all_locks.entry(key).or_default();
+        if !locks.iter().any(|other| other.conflicts_with(&lock)) {
+            remove_range(locks, lock.pid, lock.start, lock.end);
+            locks.push(lock);
+            drop(all_locks);
+            // A lock may have been downgraded from writing to reading.
+            LOCK_WAITERS.wake_all();
+            return Ok(());
+        }
+        drop(all_locks);
+        if !wait {
+            return Err(LinuxError::EAGAIN);
+        }
+        LOCK_WAITERS.wait_until(|| find_conflict(key, &lock).is_none())?;
+    }
+}
+
+/// Release the locks of `pid` in `[start, end)` of the file `key`.
+pub fn unlock(key: LockKey, pid: Pid, start: u64, end: u64) {
+    let mut all_locks = LOCKS.lock();
+    if let Some(locks) = all_locks.get_mut(&key) {
+        remove_range(locks, pid, start, end);
+        if locks.is_empty() {
+            all_locks.remove(&key);
+        }
+    }
+    drop(all_locks);
+    LOCK_WAITERS.wake_all();
+}
+
+/// Release all locks of `pid`, when the process exits.
+pub fn unlock_all(pid: Pid) {
+    let mut all_locks = LOCKS.lock();
+    for locks in all_locks.values_mut() {
+        locks.retain(|lock| lock.pid != pid);
+    }
+    all_locks.retain(|_, locks| !locks.is_empty());
+    drop(all_locks);
+    LOCK_WAITERS.wake_all();
+}
@@ -0,0 +1,126 @@
+//! The virtual filesystem.
+//!
+//! Filesystems are trees of [`VfsNode`](vfs::VfsNode)s mounted at absolute
+//! paths. Opened files are [`FileLike`] objects, which the file descriptors
+//! in the [`FdTable`] of each process refer to.
+
+mod devfs;
+#[cfg(feature = "fs")]
+mod diskfs;
+mod fd_table;
+mod file;
+pub mod initramfs;
+pub mod lock;
+mod mount;
+mod path;
+mod pipe;
+mod procfs;
+#[cfg(not(feature = "fs"))]
+mod tmpfs;
+mod tty;
+mod vfs;
+mod wait;
+
+use alloc::{string::String, sync::Arc};
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+pub use self::fd_table::{FdTable, MAX_FDS};
+pub use self::file::{File, FileLike, OpenFlags, SeekFrom};
+pub use self::mount::{is_mount_point, lookup, lookup_in_fs, lookup_link, lookup_parent, mount};
+pub use self::pipe::Pipe;
+pub use self::tty::set_foreground_pgrp;
+use self::vfs::VfsNode;
+pub use self::vfs::{NodeStat, NodeType};
+
+/// The directory file descriptor that stands for the current directory.
+pub const AT_FDCWD: i32 = -100;
+
+/// Mount the root filesystem and the pseudo filesystems, and start the
+/// terminal on the console.
+///
+/// With the `fs` feature, the root is the filesystem on the block device,
+/// which ArceOS has mounted at its own root. Otherwise it is an in-memory
+/// filesystem with the contents of the initramfs.
+pub fn init() {
+    tty::init();
+    #[cfg(feature = "fs")]
+    mount("/", "vfat", diskfs::DiskNode::root()).expect("failed to mount the root filesystem");
+    #[cfg(not(feature = "fs"))]
+    {
+        mount("/", "tmpfs", tmpfs::TmpNode::new_dir(0o755))
+            .expect("failed to mount the root filesystem");
+        unpack_initramfs().expect("failed to unpack the initramfs");
+    }
+    mount_pseudo("/dev", "devtmpfs", devfs::DevDir::root()).expect("failed to mount devfs");
+    mount_pseudo("/proc", "proc", procfs::ProcDir::root()).expect("failed to mount procfs");
+}
+
+/// Mount a filesystem that is not backed by storage at `path`, creating the
+/// directory if it does not exist.
+fn mount_pseudo(path: &str, fs_type: &'static str, root: Arc<dyn VfsNode>) -> LinuxResult {
+    let (parent, name) = lookup_parent(path)?;
+    match parent.create(name, NodeType::Dir, 0o555) {
+        Ok(_) | Err(LinuxError::EEXIST) => {}
+        Err(err) => return Err(err),
+    }
+    mount(path, fs_type, root)
+}
+
+/// Unpack the initramfs into the root filesystem.
+///
+/// Directories come before their contents in the archive, which `build.rs`
+/// ensures. Entries of unsupported types are skipped.
+#[cfg(not(feature = "fs"))]
+fn unpack_initramfs() -> LinuxResult {
+    for entry in initramfs::entries() {
+        let Some(node_type) = entry.node_type() else {
+            warn!("initramfs: skipping {}: unsupported file type", entry.name);
+            continue;
+        };
+        let path = path::normalize("/", entry.name);
+        let (parent, name) = match lookup_parent(&path) {
+            Ok(res) => res,
+            // The root directory itself, which exists already.
+            Err(LinuxError::EEXIST) => continue,
+            Err(err) => return Err(err),
+        };
+        match parent.create(name, node_type, entry.mode & 0o7777) {
+            Ok(node) if node_type == NodeType::File => {
+                node.write_at(0, entry.data)?;
+            }
+            Ok(_) => {}
+            Err(LinuxError::EEXIST) if node_type == NodeType::Dir => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// The file that `fd` of the current process refers to.
+pub fn get_file(fd: i32) -> LinuxResult<Arc<dyn FileLike>> {
+    current().task_ext().process.fd_table.lock().get(fd)
+}
+
+/// Resolve `path` relative to the directory `dirfd` of the current process
+/// (or its current directory for `AT_FDCWD`), and return the normalized
+/// absolute path.
+pub fn absolute_path(dirfd: i32, path: &str) -> LinuxResult<String> {
+    if path.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    if path.starts_with('/') {
+        return Ok(path::normalize("/", path));
+    }
+    if dirfd == AT_FDCWD {
+        let cwd = current().task_ext().process.cwd.lock().clone();
+        return Ok(path::normalize(&cwd, path));
+    }
+    let dir = get_file(dirfd)?;
+    if dir.stat()?.node_type != NodeType::Dir {
+        return Err(LinuxError::ENOTDIR);
+    }
+    let base = dir.path().ok_or(LinuxError::ENOTDIR)?;
+    Ok(path::normalize(base, path))
+}
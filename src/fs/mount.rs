@@ -0,0 +1,166 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+
+use super::{
+    path::{components, normalize, split_parent},
+    vfs::{NodeType, VfsNode},
+};
+
+/// The maximum number of symbolic links followed in a lookup, as on Linux.
+const MAX_SYMLINKS: usize = 40;
+
+/// A mounted filesystem.
+#[derive(Clone)]
+struct MountPoint {
+    /// The absolute path where the filesystem is mounted.
+    path: String,
+    /// The type of the filesystem, e.g. `tmpfs`.
+    fs_type: &'static str,
+    /// The root directory of the filesystem.
+    root: Arc<dyn VfsNode>,
+    /// The identifier of the filesystem, which is unique since filesystems
+    /// are never unmounted.
+    fs_id: u64,
+}
+
+/// The mounted filesystems.
+static MOUNTS: Mutex<Vec<MountPoint>> = Mutex::new(Vec::new());
+
+/// Whether `path` is `prefix` or inside it.
+fn is_under(path: &str, prefix: &str) -> bool {
+    prefix == "/"
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Mount the filesystem of type `fs_type` with the root node `root` at the
+/// absolute path `path`, which must be an existing directory unless it is
+/// `/`.
+pub fn mount(path: &str, fs_type: &'static str, root: Arc<dyn VfsNode>) -> LinuxResult {
+    if path != "/" && lookup(path)?.stat()?.node_type != NodeType::Dir {
+        return Err(LinuxError::ENOTDIR);
+    }
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|mount| mount.path == path) {
+        return Err(LinuxError::EBUSY);
+    }
+    let fs_id = mounts.len() as u64 + 1;
+    mounts.push(MountPoint {
+        path: path.into(),
+        fs_type,
+        root,
+        fs_id,
+    });
+    Ok(())
+}
+
+/// Whether a filesystem is mounted at the absolute path `path`.
+pub fn is_mount_point(path: &str) -> bool {
+    MOUNTS.lock().iter().any(|mount| mount.path == path)
+}
+
+/// The mount points and the types of the mounted filesystems, in the order
+/// they were mounted.
+pub fn mounts() -> Vec<(String, &'static str)> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| (mount.path.clone(), mount.fs_type))
+        .collect()
+}
+
+/// The result of walking a path until the first symbolic link to follow.
+enum Walk {
+    /// The node at the end of the path, and the identifier of its
+    /// filesystem.
+    Node(Arc<dyn VfsNode>, u64),
+    /// The path with the symbolic link replaced by its target.
+    Link(String),
+}
+
+/// Walk the normalized absolute path `path` from the filesystem mounted
+/// on it, stopping at the first symbolic link to follow.
+fn walk(path: &str, follow_last: bool) -> LinuxResult<Walk> {
+    let mount = MOUNTS
+        .lock()
+        .iter()
+        .filter(|mount| is_under(path, &mount.path))
+        .max_by_key(|mount| mount.path.len())
+        .cloned()
+        .ok_or(LinuxError::ENOENT)?;
+    let rest = if mount.path == "/" {
+        path
+    } else {
+        &path[mount.path.len()..]
+    };
+    let names: Vec<&str> = components(rest).collect();
+    let mut node = mount.root;
+    for (i, name) in names.iter().enumerate() {
+        node = node.lookup(name)?;
+        let is_last = i + 1 == names.len();
+        if (is_last && !follow_last) || node.stat()?.node_type != NodeType::Symlink {
+            continue;
+        }
+        // A relative target is resolved from the directory of the link.
+        let mut dir = mount.path.clone();
+        for name in &names[..i] {
+            dir.push('/');
+            dir.push_str(name);
+        }
+        let target = normalize(&dir, &node.read_link()?);
+        return Ok(Walk::Link(normalize(&target, &names[i + 1..].join("/"))));
+    }
+    Ok(Walk::Node(node, mount.fs_id))
+}
+
+/// Find the node at the normalized absolute path `path` and the identifier
+/// of its filesystem, following symbolic links, and the last component too
+/// if `follow_last` is set.
+fn resolve(path: &str, follow_last: bool) -> LinuxResult<(Arc<dyn VfsNode>, u64)> {
+    let mut path = path.to_string();
+    for _ in 0..=MAX_SYMLINKS {
+        match walk(&path, follow_last)? {
+            Walk::Node(node, fs_id) => return Ok((node, fs_id)),
+            Walk::Link(target) => path = target,
+        }
+    }
+    Err(LinuxError::ELOOP)
+}
+
+/// Find the node at the normalized absolute path `path`, following symbolic
+/// links.
+pub fn lookup(path: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+    resolve(path, true).map(|(node, _)| node)
+}
+
+/// Find the node at the normalized absolute path `path` like [`lookup`],
+/// and return it with the identifier of the filesystem it is in.
+pub fn lookup_in_fs(path: &str) -> LinuxResult<(Arc<dyn VfsNode>, u64)> {
+    resolve(path, true)
+}
+
+/// Find the node at the normalized absolute path `path` like [`lookup`],
+/// but return the symbolic link itself if the last component is one.
+pub fn lookup_link(path: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+    resolve(path, false).map(|(node, _)| node)
+}
+
+/// Find the parent directory of the normalized absolute path `path`, and
+/// return it with the last component of `path`.
+///
+/// Returns `EEXIST` for `/`, which has no parent.
+pub fn lookup_parent(path: &str) -> LinuxResult<(Arc<dyn VfsNode>, &str)> {
+    let (parent, name) = split_parent(path).ok_or(LinuxError::EEXIST)?;
+    let parent_node = lookup(parent)?;
+    if parent_node.stat()?.node_type != NodeType::Dir {
+        return Err(LinuxError::ENOTDIR);
+    }
+    Ok((parent_node, name))
+}
@@ -0,0 +1,44 @@
+use alloc::{string::String, vec::Vec};
+
+/// The non-empty components of `path`.
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
+
+/// Join `path` to the absolute directory `base` unless it is absolute, and
+/// normalize the result by removing `.`, `..` and redundant slashes.
+///
+/// `..` is resolved lexically, without following symbolic links.
+pub fn normalize(base: &str, path: &str) -> String {
+    let mut names = Vec::new();
+    let base = if path.starts_with('/') { "" } else { base };
+    for name in components(base).chain(components(path)) {
+        match name {
+            "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name),
+        }
+    }
+    let mut result = String::new();
+    for name in names {
+        result.push('/');
+        result.push_str(name);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    result
+}
+
+/// Split a normalized absolute path into the path of its parent and its last
+/// component, or return `None` for `/`.
+pub fn split_parent(path: &str) -> Option<(&str, &str)> {
+    let idx = path.rfind('/')?;
+    let name = &path[idx + 1..];
+    if name.is_empty() {
+        return None;
+    }
+    Some((if idx == 0 { "/" } else { &path[..idx] }, name))
+}
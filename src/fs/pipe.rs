@@ -0,0 +1,228 @@
+use alloc::{boxed::Box, sync::Arc, vec};
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::current;
+
+use super::{
+    file::{FileLike, OpenFlags},
+    vfs::{NodeStat, NodeType},
+    wait::WaitList,
+};
+use crate::signal::{send_signal_to_thread, SigInfo, SIGPIPE, SI_USER};
+
+/// The capacity of a pipe, which is 16 pages as on Linux.
+const PIPE_CAPACITY: usize = 0x10000;
+/// The maximum size of the writes that are not interleaved with others.
+const PIPE_BUF: usize = 4096;
+
+/// A fixed-size FIFO of bytes.
+struct RingBuffer {
+    data: Box<[u8]>,
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            data: vec![0; capacity].into_boxed_slice(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn free_space(&self) -> usize {
+        self.data.len() - self.len
+    }
+
+    /// Move bytes from the buffer into `buf`, and return the number of
+    /// bytes moved.
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len);
+        let first = len.min(self.data.len() - self.head);
+        buf[..first].copy_from_slice(&self.data[self.head..self.head + first]);
+        buf[first..len].copy_from_slice(&self.data[..len - first]);
+        self.head = (self.head + len) % self.data.len();
+        self.len -= len;
+        len
+    }
+
+    /// Move bytes from `buf` into the buffer, and return the number of bytes
+    /// moved.
+    fn write(&mut self, buf: &[u8]) -> usize {
+        let len = buf.len().min(self.free_space());
+        let tail = (self.head + self.len) % self.data.len();
+        let first = len.min(self.data.len() - tail);
+        self.data[tail..tail + first].copy_from_slice(&buf[..first]);
+        self.data[..len - first].copy_from_slice(&buf[first..len]);
+        self.len += len;
+        len
+    }
+}
+
+/// The state shared by both ends of a pipe.
+struct PipeShared {
+    buffer: Mutex<RingBuffer>,
+    /// The number of open read ends.
+    readers: AtomicUsize,
+    /// The number of open write ends.
+    writers: AtomicUsize,
+    /// The threads blocked on the pipe.
+    waiters: WaitList,
+}
+
+/// One end of a pipe.
+pub struct Pipe {
+    shared: Arc<PipeShared>,
+    flags: AtomicU32,
+}
+
+impl Pipe {
+    /// Create a pipe, and return its read end and write end.
+    ///
+    /// Only `O_NONBLOCK` in `flags` is kept, for both ends.
+    pub fn new(flags: OpenFlags) -> (Self, Self) {
+        let shared = Arc::new(PipeShared {
+            buffer: Mutex::new(RingBuffer::new(PIPE_CAPACITY)),
+            readers: AtomicUsize::new(1),
+            writers: AtomicUsize::new(1),
+            waiters: WaitList::new(),
+        });
+        let flags = flags & OpenFlags::O_NONBLOCK;
+        let reader = Self {
+            shared: shared.clone(),
+            flags: AtomicU32::new(flags.bits()),
+        };
+        let writer = Self {
+            shared,
+            flags: AtomicU32::new((flags | OpenFlags::O_WRONLY).bits()),
+        };
+        (reader, writer)
+    }
+
+    fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.flags.load(Ordering::Relaxed))
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.flags().contains(OpenFlags::O_NONBLOCK)
+    }
+}
+
+impl FileLike for Pipe {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if !self.flags().readable() {
+            return Err(LinuxError::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let len = self.shared.buffer.lock().read(buf);
+            if len > 0 {
+                self.shared.waiters.wake_all();
+                return Ok(len);
+            }
+            if self.shared.writers.load(Ordering::Acquire) == 0 {
+                // End of file
+                return Ok(0);
+            }
+            if self.is_nonblocking() {
+                return Err(LinuxError::EAGAIN);
+            }
+            let shared = &self.shared;
+            shared.waiters.wait_until(|| {
+                !shared.buffer.lock().is_empty() || shared.writers.load(Ordering::Acquire) == 0
+            })?;
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if !self.flags().writable() {
+            return Err(LinuxError::EBADF);
+        }
+        let mut written = 0;
+        while written < buf.len() {
+            if self.shared.readers.load(Ordering::Acquire) == 0 {
+                let curr = current();
+                send_signal_to_thread(curr.as_task_ref(), SigInfo::new(SIGPIPE, SI_USER));
+                if written == 0 {
+                    return Err(LinuxError::EPIPE);
+                }
+                break;
+            }
+            // Small writes are done at once, so that they are not interleaved
+            // with the writes of others.
+            let rest = &buf[written..];
+            let needed = if rest.len() <= PIPE_BUF {
+                rest.len()
+            } else {
+                1
+            };
+            let mut buffer = self.shared.buffer.lock();
+            if buffer.free_space() >= needed {
+                written += buffer.write(rest);
+                drop(buffer);
+                self.shared.waiters.wake_all();
+                continue;
+            }
+            drop(buffer);
+            if self.is_nonblocking() {
+                break;
+            }
+            let shared = &self.shared;
+            let res = shared.waiters.wait_until(|| {
+                shared.buffer.lock().free_space() >= needed
+                    || shared.readers.load(Ordering::Acquire) == 0
+            });
+            if let Err(err) = res {
+                if written == 0 {
+                    return Err(err);
+                }
+                break;
+            }
+        }
+        if written == 0 && !buf.is_empty() {
+            return Err(LinuxError::EAGAIN);
+        }
+        Ok(written)
+    }
+
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        Ok(NodeStat {
+            ino: Arc::as_ptr(&self.shared) as u64,
+            node_type: NodeType::Fifo,
+            mode: 0o600,
+            nlink: 1,
+            size: 0,
+            rdev: 0,
+        })
+    }
+
+    fn status_flags(&self) -> OpenFlags {
+        self.flags()
+    }
+
+    fn set_status_flags(&self, flags: OpenFlags) {
+        let flags = self.flags().with_settable(flags);
+        self.flags.store(flags.bits(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let count = if self.flags().writable() {
+            &self.shared.writers
+        } else {
+            &self.shared.readers
+        };
+        count.fetch_sub(1, Ordering::AcqRel);
+        self.shared.waiters.wake_all();
+    }
+}
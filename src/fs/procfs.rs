@@ -0,0 +1,467 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{any::Any, fmt::Write, time::Duration};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axtask::{current, TaskExtRef};
+use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::{
+    file::FileLike,
+    mount::mounts,
+    vfs::{DirEntry, NodeStat, NodeType, VfsNode},
+};
+use crate::{
+    config,
+    task::{find_process, processes, Pid, Process},
+};
+
+/// The clock ticks per second of the times in procfs, i.e. `USER_HZ`.
+const USER_HZ: u128 = 100;
+
+/// The index of the first file descriptor link in [`ino_of`].
+const FD_INO_BASE: u64 = 0x1000;
+
+/// The inode number of the `index`-th entry of the directory of the process
+/// `pid`, or of the root directory if `pid` is 0.
+fn ino_of(pid: Pid, index: u64) -> u64 {
+    (pid << 16) | index
+}
+
+fn ticks(time: Duration) -> u128 {
+    time.as_nanos() * USER_HZ / 1_000_000_000
+}
+
+fn process(pid: Pid) -> LinuxResult<Arc<Process>> {
+    find_process(pid).ok_or(LinuxError::ESRCH)
+}
+
+/// The state of `process` as the letter and the name shown in `stat` and
+/// `status`.
+fn state_of(process: &Process) -> (char, &'static str) {
+    if process.is_zombie() {
+        ('Z', "zombie")
+    } else if current().task_ext().process.pid() == process.pid() {
+        ('R', "running")
+    } else {
+        ('S', "sleeping")
+    }
+}
+
+/// The content of a file of procfs, generated when it is read.
+type Generator = Box<dyn Fn() -> LinuxResult<String> + Send + Sync>;
+
+/// A read-only file of procfs.
+struct ProcFile {
+    ino: u64,
+    generate: Generator,
+}
+
+/// A symbolic link of procfs.
+struct ProcLink {
+    ino: u64,
+    target: String,
+}
+
+/// What a directory of procfs lists.
+#[derive(Clone, Copy)]
+enum DirKind {
+    /// The root, i.e. `/proc`.
+    Root,
+    /// `/proc/<pid>`.
+    Process(Pid),
+    /// `/proc/<pid>/fd`.
+    Fds(Pid),
+}
+
+/// A directory of procfs, whose entries are generated on every access.
+pub struct ProcDir {
+    ino: u64,
+    kind: DirKind,
+}
+
+fn file(
+    ino: u64,
+    generate: impl Fn() -> LinuxResult<String> + Send + Sync + 'static,
+) -> Arc<dyn VfsNode> {
+    Arc::new(ProcFile {
+        ino,
+        generate: Box::new(generate),
+    })
+}
+
+fn link(ino: u64, target: String) -> Arc<dyn VfsNode> {
+    Arc::new(ProcLink { ino, target })
+}
+
+fn dir(ino: u64, kind: DirKind) -> Arc<dyn VfsNode> {
+    Arc::new(ProcDir { ino, kind })
+}
+
+impl ProcDir {
+    /// The root directory of procfs.
+    pub fn root() -> Arc<Self> {
+        Arc::new(Self {
+            ino: ino_of(0, 1),
+            kind: DirKind::Root,
+        })
+    }
+
+    fn children(&self) -> LinuxResult<Vec<(String, Arc<dyn VfsNode>)>> {
+        let mut children = Vec::new();
+        match self.kind {
+            DirKind::Root => {
+                let pid = current().task_ext().process.pid();
+                children.push(("self".into(), link(ino_of(0, 2), pid.to_string())));
+                children.push(("meminfo".into(), file(ino_of(0, 3), meminfo)));
+                children.push(("cpuinfo".into(), file(ino_of(0, 4), cpuinfo)));
+                children.push(("uptime".into(), file(ino_of(0, 5), uptime)));
+                children.push(("mounts".into(), file(ino_of(0, 6), mounts_info)));
+                for process in processes() {
+                    let pid = process.pid();
+                    children.push((pid.to_string(), dir(ino_of(pid, 1), DirKind::Process(pid))));
+                }
+            }
+            DirKind::Process(pid) => {
+                let process = process(pid)?;
+                let info = process.exec_info();
+                let cwd = process.cwd.lock().clone();
+                children.push(("cmdline".into(), file(ino_of(pid, 2), move || cmdline(pid))));
+                children.push(("environ".into(), file(ino_of(pid, 3), move || environ(pid))));
+                children.push(("maps".into(), file(ino_of(pid, 4), move || maps(pid))));
+                children.push(("stat".into(), file(ino_of(pid, 5), move || stat(pid))));
+                children.push(("status".into(), file(ino_of(pid, 6), move || status(pid))));
+                children.push(("exe".into(), link(ino_of(pid, 7), info.path)));
+                children.push(("cwd".into(), link(ino_of(pid, 8), cwd)));
+                children.push(("fd".into(), dir(ino_of(pid, 9), DirKind::Fds(pid))));
+            }
+            DirKind::Fds(pid) => {
+                let process = process(pid)?;
+                for (fd, file) in process.fd_table.lock().files() {
+                    let ino = ino_of(pid, FD_INO_BASE + fd as u64);
+                    children.push((fd.to_string(), link(ino, fd_target(file.as_ref()))));
+                }
+            }
+        }
+        Ok(children)
+    }
+}
+
+impl VfsNode for ProcDir {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        Ok(NodeStat {
+            ino: self.ino,
+            node_type: NodeType::Dir,
+            mode: 0o555,
+            nlink: 2,
+            size: 0,
+            rdev: 0,
+        })
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+        self.children()?
+            .into_iter()
+            .find(|(child, _)| child == name)
+            .map(|(_, node)| node)
+            .ok_or(LinuxError::ENOENT)
+    }
+
+    fn read_dir(&self) -> LinuxResult<Vec<DirEntry>> {
+        self.children()?
+            .into_iter()
+            .map(|(name, node)| {
+                let stat = node.stat()?;
+                Ok(DirEntry {
+                    name,
+                    ino: stat.ino,
+                    node_type: stat.node_type,
+                })
+            })
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl VfsNode for ProcFile {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        // The size is unknown until the content is generated, as on Linux.
+        Ok(NodeStat {
+            ino: self.ino,
+            node_type: NodeType::File,
+            mode: 0o444,
+            nlink: 1,
+            size: 0,
+            rdev: 0,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        let content = (self.generate)()?;
+        let content = content.as_bytes();
+        let start = (offset as usize).min(content.len());
+        let len = buf.len().min(content.len() - start);
+        buf[..len].copy_from_slice(&content[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EACCES)
+    }
+
+    fn truncate(&self, _size: u64) -> LinuxResult {
+        Err(LinuxError::EACCES)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl VfsNode for ProcLink {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        Ok(NodeStat {
+            ino: self.ino,
+            node_type: NodeType::Symlink,
+            mode: 0o777,
+            nlink: 1,
+            size: self.target.len() as u64,
+            rdev: 0,
+        })
+    }
+
+    fn read_link(&self) -> LinuxResult<String> {
+        Ok(self.target.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The target of the link in `/proc/<pid>/fd` for `file`, which is its path
+/// or a description of the object for files not in the filesystem.
+fn fd_target(file: &dyn FileLike) -> String {
+    if let Some(path) = file.path() {
+        return path.into();
+    }
+    match file.stat() {
+        Ok(stat) if stat.node_type == NodeType::Fifo => format!("pipe:[{}]", stat.ino),
+        Ok(stat) => format!("anon_inode:[{}]", stat.ino),
+        Err(_) => "anon_inode:[0]".into(),
+    }
+}
+
+fn meminfo() -> LinuxResult<String> {
+    let allocator = axalloc::global_allocator();
+    let free = allocator.available_pages() * PAGE_SIZE_4K / 1024;
+    let total = (allocator.used_pages() + allocator.available_pages()) * PAGE_SIZE_4K / 1024;
+    let mut out = String::new();
+    for (name, kb) in [
+        ("MemTotal:", total),
+        ("MemFree:", free),
+        ("MemAvailable:", free),
+        ("Buffers:", 0),
+        ("Cached:", 0),
+        ("SwapTotal:", 0),
+        ("SwapFree:", 0),
+    ] {
+        writeln!(out, "{:<15} {:>8} kB", name, kb).unwrap();
+    }
+    Ok(out)
+}
+
+/// Only the boot CPU is listed, with the fields that identify the
+/// architecture.
+fn cpuinfo() -> LinuxResult<String> {
+    let mut out = String::from("processor\t: 0\n");
+    #[cfg(target_arch = "x86_64")]
+    {
+        let cpuid = x86::cpuid::CpuId::new();
+        if let Some(vendor) = cpuid.get_vendor_info() {
+            writeln!(out, "vendor_id\t: {}", vendor.as_str()).unwrap();
+        }
+        if let Some(brand) = cpuid.get_processor_brand_string() {
+            writeln!(out, "model name\t: {}", brand.as_str().trim()).unwrap();
+        }
+    }
+    #[cfg(target_arch = "riscv64")]
+    out.push_str("isa\t\t: rv64imafdc\nmmu\t\t: sv39\n");
+    #[cfg(target_arch = "aarch64")]
+    out.push_str("CPU architecture: 8\n");
+    out.push('\n');
+    Ok(out)
+}
+
+fn uptime() -> LinuxResult<String> {
+    let now = axhal::time::monotonic_time();
+    // The idle time is not tracked.
+    Ok(format!(
+        "{}.{:02} 0.00\n",
+        now.as_secs(),
+        now.subsec_millis() / 10
+    ))
+}
+
+fn mounts_info() -> LinuxResult<String> {
+    let mut out = String::new();
+    for (path, fs_type) in mounts() {
+        writeln!(out, "{} {} {} rw 0 0", fs_type, path, fs_type).unwrap();
+    }
+    Ok(out)
+}
+
+/// The strings separated and terminated by NUL bytes.
+fn nul_separated(strings: &[Vec<u8>]) -> String {
+    let mut out = String::new();
+    for s in strings {
+        out.push_str(&String::from_utf8_lossy(s));
+        out.push('\0');
+    }
+    out
+}
+
+fn cmdline(pid: Pid) -> LinuxResult<String> {
+    Ok(nul_separated(&process(pid)?.exec_info().args))
+}
+
+fn environ(pid: Pid) -> LinuxResult<String> {
+    Ok(nul_separated(&process(pid)?.exec_info().envs))
+}
+
+fn maps(pid: Pid) -> LinuxResult<String> {
+    let process = process(pid)?;
+    let aspace = process.aspace.lock();
+    let heap = aspace.heap_start()..aspace.brk().align_up_4k();
+    let mut out = String::new();
+    for area in aspace.areas() {
+        let flag = |flag, c| if area.flags.contains(flag) { c } else { '-' };
+        let (offset, name) = match area.backend.file() {
+            Some((file, offset)) => (offset, file.path().unwrap_or_default()),
+            None if area.grows_down || area.end == VirtAddr::from(config::USER_STACK_TOP) => {
+                (0, "[stack]")
+            }
+            None if heap.start <= area.start && area.end <= heap.end => (0, "[heap]"),
+            None if area.start == VirtAddr::from(config::SIGNAL_TRAMPOLINE) => (0, "[sigpage]"),
+            None => (0, ""),
+        };
+        write!(
+            out,
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+            area.start,
+            area.end,
+            flag(MappingFlags::READ, 'r'),
+            flag(MappingFlags::WRITE, 'w'),
+            flag(MappingFlags::EXECUTE, 'x'),
+            if area.backend.is_shared() { 's' } else { 'p' },
+            offset,
+        )
+        .unwrap();
+        if !name.is_empty() {
+            write!(out, " {}", name).unwrap();
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The virtual memory size of `process` in bytes.
+fn vm_size(process: &Process) -> usize {
+    process
+        .aspace
+        .lock()
+        .areas()
+        .map(|area| area.end - area.start)
+        .sum()
+}
+
+fn status(pid: Pid) -> LinuxResult<String> {
+    let process = process(pid)?;
+    let (state, state_name) = state_of(&process);
+    let rss = process.aspace.lock().resident_size();
+    let mut out = String::new();
+    writeln!(out, "Name:\t{}", process.name()).unwrap();
+    writeln!(out, "State:\t{} ({})", state, state_name).unwrap();
+    writeln!(out, "Tgid:\t{}", pid).unwrap();
+    writeln!(out, "Pid:\t{}", pid).unwrap();
+    writeln!(out, "PPid:\t{}", process.ppid()).unwrap();
+    writeln!(out, "TracerPid:\t0").unwrap();
+    writeln!(out, "Uid:\t0\t0\t0\t0").unwrap();
+    writeln!(out, "Gid:\t0\t0\t0\t0").unwrap();
+    writeln!(out, "VmSize:\t{:>8} kB", vm_size(&process) / 1024).unwrap();
+    writeln!(out, "VmRSS:\t{:>8} kB", rss / 1024).unwrap();
+    writeln!(out, "Threads:\t{}", process.thread_count()).unwrap();
+    Ok(out)
+}
+
+/// The fields of `/proc/<pid>/stat`, described in `proc(5)`. Those that are
+/// not tracked are 0.
+fn stat(pid: Pid) -> LinuxResult<String> {
+    let process = process(pid)?;
+    let (state, _) = state_of(&process);
+    let cpu_time = process.cpu_time();
+    let children_time = process.children_cpu_time();
+    let (rss, start_brk) = {
+        let aspace = process.aspace.lock();
+        (aspace.resident_size() / PAGE_SIZE_4K, aspace.heap_start())
+    };
+    let fields: Vec<String> = vec![
+        // ppid, pgrp, session, tty_nr, tpgid, flags
+        process.ppid().to_string(),
+        process.pgid().to_string(),
+        "0".into(),
+        "0".into(),
+        "-1".into(),
+        "0".into(),
+        // minflt, cminflt, majflt, cmajflt
+        "0".into(),
+        "0".into(),
+        "0".into(),
+        "0".into(),
+        // utime, stime, cutime, cstime
+        ticks(cpu_time.utime()).to_string(),
+        ticks(cpu_time.stime()).to_string(),
+        ticks(children_time.utime()).to_string(),
+        ticks(children_time.stime()).to_string(),
+        // priority, nice, num_threads, itrealvalue, starttime
+        "20".into(),
+        "0".into(),
+        process.thread_count().to_string(),
+        "0".into(),
+        ticks(process.start_time()).to_string(),
+        // vsize, rss, rsslim
+        vm_size(&process).to_string(),
+        rss.to_string(),
+        u64::MAX.to_string(),
+    ];
+    let mut out = format!("{} ({}) {}", pid, process.name(), state);
+    for field in fields {
+        write!(out, " {}", field).unwrap();
+    }
+    // startcode through cnswap
+    for _ in 0..12 {
+        out.push_str(" 0");
+    }
+    write!(out, " {}", process.exit_signal()).unwrap();
+    // processor through start_data, end_data
+    for _ in 0..8 {
+        out.push_str(" 0");
+    }
+    write!(out, " {}", start_brk.as_usize()).unwrap();
+    // arg_start, arg_end, env_start, env_end, exit_code
+    for _ in 0..5 {
+        out.push_str(" 0");
+    }
+    out.push('\n');
+    Ok(out)
+}
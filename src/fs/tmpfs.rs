@@ -0,0 +1,214 @@
+use alloc::{
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+
+use super::vfs::{DirEntry, NodeStat, NodeType, VfsNode};
+
+/// The next inode number to allocate.
+static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+enum TmpContent {
+    File(Mutex<Vec<u8>>),
+    Dir(Mutex<BTreeMap<String, Arc<TmpNode>>>),
+}
+
+/// A file or directory of a filesystem kept in memory.
+pub struct TmpNode {
+    ino: u64,
+    mode: u32,
+    content: TmpContent,
+}
+
+impl TmpNode {
+    fn new(content: TmpContent, mode: u32) -> Arc<Self> {
+        Arc::new(Self {
+            ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+            mode,
+            content,
+        })
+    }
+
+    /// Create an empty directory, e.g. the root of a new filesystem.
+    pub fn new_dir(mode: u32) -> Arc<Self> {
+        Self::new(TmpContent::Dir(Mutex::new(BTreeMap::new())), mode)
+    }
+
+    fn entries(&self) -> LinuxResult<&Mutex<BTreeMap<String, Arc<TmpNode>>>> {
+        match &self.content {
+            TmpContent::Dir(entries) => Ok(entries),
+            TmpContent::File(_) => Err(LinuxError::ENOTDIR),
+        }
+    }
+
+    fn data(&self) -> LinuxResult<&Mutex<Vec<u8>>> {
+        match &self.content {
+            TmpContent::File(data) => Ok(data),
+            TmpContent::Dir(_) => Err(LinuxError::EISDIR),
+        }
+    }
+
+    fn node_type(&self) -> NodeType {
+        match self.content {
+            TmpContent::File(_) => NodeType::File,
+            TmpContent::Dir(_) => NodeType::Dir,
+        }
+    }
+
+    fn is_empty_dir(&self) -> bool {
+        self.entries()
+            .is_ok_and(|entries| entries.lock().is_empty())
+    }
+}
+
+/// Check that `new` can replace `old` in `rename`.
+fn check_replace(old: &TmpNode, new: &TmpNode) -> LinuxResult {
+    match (old.node_type(), new.node_type()) {
+        (NodeType::Dir, NodeType::Dir) if !new.is_empty_dir() => Err(LinuxError::ENOTEMPTY),
+        (NodeType::Dir, NodeType::Dir) => Ok(()),
+        (NodeType::Dir, _) => Err(LinuxError::ENOTDIR),
+        (_, NodeType::Dir) => Err(LinuxError::EISDIR),
+        _ => Ok(()),
+    }
+}
+
+impl VfsNode for TmpNode {
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        let (size, nlink) = match &self.content {
+            TmpContent::File(data) => (data.lock().len() as u64, 1),
+            TmpContent::Dir(entries) => {
+                let subdirs = entries
+                    .lock()
+                    .values()
+                    .filter(|node| node.node_type() == NodeType::Dir)
+                    .count();
+                (0, 2 + subdirs as u32)
+            }
+        };
+        Ok(NodeStat {
+            ino: self.ino,
+            node_type: self.node_type(),
+            mode: self.mode,
+            nlink,
+            size,
+            rdev: 0,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        let data = self.data()?.lock();
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        let mut data = self.data()?.lock();
+        let end = (offset as usize)
+            .checked_add(buf.len())
+            .ok_or(LinuxError::EFBIG)?;
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: u64) -> LinuxResult {
+        self.data()?.lock().resize(size as usize, 0);
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+        match self.entries()?.lock().get(name) {
+            Some(node) => Ok(node.clone()),
+            None => Err(LinuxError::ENOENT),
+        }
+    }
+
+    fn create(&self, name: &str, node_type: NodeType, mode: u32) -> LinuxResult<Arc<dyn VfsNode>> {
+        let mut entries = self.entries()?.lock();
+        if entries.contains_key(name) {
+            return Err(LinuxError::EEXIST);
+        }
+        let node = match node_type {
+            NodeType::File => TmpNode::new(TmpContent::File(Mutex::new(Vec::new())), mode),
+            NodeType::Dir => TmpNode::new_dir(mode),
+            _ => return Err(LinuxError::EPERM),
+        };
+        entries.insert(name.to_string(), node.clone());
+        Ok(node)
+    }
+
+    fn unlink(&self, name: &str) -> LinuxResult {
+        let mut entries = self.entries()?.lock();
+        let node = entries.get(name).ok_or(LinuxError::ENOENT)?;
+        if node.node_type() == NodeType::Dir && !node.is_empty_dir() {
+            return Err(LinuxError::ENOTEMPTY);
+        }
+        entries.remove(name);
+        Ok(())
+    }
+
+    fn rename(&self, old_name: &str, new_parent: &Arc<dyn VfsNode>, new_name: &str) -> LinuxResult {
+        let new_parent = new_parent
+            .as_any()
+            .downcast_ref::<TmpNode>()
+            .ok_or(LinuxError::EXDEV)?;
+        if core::ptr::eq(self, new_parent) {
+            let mut entries = self.entries()?.lock();
+            let node = entries.get(old_name).ok_or(LinuxError::ENOENT)?.clone();
+            if let Some(target) = entries.get(new_name) {
+                check_replace(&node, target)?;
+            }
+            entries.remove(old_name);
+            entries.insert(new_name.to_string(), node);
+            return Ok(());
+        }
+
+        // Lock the directories in a fixed order to avoid deadlocks.
+        let (old_entries, new_entries) = (self.entries()?, new_parent.entries()?);
+        let (mut old_entries, mut new_entries) =
+            if (self as *const Self) < (new_parent as *const Self) {
+                let old_entries = old_entries.lock();
+                (old_entries, new_entries.lock())
+            } else {
+                let new_entries = new_entries.lock();
+                (old_entries.lock(), new_entries)
+            };
+        let node = old_entries.get(old_name).ok_or(LinuxError::ENOENT)?.clone();
+        if let Some(target) = new_entries.get(new_name) {
+            check_replace(&node, target)?;
+        }
+        old_entries.remove(old_name);
+        new_entries.insert(new_name.to_string(), node);
+        Ok(())
+    }
+
+    fn read_dir(&self) -> LinuxResult<Vec<DirEntry>> {
+        Ok(self
+            .entries()?
+            .lock()
+            .iter()
+            .map(|(name, node)| DirEntry {
+                name: name.clone(),
+                ino: node.ino,
+                node_type: node.node_type(),
+            })
+            .collect())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
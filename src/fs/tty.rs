@@ -0,0 +1,554 @@
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::{
+    mem,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use num_enum::TryFromPrimitive;
+
+use super::{
+    file::{FileLike, OpenFlags},
+    vfs::{NodeStat, NodeType},
+    wait::WaitList,
+};
+use crate::{
+    ptr::UserPtr,
+    signal::{send_signal_to_process, SigInfo, SIGINT, SIGQUIT, SIGTSTP, SIGWINCH, SI_KERNEL},
+    task::{processes, Pid},
+};
+
+/// How long the console is left alone when it has no input, since it has no
+/// interrupt and is polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The maximum length of a line in canonical mode. Characters past it are
+/// dropped, as on Linux.
+const MAX_LINE: usize = 4095;
+
+/// The number of control characters in [`Termios`].
+const NCCS: usize = 19;
+
+// Indices of the control characters.
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VMIN: usize = 6;
+const VSUSP: usize = 10;
+const VEOL: usize = 11;
+const VWERASE: usize = 14;
+const VEOL2: usize = 16;
+
+// Input flags.
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+
+// Local flags.
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const ECHOCTL: u32 = 0o1000;
+const IEXTEN: u32 = 0o100000;
+
+/// Arguments of `TCFLSH`.
+const TCIFLUSH: usize = 0;
+const TCOFLUSH: usize = 1;
+const TCIOFLUSH: usize = 2;
+
+/// The terminal settings, i.e. `struct termios` of the kernel, which is
+/// the same on all supported architectures.
+///
+/// Output processing such as `ONLCR` is done by the console driver, so
+/// `c_oflag` is kept but has no effect. `VTIME` is not supported either,
+/// and reads in noncanonical mode wait for `VMIN` bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Termios {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    c_line: u8,
+    c_cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// The settings of a new terminal, which are `tty_std_termios` of Linux.
+    const fn new() -> Self {
+        Self {
+            // ICRNL | IXON
+            c_iflag: 0o2400,
+            // OPOST | ONLCR
+            c_oflag: 0o5,
+            // B38400 | CS8 | CREAD | HUPCL
+            c_cflag: 0o2277,
+            // ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN
+            c_lflag: 0o105073,
+            c_line: 0,
+            c_cc: [
+                3, 28, 127, 21, 4, 0, 1, 0, 17, 19, 26, 0, 18, 15, 23, 22, 0, 0, 0,
+            ],
+        }
+    }
+
+    fn has_iflag(&self, flag: u32) -> bool {
+        self.c_iflag & flag != 0
+    }
+
+    fn has_lflag(&self, flag: u32) -> bool {
+        self.c_lflag & flag != 0
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.has_lflag(ICANON)
+    }
+
+    /// Whether `byte` is the control character at `index`, which is
+    /// disabled if it is 0.
+    fn is_cc(&self, byte: u8, index: usize) -> bool {
+        self.c_cc[index] != 0 && self.c_cc[index] == byte
+    }
+}
+
+/// The size of the terminal window, i.e. `struct winsize`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+/// Requests of `ioctl` on terminals.
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/ioctls.h>
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u32)]
+enum TtyIoctl {
+    /// Get the settings.
+    Tcgets = 0x5401,
+    /// Change the settings.
+    Tcsets = 0x5402,
+    /// Change the settings after the output is written.
+    Tcsetsw = 0x5403,
+    /// Change the settings after the output is written, and discard the
+    /// input.
+    Tcsetsf = 0x5404,
+    /// Discard the input or the output.
+    Tcflsh = 0x540b,
+    /// Make the terminal the controlling terminal.
+    Tiocsctty = 0x540e,
+    /// Get the foreground process group.
+    Tiocgpgrp = 0x540f,
+    /// Set the foreground process group.
+    Tiocspgrp = 0x5410,
+    /// Get the window size.
+    Tiocgwinsz = 0x5413,
+    /// Set the window size.
+    Tiocswinsz = 0x5414,
+    /// Get the number of bytes that can be read.
+    Fionread = 0x541b,
+    /// Give up the controlling terminal.
+    Tiocnotty = 0x5422,
+}
+
+/// Whether `byte` is echoed as `^X` with `ECHOCTL`.
+fn is_control(byte: u8) -> bool {
+    (byte < 0x20 && byte != b'\t' && byte != b'\n') || byte == 0x7f
+}
+
+/// Serializes the writes to the console, so that lines from different
+/// threads are not interleaved.
+static CONSOLE_LOCK: Mutex<()> = Mutex::new(());
+
+fn write_console(buf: &[u8]) {
+    let _guard = CONSOLE_LOCK.lock();
+    axhal::console::write_bytes(buf);
+}
+
+/// Send the signal `signo` to the processes in the group `pgid`.
+fn signal_group(pgid: Pid, signo: u32) {
+    if pgid == 0 {
+        return;
+    }
+    for process in processes().iter().filter(|p| p.pgid() == pgid) {
+        send_signal_to_process(process, SigInfo::new(signo, SI_KERNEL));
+    }
+}
+
+struct TtyState {
+    termios: Termios,
+    winsize: WinSize,
+    /// The foreground process group, which receives the signals generated
+    /// by the input, or 0 if there is none.
+    foreground: Pid,
+    /// The input that can be read. In canonical mode, each chunk is a line,
+    /// and an empty one is the end of file.
+    ready: VecDeque<Vec<u8>>,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+}
+
+impl TtyState {
+    fn echo(&self, byte: u8) {
+        if !self.termios.has_lflag(ECHO) {
+            return;
+        }
+        if self.termios.has_lflag(ECHOCTL) && is_control(byte) {
+            write_console(&[b'^', byte ^ 0x40]);
+        } else {
+            write_console(&[byte]);
+        }
+    }
+
+    /// Remove the last character of the line, and return whether there was
+    /// one.
+    fn erase(&mut self) -> bool {
+        let Some(byte) = self.line.pop() else {
+            return false;
+        };
+        if self.termios.has_lflag(ECHO) && self.termios.has_lflag(ECHOE) {
+            let width = if self.termios.has_lflag(ECHOCTL) && is_control(byte) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..width {
+                write_console(b"\x08 \x08");
+            }
+        }
+        true
+    }
+
+    fn end_line(&mut self) {
+        let line = mem::take(&mut self.line);
+        self.ready.push_back(line);
+    }
+
+    fn flush_input(&mut self) {
+        self.ready.clear();
+        self.line.clear();
+    }
+
+    /// The number of bytes that can be read.
+    fn available(&self) -> usize {
+        self.ready.iter().map(Vec::len).sum()
+    }
+
+    /// Process a byte of input, and return the signal that it generates.
+    fn receive(&mut self, mut byte: u8) -> Option<u32> {
+        let termios = self.termios;
+        if byte == b'\r' {
+            if termios.has_iflag(IGNCR) {
+                return None;
+            }
+            if termios.has_iflag(ICRNL) {
+                byte = b'\n';
+            }
+        } else if byte == b'\n' && termios.has_iflag(INLCR) {
+            byte = b'\r';
+        }
+
+        if termios.has_lflag(ISIG) {
+            let signo = if termios.is_cc(byte, VINTR) {
+                Some(SIGINT)
+            } else if termios.is_cc(byte, VQUIT) {
+                Some(SIGQUIT)
+            } else if termios.is_cc(byte, VSUSP) {
+                Some(SIGTSTP)
+            } else {
+                None
+            };
+            if signo.is_some() {
+                if !termios.has_lflag(NOFLSH) {
+                    self.flush_input();
+                }
+                self.echo(byte);
+                return signo;
+            }
+        }
+
+        if !termios.is_canonical() {
+            match self.ready.back_mut() {
+                Some(chunk) => chunk.push(byte),
+                None => self.ready.push_back(vec![byte]),
+            }
+            self.echo(byte);
+            return None;
+        }
+
+        if termios.is_cc(byte, VERASE) {
+            self.erase();
+        } else if termios.is_cc(byte, VKILL) {
+            while self.erase() {}
+        } else if termios.has_lflag(IEXTEN) && termios.is_cc(byte, VWERASE) {
+            while self.line.last() == Some(&b' ') && self.erase() {}
+            while self.line.last().is_some_and(|&c| c != b' ') && self.erase() {}
+        } else if termios.is_cc(byte, VEOF) {
+            self.end_line();
+        } else if byte == b'\n' || termios.is_cc(byte, VEOL) || termios.is_cc(byte, VEOL2) {
+            self.line.push(byte);
+            if byte == b'\n' && termios.has_lflag(ECHONL) && !termios.has_lflag(ECHO) {
+                write_console(b"\n");
+            } else {
+                self.echo(byte);
+            }
+            self.end_line();
+        } else if self.line.len() < MAX_LINE {
+            self.line.push(byte);
+            self.echo(byte);
+        }
+        None
+    }
+
+    /// Whether a read into a buffer of `len` bytes would not block.
+    fn can_read(&self, len: usize) -> bool {
+        if self.termios.is_canonical() {
+            !self.ready.is_empty()
+        } else {
+            self.available() >= len.min(self.termios.c_cc[VMIN] as usize)
+        }
+    }
+
+    /// Move the input into `buf`, and return the number of bytes moved, or
+    /// `None` if it has to wait for more input.
+    fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if !self.can_read(buf.len()) {
+            return None;
+        }
+        // A read in canonical mode returns at most one line.
+        let max_chunks = if self.termios.is_canonical() {
+            1
+        } else {
+            usize::MAX
+        };
+        let mut len = 0;
+        for _ in 0..max_chunks {
+            let Some(chunk) = self.ready.front_mut() else {
+                break;
+            };
+            let n = chunk.len().min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&chunk[..n]);
+            chunk.drain(..n);
+            len += n;
+            if !chunk.is_empty() {
+                break;
+            }
+            self.ready.pop_front();
+        }
+        Some(len)
+    }
+
+    fn set_termios(&mut self, termios: Termios) {
+        // The line being edited becomes readable when canonical mode is
+        // turned off.
+        if !termios.is_canonical() && !self.line.is_empty() {
+            self.end_line();
+        }
+        self.termios = termios;
+    }
+}
+
+/// The line discipline of the console.
+struct Terminal {
+    state: Mutex<TtyState>,
+    /// The threads waiting for input.
+    waiters: WaitList,
+}
+
+static TERMINAL: Terminal = Terminal {
+    state: Mutex::new(TtyState {
+        termios: Termios::new(),
+        winsize: WinSize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        },
+        foreground: 0,
+        ready: VecDeque::new(),
+        line: Vec::new(),
+    }),
+    waiters: WaitList::new(),
+};
+
+impl Terminal {
+    fn receive(&self, input: &[u8]) {
+        let mut signals = Vec::new();
+        let mut state = self.state.lock();
+        for &byte in input {
+            if let Some(signo) = state.receive(byte) {
+                signals.push(signo);
+            }
+        }
+        let foreground = state.foreground;
+        drop(state);
+        for signo in signals {
+            signal_group(foreground, signo);
+        }
+        self.waiters.wake_all();
+    }
+}
+
+/// Start polling the console for input.
+pub fn init() {
+    axtask::spawn(|| loop {
+        let mut buf = [0; 64];
+        let len = axhal::console::read_bytes(&mut buf);
+        if len > 0 {
+            TERMINAL.receive(&buf[..len]);
+        } else {
+            axtask::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// Make `pgid` the foreground process group of the console, which receives
+/// the signals generated by the input, e.g. SIGINT for `^C`.
+pub fn set_foreground_pgrp(pgid: Pid) {
+    TERMINAL.state.lock().foreground = pgid;
+}
+
+/// The console opened as a terminal.
+pub struct Tty {
+    flags: AtomicU32,
+}
+
+impl Tty {
+    /// Open the console with `flags`.
+    pub fn new(flags: OpenFlags) -> Self {
+        Self {
+            flags: AtomicU32::new(flags.bits()),
+        }
+    }
+
+    fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.flags.load(Ordering::Relaxed))
+    }
+}
+
+impl FileLike for Tty {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if !self.flags().readable() {
+            return Err(LinuxError::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(len) = TERMINAL.state.lock().read(buf) {
+                return Ok(len);
+            }
+            if self.flags().contains(OpenFlags::O_NONBLOCK) {
+                return Err(LinuxError::EAGAIN);
+            }
+            let len = buf.len();
+            TERMINAL
+                .waiters
+                .wait_until(|| TERMINAL.state.lock().can_read(len))?;
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if !self.flags().writable() {
+            return Err(LinuxError::EBADF);
+        }
+        write_console(buf);
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<NodeStat> {
+        Ok(NodeStat {
+            ino: 0,
+            node_type: NodeType::CharDevice,
+            mode: 0o620,
+            nlink: 1,
+            size: 0,
+            // The device 5:1, i.e. /dev/console.
+            rdev: 0x501,
+        })
+    }
+
+    fn status_flags(&self) -> OpenFlags {
+        self.flags()
+    }
+
+    fn set_status_flags(&self, flags: OpenFlags) {
+        let flags = self.flags().with_settable(flags);
+        self.flags.store(flags.bits(), Ordering::Relaxed);
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some("/dev/console")
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        let cmd = TtyIoctl::try_from(cmd).map_err(|_| LinuxError::ENOTTY)?;
+        debug!("tty ioctl <= cmd: {:?}, arg: {:#x}", cmd, arg);
+        match cmd {
+            TtyIoctl::Tcgets => {
+                let termios = TERMINAL.state.lock().termios;
+                UserPtr::<Termios>::from(arg).write(termios)?;
+            }
+            TtyIoctl::Tcsets | TtyIoctl::Tcsetsw | TtyIoctl::Tcsetsf => {
+                let termios = UserPtr::<Termios>::from(arg).read()?;
+                let mut state = TERMINAL.state.lock();
+                if cmd == TtyIoctl::Tcsetsf {
+                    state.flush_input();
+                }
+                state.set_termios(termios);
+                drop(state);
+                TERMINAL.waiters.wake_all();
+            }
+            TtyIoctl::Tcflsh => match arg {
+                TCIFLUSH | TCIOFLUSH => TERMINAL.state.lock().flush_input(),
+                // The output is written at once.
+                TCOFLUSH => {}
+                _ => return Err(LinuxError::EINVAL),
+            },
+            TtyIoctl::Tiocgpgrp => {
+                let pgid = TERMINAL.state.lock().foreground;
+                UserPtr::<i32>::from(arg).write(pgid as i32)?;
+            }
+            TtyIoctl::Tiocspgrp => {
+                let pgid = UserPtr::<i32>::from(arg).read()?;
+                let pgid = Pid::try_from(pgid).map_err(|_| LinuxError::EINVAL)?;
+                if !processes().iter().any(|p| p.pgid() == pgid) {
+                    return Err(LinuxError::ESRCH);
+                }
+                set_foreground_pgrp(pgid);
+            }
+            TtyIoctl::Tiocgwinsz => {
+                let winsize = TERMINAL.state.lock().winsize;
+                UserPtr::<WinSize>::from(arg).write(winsize)?;
+            }
+            TtyIoctl::Tiocswinsz => {
+                let winsize = UserPtr::<WinSize>::from(arg).read()?;
+                let mut state = TERMINAL.state.lock();
+                if state.winsize != winsize {
+                    state.winsize = winsize;
+                    let foreground = state.foreground;
+                    drop(state);
+                    signal_group(foreground, SIGWINCH);
+                }
+            }
+            TtyIoctl::Fionread => {
+                let available = TERMINAL.state.lock().available();
+                UserPtr::<i32>::from(arg).write(available as i32)?;
+            }
+            // There are no sessions, so the console is the controlling
+            // terminal of every process.
+            TtyIoctl::Tiocsctty | TtyIoctl::Tiocnotty => {}
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+
+use axerrno::{LinuxError, LinuxResult};
+
+use super::file::{FileLike, OpenFlags};
+
+/// The type of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeType {
+    /// A block device.
+    BlockDevice,
+    /// A character device, such as the console.
+    CharDevice,
+    /// A directory.
+    Dir,
+    /// A pipe.
+    Fifo,
+    /// A regular file.
+    File,
+    /// A symbolic link.
+    Symlink,
+}
+
+impl NodeType {
+    /// The file type bits of `st_mode`.
+    pub const fn mode_bits(self) -> u32 {
+        match self {
+            Self::BlockDevice => 0o060000,
+            Self::CharDevice => 0o020000,
+            Self::Dir => 0o040000,
+            Self::Fifo => 0o010000,
+            Self::File => 0o100000,
+            Self::Symlink => 0o120000,
+        }
+    }
+
+    /// The `d_type` of the directory entries returned by `getdents64`.
+    pub const fn dirent_type(self) -> u8 {
+        match self {
+            Self::BlockDevice => 6,
+            Self::CharDevice => 2,
+            Self::Dir => 4,
+            Self::Fifo => 1,
+            Self::File => 8,
+            Self::Symlink => 10,
+        }
+    }
+}
+
+/// The attributes of a node.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeStat {
+    /// The inode number.
+    pub ino: u64,
+    /// The type of the node.
+    pub node_type: NodeType,
+    /// The permission bits.
+    pub mode: u32,
+    /// The number of hard links.
+    pub nlink: u32,
+    /// The size in bytes.
+    pub size: u64,
+    /// The device number, for device nodes.
+    pub rdev: u64,
+}
+
+/// An entry of a directory.
+pub struct DirEntry {
+    /// The name of the entry.
+    pub name: String,
+    /// The inode number of the entry.
+    pub ino: u64,
+    /// The type of the entry.
+    pub node_type: NodeType,
+}
+
+/// A file, directory or device in a filesystem.
+///
+/// Directory operations take single path components. The default
+/// implementations fail as the operations of a regular file would.
+pub trait VfsNode: Send + Sync {
+    /// The attributes of the node.
+    fn stat(&self) -> LinuxResult<NodeStat>;
+
+    /// Read from the node at `offset` into `buf`, and return the number of
+    /// bytes read.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    /// Write `buf` to the node at `offset`, and return the number of bytes
+    /// written.
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    /// Change the size of the file to `size`.
+    fn truncate(&self, _size: u64) -> LinuxResult {
+        Err(LinuxError::EINVAL)
+    }
+
+    /// Find the entry `name` in the directory.
+    fn lookup(&self, _name: &str) -> LinuxResult<Arc<dyn VfsNode>> {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// Create the entry `name` in the directory, with the given type and
+    /// permission bits.
+    fn create(
+        &self,
+        _name: &str,
+        _node_type: NodeType,
+        _mode: u32,
+    ) -> LinuxResult<Arc<dyn VfsNode>> {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// Remove the entry `name` from the directory. Directories must be
+    /// empty to be removed.
+    fn unlink(&self, _name: &str) -> LinuxResult {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// Move the entry `old_name` of the directory to `new_name` in
+    /// `new_parent`, replacing the existing entry there if any.
+    fn rename(
+        &self,
+        _old_name: &str,
+        _new_parent: &Arc<dyn VfsNode>,
+        _new_name: &str,
+    ) -> LinuxResult {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// The target of the symbolic link.
+    fn read_link(&self) -> LinuxResult<String> {
+        Err(LinuxError::EINVAL)
+    }
+
+    /// The entries of the directory, excluding `.` and `..`.
+    fn read_dir(&self) -> LinuxResult<Vec<DirEntry>> {
+        Err(LinuxError::ENOTDIR)
+    }
+
+    /// Handle the request `cmd` of `ioctl` with the argument `arg`, for
+    /// device nodes.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<isize> {
+        Err(LinuxError::ENOTTY)
+    }
+
+    /// Open the node as a file of its own type with `flags`, e.g. a terminal,
+    /// or return `None` to open it as a [`File`](super::File).
+    fn open(&self, _flags: OpenFlags) -> Option<Arc<dyn FileLike>> {
+        None
+    }
+
+    /// The node as [`Any`], so that filesystems can recognize their own
+    /// nodes, e.g. in `rename`.
+    fn as_any(&self) -> &dyn Any;
+}
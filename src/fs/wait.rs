@@ -0,0 +1,48 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{current, AxTaskRef, TaskExtRef};
+
+/// The threads blocked on an object of the filesystem, e.g. a pipe.
+///
+/// Each thread waits on its own signal wait queue, so that it also wakes up
+/// when a signal arrives.
+pub struct WaitList {
+    waiters: Mutex<Vec<AxTaskRef>>,
+}
+
+impl WaitList {
+    /// Create an empty list.
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Wake up all waiting threads to check their conditions again.
+    pub fn wake_all(&self) {
+        for task in self.waiters.lock().iter() {
+            task.task_ext().signal_wq.notify_all(false);
+        }
+    }
+
+    /// Block the current thread until `condition` holds.
+    ///
+    /// Returns `EINTR` if the thread is killed or receives a signal first.
+    pub fn wait_until(&self, condition: impl Fn() -> bool) -> LinuxResult {
+        let curr = current();
+        let ext = curr.task_ext();
+        self.waiters.lock().push(curr.as_task_ref().clone());
+        ext.signal_wq
+            .wait_until(|| condition() || ext.is_interrupted());
+        self.waiters
+            .lock()
+            .retain(|task| !Arc::ptr_eq(task, curr.as_task_ref()));
+        if condition() {
+            Ok(())
+        } else {
+            Err(LinuxError::EINTR)
+        }
+    }
+}
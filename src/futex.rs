@@ -0,0 +1,261 @@
+//! Fast user-space mutexes.
+//!
+//! The threads waiting on futexes are kept in a global table indexed by
+//! [`FutexKey`]. Each waiter has its own wait queue, so that it can be woken
+//! up or moved to another futex individually.
+
+use alloc::{
+    collections::{btree_map::BTreeMap, VecDeque},
+    sync::Arc,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::phys_to_virt;
+use axsync::Mutex;
+use axtask::{AxTaskRef, TaskExtRef, WaitQueue};
+use memory_addr::PhysAddr;
+
+use crate::{mm::UserSpace, task::Process};
+
+/// The bitset that matches all waiters.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Identifies a futex word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FutexKey {
+    /// A futex used by a single address space, identified by the address
+    /// space and the virtual address of the word.
+    Private { aspace: usize, vaddr: usize },
+    /// A futex that may be shared between processes, identified by the
+    /// physical address of the word.
+    Shared { paddr: usize },
+}
+
+/// A thread waiting on a futex.
+struct FutexWaiter {
+    task: AxTaskRef,
+    bitset: u32,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl FutexWaiter {
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+}
+
+/// The waiters of all futexes, in FIFO order for each futex.
+static FUTEX_TABLE: Mutex<BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Find the key of the futex word at `uaddr` in `aspace`, the locked address
+/// space of `process`, and the physical address of the word.
+fn resolve(
+    process: &Process,
+    aspace: &mut UserSpace,
+    uaddr: usize,
+    private: bool,
+) -> LinuxResult<(FutexKey, PhysAddr)> {
+    if uaddr % 4 != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let paddr = aspace
+        .resolve(uaddr.into())
+        .map_err(|_| LinuxError::EFAULT)?;
+    let key = if private {
+        FutexKey::Private {
+            aspace: Arc::as_ptr(&process.aspace) as usize,
+            vaddr: uaddr,
+        }
+    } else {
+        FutexKey::Shared {
+            paddr: paddr.as_usize(),
+        }
+    };
+    Ok((key, paddr))
+}
+
+/// Read the futex word at `paddr`.
+///
+/// The word is accessed through the linear mapping, so that it can be read
+/// while holding the futex table lock without faulting.
+///
+/// # Safety
+///
+/// The address space that the word was resolved in must still be locked, so
+/// that its page is not unmapped and freed in the meantime.
+unsafe fn load_word(paddr: PhysAddr) -> u32 {
+    let word = unsafe { &*(phys_to_virt(paddr).as_ptr() as *const AtomicU32) };
+    word.load(Ordering::SeqCst)
+}
+
+/// Wake up at most `count` waiters of `key` whose bitsets intersect with
+/// `bitset`, and return the number of woken waiters.
+fn wake_key(
+    table: &mut BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>,
+    key: FutexKey,
+    bitset: u32,
+    count: usize,
+) -> usize {
+    let Some(queue) = table.get_mut(&key) else {
+        return 0;
+    };
+    let mut woken = 0;
+    queue.retain(|waiter| {
+        if woken < count && waiter.bitset & bitset != 0 {
+            waiter.wake();
+            woken += 1;
+            false
+        } else {
+            true
+        }
+    });
+    if queue.is_empty() {
+        table.remove(&key);
+    }
+    woken
+}
+
+/// Block the current thread on the futex at `uaddr` if the word still
+/// contains `val`.
+///
+/// Returns `EAGAIN` if the word does not contain `val`, `ETIMEDOUT` if it
+/// is not woken up within `timeout`, or `EINTR` if the thread is killed or
+/// receives a signal while waiting.
+pub fn futex_wait(
+    process: &Process,
+    uaddr: usize,
+    val: u32,
+    private: bool,
+    bitset: u32,
+    timeout: Option<Duration>,
+) -> LinuxResult {
+    let mut aspace = process.aspace.lock();
+    let (key, paddr) = resolve(process, &mut aspace, uaddr, private)?;
+    let curr = axtask::current();
+    let waiter = Arc::new(FutexWaiter {
+        task: curr.clone(),
+        bitset,
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    {
+        let mut table = FUTEX_TABLE.lock();
+        // SAFETY: The address space is locked until the waiter is queued.
+        if unsafe { load_word(paddr) } != val {
+            return Err(LinuxError::EAGAIN);
+        }
+        table.entry(key).or_default().push_back(waiter.clone());
+    }
+    drop(aspace);
+
+    let condition = || waiter.woken.load(Ordering::Acquire) || curr.task_ext().is_interrupted();
+    match timeout {
+        Some(timeout) => {
+            waiter.wq.wait_timeout_until(timeout, condition);
+        }
+        None => waiter.wq.wait_until(condition),
+    }
+
+    let mut table = FUTEX_TABLE.lock();
+    if waiter.woken.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    // The waiter may have been requeued, so it is searched in all futexes.
+    table.retain(|_, queue| {
+        queue.retain(|w| !Arc::ptr_eq(w, &waiter));
+        !queue.is_empty()
+    });
+    if curr.task_ext().is_interrupted() {
+        Err(LinuxError::EINTR)
+    } else {
+        Err(LinuxError::ETIMEDOUT)
+    }
+}
+
+/// Wake up at most `count` waiters of the futex at `uaddr` whose bitsets
+/// intersect with `bitset`.
+///
+/// Returns the number of woken waiters.
+pub fn futex_wake(
+    process: &Process,
+    uaddr: usize,
+    private: bool,
+    bitset: u32,
+    count: usize,
+) -> LinuxResult<usize> {
+    let (key, _) = resolve(process, &mut process.aspace.lock(), uaddr, private)?;
+    Ok(wake_key(&mut FUTEX_TABLE.lock(), key, bitset, count))
+}
+
+/// Wake up at most `nr_wake` waiters of the futex at `uaddr`, and move at
+/// most `nr_requeue` of the remaining ones to the futex at `uaddr2`.
+///
+/// If `cmp` is given, nothing is done unless the word at `uaddr` contains
+/// it, and `EAGAIN` is returned instead.
+///
+/// Returns the numbers of woken and requeued waiters.
+pub fn futex_requeue(
+    process: &Process,
+    uaddr: usize,
+    uaddr2: usize,
+    private: bool,
+    nr_wake: usize,
+    nr_requeue: usize,
+    cmp: Option<u32>,
+) -> LinuxResult<(usize, usize)> {
+    let mut aspace = process.aspace.lock();
+    let (key, paddr) = resolve(process, &mut aspace, uaddr, private)?;
+    let (key2, _) = resolve(process, &mut aspace, uaddr2, private)?;
+    let mut table = FUTEX_TABLE.lock();
+    // SAFETY: The address space stays locked until the function returns.
+    if cmp.is_some_and(|val| unsafe { load_word(paddr) } != val) {
+        return Err(LinuxError::EAGAIN);
+    }
+    let woken = wake_key(&mut table, key, FUTEX_BITSET_MATCH_ANY, nr_wake);
+    if key == key2 {
+        return Ok((woken, 0));
+    }
+    let Some(mut queue) = table.remove(&key) else {
+        return Ok((woken, 0));
+    };
+    let moved = queue
+        .drain(..nr_requeue.min(queue.len()))
+        .collect::<VecDeque<_>>();
+    if !queue.is_empty() {
+        table.insert(key, queue);
+    }
+    let requeued = moved.len();
+    if requeued > 0 {
+        table.entry(key2).or_default().extend(moved);
+    }
+    Ok((woken, requeued))
+}
+
+/// Wake up the threads waiting on the `clear_child_tid` word of an exiting
+/// thread, e.g. in `pthread_join`.
+///
+/// Waiters of both private and shared futexes are woken, since both kinds
+/// are used by C libraries for this word.
+pub fn wake_clear_child_tid(process: &Process, uaddr: usize) {
+    let _ = futex_wake(process, uaddr, false, FUTEX_BITSET_MATCH_ANY, 1);
+    let _ = futex_wake(process, uaddr, true, FUTEX_BITSET_MATCH_ANY, 1);
+}
+
+/// Wake up the futex waiters that have been killed or have signals to
+/// handle.
+///
+/// They stay in the table until they remove themselves.
+pub fn interrupt_waiters() {
+    for waiter in FUTEX_TABLE.lock().values().flatten() {
+        if waiter.task.task_ext().is_interrupted() {
+            waiter.wq.notify_one(false);
+        }
+    }
+}
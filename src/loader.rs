@@ -1,72 +1,170 @@
 //! Loader for loading apps.
 //!
-//! It will read and parse ELF files.
+//! It will read and parse ELF files, and follow the `#!` lines of scripts
+//! to their interpreters.
 //!
-//! Now these apps are loaded into memory as a part of the kernel image.
-use alloc::{collections::btree_map::BTreeMap, vec::Vec};
-use core::arch::global_asm;
+//! Apps are read from the root filesystem. The initramfs embedded in the
+//! kernel image is used as a fallback for the files missing there.
+use alloc::{
+    borrow::Cow,
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
+use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
 use memory_addr::{MemoryAddr, VirtAddr};
 
-global_asm!(include_str!(concat!(env!("OUT_DIR"), "/link_app.S")));
+use crate::fs::{self, NodeType};
 
-extern "C" {
-    fn _app_count();
-}
+/// The auxv entry that ends the auxv.
+pub(crate) const AT_NULL: u8 = 0;
+/// The auxv entry of the address of the program headers.
+pub(crate) const AT_PHDR: u8 = 3;
+/// The auxv entry of the base address of the interpreter.
+pub(crate) const AT_BASE: u8 = 7;
+/// The auxv entry of the entry point of the program.
+pub(crate) const AT_ENTRY: u8 = 9;
+/// The auxv entry of the real user ID.
+pub(crate) const AT_UID: u8 = 11;
+/// The auxv entry of the effective user ID.
+pub(crate) const AT_EUID: u8 = 12;
+/// The auxv entry of the real group ID.
+pub(crate) const AT_GID: u8 = 13;
+/// The auxv entry of the effective group ID.
+pub(crate) const AT_EGID: u8 = 14;
+/// The auxv entry of the CPU features.
+pub(crate) const AT_HWCAP: u8 = 16;
+/// The auxv entry of the frequency of `times`.
+pub(crate) const AT_CLKTCK: u8 = 17;
+/// The auxv entry of whether the program runs with elevated privileges.
+pub(crate) const AT_SECURE: u8 = 23;
+/// The auxv entry of the address of 16 random bytes.
+pub(crate) const AT_RANDOM: u8 = 25;
+/// The auxv entry of the address of the path of the program.
+pub(crate) const AT_EXECFN: u8 = 31;
+/// The auxv entry of the minimum size of a signal stack.
+pub(crate) const AT_MINSIGSTKSZ: u8 = 51;
+
+/// The maximum number of `#!` lines followed to reach an ELF file, as on
+/// Linux.
+const MAX_SCRIPT_DEPTH: usize = 4;
+/// The number of bytes at the start of a script that hold its `#!` line, as
+/// on Linux.
+const SCRIPT_LINE_MAX: usize = 256;
 
-/// Get the number of apps.
-pub(crate) fn get_app_count() -> usize {
-    unsafe { (_app_count as *const u64).read() as usize }
+/// Get the data of an app in the initramfs by the given app name, which is
+/// its path in the archive.
+pub(crate) fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
+    fs::initramfs::entries()
+        .find(|entry| entry.name == name && entry.node_type() == Some(NodeType::File))
+        .map(|entry| entry.data)
 }
 
-/// Get the name of an app by a given app ID.
-pub(crate) fn get_app_name(app_id: usize) -> &'static str {
-    unsafe {
-        let app_0_start_ptr = (_app_count as *const u64).add(1);
-        assert!(app_id < get_app_count());
-        let app_name = app_0_start_ptr.add(app_id * 2).read() as *const u8;
-        let mut len = 0;
-        while app_name.add(len).read() != b'\0' {
-            len += 1;
+/// Read the ELF file of the app at the normalized absolute `path`.
+///
+/// If there is no such file in the root filesystem, which is not the
+/// initramfs with the `fs` feature, the file in the initramfs is used
+/// instead.
+pub(crate) fn read_app(path: &str) -> LinuxResult<Cow<'static, [u8]>> {
+    let node = match fs::lookup(path) {
+        Ok(node) => node,
+        Err(LinuxError::ENOENT) => {
+            return get_app_data_by_name(path.trim_start_matches('/'))
+                .map(Cow::Borrowed)
+                .ok_or(LinuxError::ENOENT);
+        }
+        Err(err) => return Err(err),
+    };
+    let stat = node.stat()?;
+    if stat.node_type != NodeType::File {
+        return Err(LinuxError::EACCES);
+    }
+    let mut data = vec![0; stat.size as usize];
+    let mut len = 0;
+    while len < data.len() {
+        let n = node.read_at(len as u64, &mut data[len..])?;
+        if n == 0 {
+            break;
         }
-        let slice = core::slice::from_raw_parts(app_name, len);
-        core::str::from_utf8(slice).unwrap()
+        len += n;
     }
+    data.truncate(len);
+    Ok(Cow::Owned(data))
 }
 
-/// Get the data of an app by a given app ID.
-pub(crate) fn get_app_data(app_id: usize) -> &'static [u8] {
-    unsafe {
-        let app_0_start_ptr = (_app_count as *const u64).add(1);
-        assert!(app_id < get_app_count());
-        let app_start = app_0_start_ptr.add(app_id * 2 + 1).read() as usize;
-        let app_end = app_0_start_ptr.add(app_id * 2 + 2).read() as usize;
-        let app_size = app_end - app_start;
-        core::slice::from_raw_parts(app_start as *const u8, app_size)
+/// Parse the `#!` line of a script into the interpreter and its optional
+/// argument, or return `None` if `data` is not a script.
+fn parse_script_line(data: &[u8]) -> Option<LinuxResult<(&str, Option<&str>)>> {
+    let rest = data.strip_prefix(b"#!")?;
+    let buf = &rest[..rest.len().min(SCRIPT_LINE_MAX - 2)];
+    let (line, complete) = match buf.iter().position(|&b| b == b'\n') {
+        Some(end) => (&buf[..end], true),
+        None => (buf, buf.len() == rest.len()),
+    };
+    let Ok(line) = core::str::from_utf8(line) else {
+        return Some(Err(LinuxError::ENOEXEC));
+    };
+    let line = line.trim_matches([' ', '\t']);
+    let (interp, arg) = match line.split_once([' ', '\t']) {
+        Some((interp, arg)) => (interp, Some(arg.trim_start_matches([' ', '\t']))),
+        None => (line, None),
+    };
+    // A truncated line must still hold the whole interpreter.
+    if interp.is_empty() || (!complete && arg.is_none()) {
+        return Some(Err(LinuxError::ENOEXEC));
     }
+    Some(Ok((interp, arg)))
 }
 
-/// Get the data of an app by the given app name.
-pub(crate) fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
-    let app_count = get_app_count();
-    (0..app_count)
-        .find(|&i| get_app_name(i) == name)
-        .map(get_app_data)
+/// Read the program to execute for `path`, which is resolved from the
+/// current directory, following the `#!` lines of scripts like Linux.
+///
+/// The interpreter of a script is run with its optional argument and the
+/// path of the script in place of `args[0]`, followed by the rest of
+/// `args`.
+///
+/// # Returns
+/// The normalized absolute path and the data of the ELF file, and the
+/// arguments to pass to it.
+pub(crate) fn read_exec(
+    path: &str,
+    mut args: Vec<Vec<u8>>,
+) -> LinuxResult<(String, Cow<'static, [u8]>, Vec<Vec<u8>>)> {
+    let mut path = path.to_string();
+    for _ in 0..=MAX_SCRIPT_DEPTH {
+        let abs_path = fs::absolute_path(fs::AT_FDCWD, &path)?;
+        let data = read_app(&abs_path)?;
+        let Some(script) = parse_script_line(&data) else {
+            return Ok((abs_path, data, args));
+        };
+        let (interp, arg) = script?;
+        debug!("Running script {} with {}", path, interp);
+        let mut new_args = vec![interp.as_bytes().to_vec()];
+        new_args.extend(arg.map(|arg| arg.as_bytes().to_vec()));
+        new_args.push(path.into_bytes());
+        new_args.extend(args.into_iter().skip(1));
+        args = new_args;
+        path = interp.to_string();
+    }
+    Err(LinuxError::ELOOP)
 }
 
-/// List all apps.
+/// List all apps in the initramfs.
 pub(crate) fn list_apps() {
     info!("/**** APPS ****");
-    let app_count = get_app_count();
-    for i in 0..app_count {
-        info!("{}", get_app_name(i));
+    for entry in fs::initramfs::entries() {
+        if entry.node_type() == Some(NodeType::File) {
+            info!("{}", entry.name);
+        }
     }
     info!("**************/");
 }
 
 /// The segment of the elf file, which is used to map the elf file to the memory space
-pub struct ELFSegment {
+pub struct ELFSegment<'a> {
     /// The start virtual address of the segment
     pub start_vaddr: VirtAddr,
     /// The size of the segment
@@ -74,41 +172,65 @@ pub struct ELFSegment {
     /// The flags of the segment which is used to set the page table entry
     pub flags: MappingFlags,
     /// The data of the segment
-    pub data: &'static [u8],
+    pub data: &'a [u8],
     /// The offset of the segment relative to the start of the page
     pub offset: usize,
 }
 
 /// The information of a given ELF file
-pub struct ELFInfo {
+pub struct ELFInfo<'a> {
     /// The entry point of the ELF file
     pub entry: VirtAddr,
+    /// The offset added to the virtual addresses in the ELF file, which is
+    /// the load address of a position-independent one
+    pub base: VirtAddr,
+    /// The path of the interpreter requested by `PT_INTERP`, if any
+    pub interp: Option<String>,
     /// The segments of the ELF file
-    pub segments: Vec<ELFSegment>,
+    pub segments: Vec<ELFSegment<'a>>,
     /// The auxiliary vectors of the ELF file
     pub auxv: BTreeMap<u8, usize>,
 }
 
-/// Load the ELF files by the given app name and return
-/// the segments of the ELF file
+/// Map the flags of an ELF segment to the flags of its mapping.
+fn into_mapflag(f: xmas_elf::program::Flags) -> MappingFlags {
+    let mut ret = MappingFlags::USER;
+    if f.is_read() {
+        ret |= MappingFlags::READ;
+    }
+    if f.is_write() {
+        ret |= MappingFlags::WRITE;
+    }
+    if f.is_execute() {
+        ret |= MappingFlags::EXECUTE;
+    }
+    ret
+}
+
+/// Parse the given ELF file and return the segments of it
 ///
 /// # Arguments
-/// * `name` - The name of the app
+/// * `elf_data` - The content of the ELF file
 /// * `base_addr` - The minimal address of user space
 ///
 /// # Returns
 /// Entry and information about segments of the given ELF file
-pub(crate) fn load_elf(name: &str, base_addr: VirtAddr) -> ELFInfo {
-    use xmas_elf::program::{Flags, SegmentData};
+///
+/// # Errors
+/// - `ENOEXEC` if the file is not an executable or shared object ELF file
+///   of the current architecture
+/// - `EINVAL` if the segments are malformed, e.g. overlap or are
+///   misaligned, or the entry point is outside of them
+pub(crate) fn load_elf(elf_data: &[u8], base_addr: VirtAddr) -> LinuxResult<ELFInfo<'_>> {
+    use xmas_elf::program::{SegmentData, Type};
     use xmas_elf::{header, ElfFile};
 
-    let elf = ElfFile::new(
-        get_app_data_by_name(name).unwrap_or_else(|| panic!("failed to get app: {}", name)),
-    )
-    .expect("invalid ELF file");
+    let elf = ElfFile::new(elf_data).map_err(|_| LinuxError::ENOEXEC)?;
     let elf_header = elf.header;
 
-    assert_eq!(elf_header.pt1.magic, *b"\x7fELF", "invalid elf!");
+    if elf_header.pt1.magic != *b"\x7fELF" {
+        return Err(LinuxError::ENOEXEC);
+    }
 
     let expect_arch = if cfg!(target_arch = "x86_64") {
         header::Machine::X86_64
@@ -117,60 +239,140 @@ pub(crate) fn load_elf(name: &str, base_addr: VirtAddr) -> ELFInfo {
     } else if cfg!(target_arch = "riscv64") {
         header::Machine::RISC_V
     } else {
-        panic!("Unsupported architecture!");
+        return Err(LinuxError::ENOEXEC);
     };
-    assert_eq!(
-        elf.header.pt2.machine().as_machine(),
-        expect_arch,
-        "invalid ELF arch"
-    );
-
-    fn into_mapflag(f: Flags) -> MappingFlags {
-        let mut ret = MappingFlags::USER;
-        if f.is_read() {
-            ret |= MappingFlags::READ;
+    if elf_header.pt2.machine().as_machine() != expect_arch
+        || !matches!(
+            elf_header.pt2.type_().as_type(),
+            header::Type::Executable | header::Type::SharedObject
+        )
+    {
+        return Err(LinuxError::ENOEXEC);
+    }
+
+    // The program headers must be inside the file before they are parsed.
+    let ph_table_size = elf_header.pt2.ph_count() as u64 * elf_header.pt2.ph_entry_size() as u64;
+    if elf_header
+        .pt2
+        .ph_offset()
+        .checked_add(ph_table_size)
+        .map_or(true, |end| end > elf_data.len() as u64)
+    {
+        return Err(LinuxError::ENOEXEC);
+    }
+
+    let elf_offset = kernel_elf_parser::get_elf_base_addr(&elf, base_addr.as_usize())
+        .map_err(|_| LinuxError::ENOEXEC)?;
+    if !memory_addr::is_aligned_4k(elf_offset) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut segments: Vec<ELFSegment> = Vec::new();
+    let mut last_end = VirtAddr::from(0);
+    let mut entry_found = false;
+    let entry_point = elf_header.pt2.entry_point();
+    for ph in elf.program_iter() {
+        if ph.get_type() != Ok(Type::Load) || ph.mem_size() == 0 {
+            continue;
         }
-        if f.is_write() {
-            ret |= MappingFlags::WRITE;
+        if ph.file_size() > ph.mem_size() {
+            return Err(LinuxError::EINVAL);
         }
-        if f.is_execute() {
-            ret |= MappingFlags::EXECUTE;
+        // The address and the offset must agree modulo the alignment, so
+        // that the segment could be mapped from the file.
+        let align = ph.align().max(1);
+        if !align.is_power_of_two() || ph.virtual_addr() % align != ph.offset() % align {
+            return Err(LinuxError::EINVAL);
         }
-        ret
-    }
+        let end = ph
+            .virtual_addr()
+            .checked_add(ph.mem_size())
+            .and_then(|end| (end as usize).checked_add(elf_offset))
+            .ok_or(LinuxError::EINVAL)?;
 
-    let mut segments = Vec::new();
-
-    let elf_offset = kernel_elf_parser::get_elf_base_addr(&elf, base_addr.as_usize()).unwrap();
-    assert!(
-        memory_addr::is_aligned_4k(elf_offset),
-        "ELF base address must be aligned to 4k"
-    );
-
-    elf.program_iter()
-        .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
-        .for_each(|ph| {
-            // align the segment to 4k
-            let st_vaddr = VirtAddr::from(ph.virtual_addr() as usize) + elf_offset;
-            let st_vaddr_align: VirtAddr = st_vaddr.align_down_4k();
-            let ed_vaddr_align = VirtAddr::from((ph.virtual_addr() + ph.mem_size()) as usize)
-                .align_up_4k()
-                + elf_offset;
-            let data = match ph.get_data(&elf).unwrap() {
-                SegmentData::Undefined(data) => data,
-                _ => panic!("failed to get ELF segment data"),
-            };
-            segments.push(ELFSegment {
-                start_vaddr: st_vaddr_align,
-                size: ed_vaddr_align.as_usize() - st_vaddr_align.as_usize(),
-                flags: into_mapflag(ph.flags()),
-                data,
-                offset: st_vaddr.align_offset_4k(),
-            });
+        // align the segment to 4k
+        let st_vaddr = VirtAddr::from(ph.virtual_addr() as usize) + elf_offset;
+        let st_vaddr_align: VirtAddr = st_vaddr.align_down_4k();
+        let ed_vaddr_align = VirtAddr::from(end).align_up_4k();
+        // The segments must be sorted and must not overlap, though the
+        // last page of one may be the first page of the next.
+        if st_vaddr < last_end {
+            return Err(LinuxError::EINVAL);
+        }
+        last_end = VirtAddr::from(end);
+        if ph
+            .offset()
+            .checked_add(ph.file_size())
+            .map_or(true, |end| end > elf_data.len() as u64)
+        {
+            return Err(LinuxError::ENOEXEC);
+        }
+        let data = match ph.get_data(&elf) {
+            Ok(SegmentData::Undefined(data)) => data,
+            _ => return Err(LinuxError::ENOEXEC),
+        };
+        if ph.flags().is_execute()
+            && ph.virtual_addr() <= entry_point
+            && entry_point < ph.virtual_addr() + ph.mem_size()
+        {
+            entry_found = true;
+        }
+        segments.push(ELFSegment {
+            start_vaddr: st_vaddr_align,
+            size: ed_vaddr_align.as_usize() - st_vaddr_align.as_usize(),
+            flags: into_mapflag(ph.flags()),
+            data,
+            offset: st_vaddr.align_offset_4k(),
         });
-    ELFInfo {
-        entry: VirtAddr::from(elf.header.pt2.entry_point() as usize + elf_offset),
-        segments,
-        auxv: kernel_elf_parser::get_auxv_vector(&elf, elf_offset),
     }
+    if segments.is_empty() {
+        return Err(LinuxError::ENOEXEC);
+    }
+    if !entry_found {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let interp = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Interp))
+        .map(|ph| -> LinuxResult<String> {
+            let start = ph.offset() as usize;
+            let path = start
+                .checked_add(ph.file_size() as usize)
+                .and_then(|end| elf_data.get(start..end))
+                .and_then(|data| data.split(|&b| b == 0).next())
+                .and_then(|path| core::str::from_utf8(path).ok())
+                .filter(|path| path.starts_with('/'))
+                .ok_or(LinuxError::ENOEXEC)?;
+            Ok(path.to_string())
+        })
+        .transpose()?;
+
+    // The program headers are found through `PT_PHDR`, or else the loaded
+    // segment that contains them.
+    let ph_offset = elf_header.pt2.ph_offset();
+    let phdr = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Phdr))
+        .map(|ph| ph.virtual_addr())
+        .or_else(|| {
+            elf.program_iter()
+                .filter(|ph| ph.get_type() == Ok(Type::Load))
+                .find(|ph| ph.offset() <= ph_offset && ph_offset < ph.offset() + ph.file_size())
+                .map(|ph| ph.virtual_addr() + ph_offset - ph.offset())
+        })
+        .unwrap_or(ph_offset) as usize
+        + elf_offset;
+
+    let entry = elf.header.pt2.entry_point() as usize + elf_offset;
+    let mut auxv = kernel_elf_parser::get_auxv_vector(&elf, elf_offset);
+    auxv.insert(AT_PHDR, phdr);
+    auxv.insert(AT_ENTRY, entry);
+    Ok(ELFInfo {
+        entry: VirtAddr::from(entry),
+        base: VirtAddr::from(elf_offset),
+        interp,
+        segments,
+        auxv,
+    })
 }
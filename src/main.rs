@@ -11,31 +11,152 @@ extern crate axstd;
 mod config {
     include!(concat!(env!("OUT_DIR"), "/uspace_config.rs"));
 }
+mod fs;
+mod futex;
 mod loader;
 mod mm;
+mod ptr;
+mod random;
+mod signal;
 mod syscall_imp;
 mod task;
 
-use alloc::sync::Arc;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 
 use axhal::arch::UspaceContext;
 use axsync::Mutex;
+use axtask::TaskExtRef;
+
+/// Split the list of testcases into command lines, which are separated by
+/// commas or newlines, and each command line into arguments like a shell.
+///
+/// Arguments are separated by spaces or tabs. Inside single quotes, every
+/// character is literal. Inside double quotes, a backslash only escapes `"`
+/// and `\`. Elsewhere, a backslash escapes any character.
+fn parse_command_lines(list: &str) -> Vec<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut args = Vec::new();
+    // The argument being parsed, which may be empty but quoted.
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = list.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                let arg = arg.get_or_insert_with(String::new);
+                match chars.next() {
+                    Some(c @ ('"' | '\\')) => arg.push(c),
+                    Some(c) => {
+                        arg.push('\\');
+                        arg.push(c);
+                    }
+                    None => arg.push('\\'),
+                }
+            }
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, '\\') => {
+                if let Some(c) = chars.next() {
+                    arg.get_or_insert_with(String::new).push(c);
+                }
+            }
+            (None, ' ' | '\t') => args.extend(arg.take()),
+            (None, ',' | '\n') => {
+                args.extend(arg.take());
+                if !args.is_empty() {
+                    lines.push(core::mem::take(&mut args));
+                }
+            }
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        warn!("Unterminated quote in the testcases list");
+    }
+    args.extend(arg.take());
+    if !args.is_empty() {
+        lines.push(args);
+    }
+    lines
+}
 
 #[no_mangle]
 fn main() {
+    fs::init();
     loader::list_apps();
-    let testcases = option_env!("AX_TESTCASES_LIST")
-        .unwrap_or_else(|| "Please specify the testcases list by making user_apps")
-        .split(',')
-        .filter(|&x| !x.is_empty());
-    for testcase in testcases {
-        info!("Running testcase: {}", testcase);
-        let (entry_vaddr, ustack_top, uspace) = mm::load_user_app(testcase).unwrap();
-        let user_task = task::spawn_user_task(
-            Arc::new(Mutex::new(uspace)),
+    let testcases = parse_command_lines(
+        option_env!("AX_TESTCASES_LIST")
+            .unwrap_or_else(|| "Please specify the testcases list by making user_apps"),
+    );
+    let envs: Vec<Vec<u8>> = config::DEFAULT_ENVS
+        .iter()
+        .map(|env| env.as_bytes().to_vec())
+        .collect();
+    for args in testcases {
+        let testcase = args[0].clone();
+        info!("Running testcase: {:?}", args);
+        let args = args.into_iter().map(String::into_bytes).collect();
+        // Relative names are looked up from the root.
+        let path = if testcase.starts_with('/') {
+            testcase.clone()
+        } else {
+            format!("/{}", testcase)
+        };
+        let (path, elf_data, args) = match loader::read_exec(&path, args) {
+            Ok(res) => res,
+            Err(err) => {
+                error!("Failed to read testcase {}: {:?}", testcase, err);
+                continue;
+            }
+        };
+        let mut uspace = mm::UserSpace::new_empty().unwrap();
+        let res = mm::load_user_app(&mut uspace, &elf_data, &path, &args, &envs);
+        let (entry_vaddr, ustack_top) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                error!("Failed to load testcase {}: {:?}", testcase, err);
+                continue;
+            }
+        };
+        let user_task = task::new_user_task(
+            "userboot",
             UspaceContext::new(entry_vaddr.into(), ustack_top, 2333),
+            0,
+            |pid| {
+                task::Process::new(
+                    pid,
+                    None,
+                    Arc::new(Mutex::new(uspace)),
+                    Arc::new(Mutex::new(signal::SignalActions::new())),
+                    Arc::new(Mutex::new(fs::FdTable::new_with_stdio())),
+                    Arc::new(Mutex::new("/".to_string())),
+                )
+            },
         );
-        let exit_code = user_task.join();
-        info!("User task {} exited with code: {:?}", testcase, exit_code);
+        let process = user_task.task_ext().process.clone();
+        process.set_exec_info(task::ExecInfo {
+            path,
+            args,
+            envs: envs.clone(),
+        });
+        task::spawn_user_task(user_task);
+        fs::set_foreground_pgrp(process.pgid());
+        let wstatus = process.wait_exit();
+        // Like shells, report a termination by a signal as 128 plus the
+        // signal number.
+        let exit_code = match wstatus & 0x7f {
+            0 => (wstatus >> 8) & 0xff,
+            signo => 128 + signo,
+        };
+        info!("User task {} exited with code: {}", testcase, exit_code);
     }
 }
@@ -0,0 +1,282 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec};
+
+use axerrno::AxResult;
+use axsync::Mutex;
+use memory_addr::PAGE_SIZE_4K;
+
+use super::frame::PhysFrame;
+
+/// A file that can be mapped into memory.
+pub trait MappedFile: Send + Sync {
+    /// Read from the file at `offset` into `buf`, and return the number of
+    /// bytes read, which is less than `buf.len()` at the end of the file.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize>;
+
+    /// Write `buf` to the file at `offset`.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize>;
+
+    /// The size of the file in bytes.
+    fn size(&self) -> AxResult<u64>;
+
+    /// The absolute path of the file, if it is in the filesystem.
+    fn path(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Allocate a frame filled with the file content at `offset`.
+///
+/// The part of the frame past the end of the file is filled with zeros.
+fn read_frame(file: &dyn MappedFile, offset: u64) -> AxResult<PhysFrame> {
+    let frame = PhysFrame::alloc_zero()?;
+    let mut buf = vec![0; PAGE_SIZE_4K];
+    let mut len = 0;
+    while len < PAGE_SIZE_4K {
+        let n = file.read_at(offset + len as u64, &mut buf[len..])?;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+    frame.write(0, &buf[..len]);
+    Ok(frame)
+}
+
+/// Memory shared by all mappings of the same object, which is either an
+/// anonymous `MAP_SHARED` region or a file mapped with `MAP_SHARED`.
+///
+/// Its pages are allocated on the first access from any mapping, and stay
+/// shared across `fork`. The pages of a file are mapped read-only until
+/// they are written, so that only the dirty ones are written back.
+///
+/// Each `mmap` of a file creates a new object, as there is no page cache:
+/// separate mappings of the same file do not see the writes to each other
+/// until they are written back, nor the writes by `write`.
+pub struct SharedMemory {
+    /// The file and the offset where the object starts in it.
+    file: Option<(Arc<dyn MappedFile>, u64)>,
+    /// Whether the file is opened for writing, so that the pages can be
+    /// written back.
+    writable: bool,
+    /// The present pages, indexed by page numbers in the object.
+    pages: Mutex<BTreeMap<usize, SharedPage>>,
+}
+
+/// A present page of [`SharedMemory`].
+struct SharedPage {
+    frame: Arc<PhysFrame>,
+    /// Whether the page has been written through any mapping.
+    ///
+    /// The flag is kept after write-back, since the mappings in other
+    /// address spaces may still be writable.
+    dirty: bool,
+}
+
+impl SharedMemory {
+    /// Create an anonymous object, whose pages are filled with zeros.
+    pub fn new_anonymous() -> Self {
+        Self {
+            file: None,
+            writable: false,
+            pages: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Create an object backed by `file` from `offset`. The pages are
+    /// written back only if `writable` is `true`.
+    pub fn new_file(file: Arc<dyn MappedFile>, offset: u64, writable: bool) -> Self {
+        Self {
+            file: Some((file, offset)),
+            writable,
+            pages: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The frame of the `index`-th page, which is allocated if needed.
+    pub fn page(&self, index: usize) -> AxResult<Arc<PhysFrame>> {
+        let mut pages = self.pages.lock();
+        if let Some(page) = pages.get(&index) {
+            return Ok(page.frame.clone());
+        }
+        let frame = match &self.file {
+            Some((file, offset)) => {
+                read_frame(file.as_ref(), offset + (index * PAGE_SIZE_4K) as u64)?
+            }
+            None => PhysFrame::alloc_zero()?,
+        };
+        let frame = Arc::new(frame);
+        pages.insert(
+            index,
+            SharedPage {
+                frame: frame.clone(),
+                dirty: false,
+            },
+        );
+        Ok(frame)
+    }
+
+    /// Whether the `index`-th page must be mapped read-only to catch the
+    /// first write to it, which is the case for the clean pages of files.
+    pub fn is_clean(&self, index: usize) -> bool {
+        self.file.is_some()
+            && self
+                .pages
+                .lock()
+                .get(&index)
+                .map_or(true, |page| !page.dirty)
+    }
+
+    /// Mark the present `index`-th page as written.
+    pub fn mark_dirty(&self, index: usize) {
+        if let Some(page) = self.pages.lock().get_mut(&index) {
+            page.dirty = true;
+        }
+    }
+
+    /// Write the dirty pages with indices in `[start, end)` back to the
+    /// file. Nothing is done for anonymous objects, or if the file is not
+    /// opened for writing.
+    ///
+    /// Like Linux, the file is not extended: the data past its end is lost.
+    pub fn sync(&self, start: usize, end: usize) -> AxResult {
+        let Some((file, offset)) = self.file.as_ref().filter(|_| self.writable) else {
+            return Ok(());
+        };
+        let size = file.size()?;
+        let pages = self.pages.lock();
+        for (&index, page) in pages.range(start..end).filter(|(_, page)| page.dirty) {
+            let page_offset = offset + (index * PAGE_SIZE_4K) as u64;
+            if page_offset >= size {
+                break;
+            }
+            let len = (size - page_offset).min(PAGE_SIZE_4K as u64) as usize;
+            file.write_at(page_offset, &page.frame.as_slice()[..len])?;
+        }
+        Ok(())
+    }
+}
+
+/// What backs the pages of a [`VmArea`](super::space::VmArea).
+#[derive(Clone)]
+pub enum Backend {
+    /// Private anonymous memory, filled with zeros on the first access.
+    Anonymous,
+    /// A private copy of a file, read from `offset` on the first access.
+    /// Changes are not written back.
+    File {
+        file: Arc<dyn MappedFile>,
+        offset: u64,
+    },
+    /// Shared memory, starting from the `page_offset`-th page of `memory`.
+    Shared {
+        memory: Arc<SharedMemory>,
+        page_offset: usize,
+    },
+}
+
+impl Backend {
+    /// The backend of the part of an area starting `delta` bytes (aligned
+    /// to 4K) after the start of the area.
+    pub fn advance(&self, delta: usize) -> Self {
+        match self {
+            Self::Anonymous => Self::Anonymous,
+            Self::File { file, offset } => Self::File {
+                file: file.clone(),
+                offset: offset + delta as u64,
+            },
+            Self::Shared {
+                memory,
+                page_offset,
+            } => Self::Shared {
+                memory: memory.clone(),
+                page_offset: page_offset + delta / PAGE_SIZE_4K,
+            },
+        }
+    }
+
+    /// The mapped file and the offset in it where the area starts, unless
+    /// the memory is anonymous.
+    pub fn file(&self) -> Option<(&Arc<dyn MappedFile>, u64)> {
+        match self {
+            Self::Anonymous => None,
+            Self::File { file, offset } => Some((file, *offset)),
+            Self::Shared {
+                memory,
+                page_offset,
+            } => {
+                let (file, offset) = memory.file.as_ref()?;
+                Some((file, offset + (page_offset * PAGE_SIZE_4K) as u64))
+            }
+        }
+    }
+
+    /// Whether the pages are shared with other mappings, so that they must
+    /// not be copied on write.
+    pub fn is_shared(&self) -> bool {
+        matches!(self, Self::Shared { .. })
+    }
+
+    /// Whether the area may be made writable: shared file mappings require
+    /// the file to be opened for writing.
+    pub fn may_write(&self) -> bool {
+        match self {
+            Self::Shared { memory, .. } => memory.file.is_none() || memory.writable,
+            _ => true,
+        }
+    }
+
+    /// Whether the page `delta` bytes (aligned to 4K) after the start of
+    /// the area belongs to a shared file mapping and has not been written
+    /// yet.
+    pub fn is_clean(&self, delta: usize) -> bool {
+        match self {
+            Self::Shared {
+                memory,
+                page_offset,
+            } => memory.is_clean(page_offset + delta / PAGE_SIZE_4K),
+            _ => false,
+        }
+    }
+
+    /// Mark the page `delta` bytes (aligned to 4K) after the start of the
+    /// area as written, if the memory is shared.
+    pub fn mark_dirty(&self, delta: usize) {
+        if let Self::Shared {
+            memory,
+            page_offset,
+        } = self
+        {
+            memory.mark_dirty(page_offset + delta / PAGE_SIZE_4K);
+        }
+    }
+
+    /// The frame of the page `delta` bytes (aligned to 4K) after the start
+    /// of the area, which is newly allocated unless the memory is shared.
+    pub fn load_page(&self, delta: usize) -> AxResult<Arc<PhysFrame>> {
+        match self {
+            Self::Anonymous => Ok(Arc::new(PhysFrame::alloc_zero()?)),
+            Self::File { file, offset } => {
+                Ok(Arc::new(read_frame(file.as_ref(), offset + delta as u64)?))
+            }
+            Self::Shared {
+                memory,
+                page_offset,
+            } => memory.page(page_offset + delta / PAGE_SIZE_4K),
+        }
+    }
+
+    /// Write the dirty pages in `[delta, delta + size)` back to the file if
+    /// the memory is a shared file mapping.
+    pub fn sync(&self, delta: usize, size: usize) -> AxResult {
+        match self {
+            Self::Shared {
+                memory,
+                page_offset,
+            } => {
+                let start = page_offset + delta / PAGE_SIZE_4K;
+                memory.sync(start, start + size.div_ceil(PAGE_SIZE_4K))
+            }
+            _ => Ok(()),
+        }
+    }
+}
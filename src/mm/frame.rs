@@ -0,0 +1,59 @@
+use axalloc::global_allocator;
+use axerrno::{AxError, AxResult};
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use memory_addr::{PhysAddr, PAGE_SIZE_4K};
+
+/// A 4K physical frame that backs a user page.
+///
+/// The frame is freed when dropped. Frames shared between address spaces
+/// (e.g. after `fork`) are reference-counted by wrapping them in an `Arc`.
+pub struct PhysFrame {
+    start_paddr: PhysAddr,
+}
+
+impl PhysFrame {
+    /// Allocate a frame filled with zeros.
+    pub fn alloc_zero() -> AxResult<Self> {
+        let vaddr = global_allocator()
+            .alloc_pages(1, PAGE_SIZE_4K)
+            .map_err(|_| AxError::NoMemory)?;
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, PAGE_SIZE_4K) };
+        Ok(Self {
+            start_paddr: virt_to_phys(vaddr.into()),
+        })
+    }
+
+    /// Allocate a frame with the same content as `self`.
+    pub fn try_clone(&self) -> AxResult<Self> {
+        let frame = Self::alloc_zero()?;
+        frame.write(0, self.as_slice());
+        Ok(frame)
+    }
+
+    /// The start physical address of the frame.
+    pub fn start_paddr(&self) -> PhysAddr {
+        self.start_paddr
+    }
+
+    /// The content of the frame, accessed through the linear mapping.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(phys_to_virt(self.start_paddr).as_ptr(), PAGE_SIZE_4K)
+        }
+    }
+
+    /// Copy `data` into the frame at `offset`.
+    pub fn write(&self, offset: usize, data: &[u8]) {
+        assert!(offset + data.len() <= PAGE_SIZE_4K);
+        unsafe {
+            let dst = phys_to_virt(self.start_paddr).as_mut_ptr().add(offset);
+            core::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+        }
+    }
+}
+
+impl Drop for PhysFrame {
+    fn drop(&mut self) {
+        global_allocator().dealloc_pages(phys_to_virt(self.start_paddr).as_usize(), 1);
+    }
+}
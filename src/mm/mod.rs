@@ -0,0 +1,232 @@
+mod backend;
+mod frame;
+mod space;
+mod stack;
+
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axhal::{
+    paging::MappingFlags,
+    trap::{register_trap_handler, PAGE_FAULT},
+};
+use axtask::TaskExtRef;
+use memory_addr::{VirtAddr, VirtAddrRange, PAGE_SIZE_4K};
+
+pub use self::backend::{Backend, MappedFile, SharedMemory};
+pub use self::space::UserSpace;
+use crate::{
+    config, loader,
+    signal::{
+        handle_signals, raise_fault_signal, user_trap_frame, SigInfo, SEGV_ACCERR, SEGV_MAPERR,
+        SIGRETURN_TRAMPOLINE, SIGSEGV,
+    },
+    task::exit_if_killed,
+};
+
+/// Map the loaded segments of an ELF file into `uspace`.
+///
+/// A page shared by adjacent segments is mapped once, with the union of
+/// their permissions, like Linux.
+fn map_segments(uspace: &mut UserSpace, elf_info: &loader::ELFInfo) -> AxResult {
+    // The segments are sorted, so only the first page of a segment may be
+    // shared with the previous ones.
+    let mut areas: Vec<(VirtAddr, VirtAddr, MappingFlags)> = Vec::new();
+    for segement in &elf_info.segments {
+        let mut start = segement.start_vaddr;
+        let end = start + segement.size;
+        if let Some(last) = areas.last_mut().filter(|last| start < last.1) {
+            if last.1 - last.0 > PAGE_SIZE_4K {
+                last.1 = start;
+                let flags = last.2 | segement.flags;
+                areas.push((start, start + PAGE_SIZE_4K, flags));
+            } else {
+                last.2 |= segement.flags;
+            }
+            start += PAGE_SIZE_4K;
+        }
+        if start < end {
+            areas.push((start, end, segement.flags));
+        }
+    }
+    for (start, end, flags) in areas {
+        debug!(
+            "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
+            start, end, flags
+        );
+        uspace.map_anonymous(start, end - start, flags, true)?;
+    }
+
+    for segement in &elf_info.segments {
+        if segement.data.is_empty() {
+            continue;
+        }
+
+        uspace.write(segement.start_vaddr + segement.offset, segement.data)?;
+
+        // TDOO: flush the I-cache
+    }
+    Ok(())
+}
+
+/// Parse the interpreter requested by an app.
+fn load_interp(interp_data: &[u8]) -> LinuxResult<loader::ELFInfo<'_>> {
+    let interp_info =
+        loader::load_elf(interp_data, VirtAddr::from_usize(config::USER_INTERP_BASE))?;
+    if interp_info.interp.is_some() {
+        // The interpreter must not need another one.
+        return Err(LinuxError::ELIBBAD);
+    }
+    Ok(interp_info)
+}
+
+/// Build the initial user stack of an app with [`stack::app_stack`].
+///
+/// Returns `E2BIG` if the arguments and the environment variables do not
+/// fit in the user stack.
+fn build_stack(
+    args: &[Vec<u8>],
+    envs: &[Vec<u8>],
+    execfn: &str,
+    auxv: BTreeMap<u8, usize>,
+) -> LinuxResult<(Vec<u8>, VirtAddr)> {
+    let ustack_end = VirtAddr::from_usize(config::USER_STACK_TOP);
+    let (stack_data, ustack_pointer) = stack::app_stack(args, envs, execfn, auxv, ustack_end);
+    if stack_data.len() > config::USER_STACK_SIZE {
+        return Err(LinuxError::E2BIG);
+    }
+    Ok((stack_data, ustack_pointer))
+}
+
+/// Load a user app into `uspace`, which should have no mappings.
+///
+/// If the app requests an interpreter (the dynamic linker), the interpreter
+/// is loaded at [`config::USER_INTERP_BASE`] too, and the app starts
+/// running there.
+///
+/// # Arguments
+/// * `uspace` - The address space to load the app into
+/// * `elf_data` - The ELF file of the app, e.g. from [`loader::read_exec`]
+/// * `execfn` - The path that the app is executed by, for `AT_EXECFN`
+/// * `args` - The arguments passed to the app, including `argv[0]`
+/// * `envs` - The environment variables passed to the app
+///
+/// # Returns
+/// - The first return value is the entry point of the user app.
+/// - The second return value is the top of the user stack.
+///
+/// # Errors
+/// The errors of parsing the app and the interpreter, or `E2BIG` for the
+/// user stack, which are reported before anything is mapped, or the error
+/// of mapping the app, e.g. `ENOMEM`.
+pub fn load_user_app(
+    uspace: &mut UserSpace,
+    elf_data: &[u8],
+    execfn: &str,
+    args: &[Vec<u8>],
+    envs: &[Vec<u8>],
+) -> LinuxResult<(VirtAddr, VirtAddr)> {
+    let mut elf_info = loader::load_elf(elf_data, uspace.base())?;
+    let interp_data = match &elf_info.interp {
+        Some(interp) => {
+            debug!("Loading interpreter: {}", interp);
+            Some(loader::read_app(interp)?)
+        }
+        None => None,
+    };
+    let interp_info = interp_data.as_deref().map(load_interp).transpose()?;
+    if let Some(interp_info) = &interp_info {
+        elf_info
+            .auxv
+            .insert(loader::AT_BASE, interp_info.base.as_usize());
+    }
+    let (stack_data, ustack_pointer) =
+        build_stack(args, envs, execfn, core::mem::take(&mut elf_info.auxv))?;
+
+    map_segments(uspace, &elf_info)?;
+    let mut entry = elf_info.entry;
+    if let Some(interp_info) = interp_info {
+        map_segments(uspace, &interp_info)?;
+        entry = interp_info.entry;
+    }
+
+    // The heap starts right after the highest segment.
+    let heap_start = elf_info
+        .segments
+        .iter()
+        .map(|segement| segement.start_vaddr + segement.size)
+        .max()
+        .unwrap_or(uspace.base());
+    uspace.init_heap(heap_start);
+
+    // The user stack is divided into two parts:
+    // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
+    // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
+    //  When the app starts running, the stack pointer points to `ustack_pointer`.
+    let ustack_end = VirtAddr::from_usize(config::USER_STACK_TOP);
+    let ustack_size = config::USER_STACK_SIZE;
+    let ustack_start = ustack_end - ustack_size;
+    debug!(
+        "Mapping user stack: {:#x?} -> {:#x?}",
+        ustack_start, ustack_end
+    );
+    uspace.map_anonymous(
+        ustack_start,
+        ustack_size,
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        true,
+    )?;
+
+    uspace.write(ustack_pointer, stack_data.as_slice())?;
+
+    // Signal handlers return to the trampoline unless a restorer is given.
+    let trampoline = VirtAddr::from_usize(config::SIGNAL_TRAMPOLINE);
+    uspace.map_anonymous(
+        trampoline,
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+        true,
+    )?;
+    uspace.write(trampoline, SIGRETURN_TRAMPOLINE)?;
+    Ok((entry, ustack_pointer))
+}
+
+/// Whether `vaddr` lies in the user part of the address space.
+fn is_user_addr(vaddr: VirtAddr) -> bool {
+    VirtAddrRange::from_start_size(config::USER_SPACE_BASE.into(), config::USER_SPACE_SIZE)
+        .contains(vaddr)
+}
+
+#[register_trap_handler(PAGE_FAULT)]
+fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
+    // The kernel may also fault on user pages that are not allocated yet or
+    // still shared copy-on-write, e.g. when a syscall writes to a user buffer.
+    if !is_user && !is_user_addr(vaddr) {
+        return false;
+    }
+    let curr = axtask::current();
+    let process = &curr.task_ext().process;
+    let mut aspace = process.aspace.lock();
+    if aspace.handle_page_fault(vaddr, access_flags) {
+        drop(aspace);
+        if is_user {
+            exit_if_killed();
+            handle_signals(user_trap_frame(), None);
+        }
+        return true;
+    }
+    if !is_user {
+        return false;
+    }
+    let code = if aspace.find_area(vaddr).is_some() {
+        SEGV_ACCERR
+    } else {
+        SEGV_MAPERR
+    };
+    drop(aspace);
+    warn!("{}: segmentation fault at {:#x}", curr.id_name(), vaddr);
+    raise_fault_signal(
+        SIGSEGV,
+        SigInfo::from_fault(SIGSEGV, code, vaddr.as_usize()),
+    );
+}
@@ -0,0 +1,795 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+
+use axerrno::{AxError, AxResult};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::{backend::Backend, frame::PhysFrame};
+use crate::config;
+
+/// The maximum size that a stack mapped with `MAP_GROWSDOWN` can grow to.
+const MAX_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// The size of the room above the start of the heap that is left free when
+/// placing mappings, so that the heap can grow.
+const HEAP_RESERVE_SIZE: usize = 1024 * 1024 * 1024;
+
+/// A contiguous range of virtual memory with the same permissions.
+#[derive(Clone)]
+pub struct VmArea {
+    /// The start address of the area, aligned to 4K.
+    pub start: VirtAddr,
+    /// The end address of the area (exclusive), aligned to 4K.
+    pub end: VirtAddr,
+    /// The permissions of the area.
+    pub flags: MappingFlags,
+    /// What backs the pages of the area.
+    pub backend: Backend,
+    /// Whether the area is a stack that grows down on faults below it.
+    pub grows_down: bool,
+}
+
+impl VmArea {
+    fn contains(&self, vaddr: VirtAddr) -> bool {
+        self.start <= vaddr && vaddr < self.end
+    }
+
+    /// The flags to map the page at `vaddr` with. Private pages still
+    /// shared with other address spaces are mapped read-only for
+    /// copy-on-write, and so are the clean pages of shared file mappings to
+    /// catch the first write.
+    fn page_flags(&self, vaddr: VirtAddr, shared_frame: bool) -> MappingFlags {
+        if (shared_frame && !self.backend.is_shared()) || self.backend.is_clean(vaddr - self.start)
+        {
+            self.flags - MappingFlags::WRITE
+        } else {
+            self.flags
+        }
+    }
+
+    /// Write the pages in `[start, end)` of a shared file mapping back to
+    /// the file.
+    fn sync(&self, start: VirtAddr, end: VirtAddr) -> AxResult {
+        let start = start.max(self.start);
+        let end = end.min(self.end);
+        if start >= end {
+            return Ok(());
+        }
+        self.backend.sync(start - self.start, end - start)
+    }
+}
+
+/// The user address space of a process.
+///
+/// The page table is managed by [`axmm::AddrSpace`], while the areas and the
+/// frames backing each page are tracked here, so that frames can be shared
+/// copy-on-write between address spaces. Pages are allocated lazily on the
+/// first access unless the area is populated explicitly.
+pub struct UserSpace {
+    /// The page table of the address space.
+    aspace: AddrSpace,
+    /// The mapped areas, indexed by their start addresses.
+    areas: BTreeMap<VirtAddr, VmArea>,
+    /// The frames of the present pages, indexed by page addresses.
+    pages: BTreeMap<VirtAddr, Arc<PhysFrame>>,
+    /// The start of the heap, aligned to 4K.
+    heap_start: VirtAddr,
+    /// The program break, i.e. the end of the heap.
+    brk: VirtAddr,
+}
+
+impl UserSpace {
+    /// Create an empty user address space.
+    pub fn new_empty() -> AxResult<Self> {
+        Ok(Self {
+            aspace: axmm::new_user_aspace(
+                VirtAddr::from_usize(config::USER_SPACE_BASE),
+                config::USER_SPACE_SIZE,
+            )?,
+            areas: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            heap_start: VirtAddr::from(0),
+            brk: VirtAddr::from(0),
+        })
+    }
+
+    /// The lowest address of the user space.
+    pub fn base(&self) -> VirtAddr {
+        self.aspace.base()
+    }
+
+    /// The highest address of the user space (exclusive).
+    pub fn end(&self) -> VirtAddr {
+        self.aspace.end()
+    }
+
+    /// The physical address of the root page table.
+    pub fn page_table_root(&self) -> PhysAddr {
+        self.aspace.page_table_root()
+    }
+
+    /// Find the area that contains `vaddr`.
+    pub fn find_area(&self, vaddr: VirtAddr) -> Option<&VmArea> {
+        self.areas
+            .range(..=vaddr)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| area.contains(vaddr))
+    }
+
+    /// The mapped areas, sorted by their start addresses.
+    pub fn areas(&self) -> impl Iterator<Item = &VmArea> {
+        self.areas.values()
+    }
+
+    /// The number of bytes of the pages present in memory.
+    pub fn resident_size(&self) -> usize {
+        self.pages.len() * PAGE_SIZE_4K
+    }
+
+    /// Whether `[start, start + size)` overlaps with any mapped area.
+    fn overlaps(&self, start: VirtAddr, size: usize) -> bool {
+        let end = start + size;
+        self.areas
+            .range(..end)
+            .next_back()
+            .is_some_and(|(_, area)| area.end > start)
+    }
+
+    /// Find a free range of `size` bytes, starting the search from `hint`.
+    ///
+    /// The room reserved for the heap to grow into is skipped, otherwise the
+    /// first mapping above the program would stop `brk` from growing.
+    pub fn find_free_area(&self, hint: VirtAddr, size: usize) -> Option<VirtAddr> {
+        let heap_reserve = (self.heap_start.as_usize() != 0).then(|| {
+            let end = self.heap_start.as_usize().saturating_add(HEAP_RESERVE_SIZE);
+            (
+                self.heap_start,
+                VirtAddr::from(end.min(self.end().as_usize())),
+            )
+        });
+        let mut used = self
+            .areas
+            .values()
+            .map(|area| (area.start, area.end))
+            .chain(heap_reserve)
+            .collect::<Vec<_>>();
+        used.sort_unstable_by_key(|&(start, _)| start);
+
+        let mut start = hint.max(self.base()).align_up_4k();
+        for (used_start, used_end) in used {
+            if used_end <= start {
+                continue;
+            }
+            if start.as_usize().checked_add(size)? <= used_start.as_usize() {
+                return Some(start);
+            }
+            start = used_end;
+        }
+        (start.as_usize().checked_add(size)? <= self.end().as_usize()).then_some(start)
+    }
+
+    /// Check that `[start, start + size)` is a non-empty, page-aligned range
+    /// inside the user space.
+    fn check_range(&self, start: VirtAddr, size: usize) -> AxResult {
+        if !start.is_aligned_4k() || !memory_addr::is_aligned_4k(size) || size == 0 {
+            return Err(AxError::InvalidInput);
+        }
+        if start < self.base()
+            || start
+                .as_usize()
+                .checked_add(size)
+                .map_or(true, |end| end > self.end().as_usize())
+        {
+            return Err(AxError::InvalidInput);
+        }
+        Ok(())
+    }
+
+    /// Map a new private anonymous area at `start` with the given
+    /// permissions.
+    ///
+    /// The pages are allocated on the first access, or right now if
+    /// `populate` is `true`.
+    pub fn map_anonymous(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+    ) -> AxResult {
+        self.map(start, size, flags, Backend::Anonymous, populate, false)
+    }
+
+    /// Map a new area at `start` with the given permissions, whose pages
+    /// are provided by `backend`.
+    ///
+    /// The pages are loaded on the first access, or right now if `populate`
+    /// is `true`. The areas in the range are replaced if `replace` is
+    /// `true`, like `MAP_FIXED`, or the mapping fails with `AlreadyExists`.
+    pub fn map(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        backend: Backend,
+        populate: bool,
+        replace: bool,
+    ) -> AxResult {
+        self.map_area(
+            VmArea {
+                start,
+                end: start + size,
+                flags,
+                backend,
+                grows_down: false,
+            },
+            populate,
+            replace,
+        )
+    }
+
+    /// Map a new anonymous stack at `start`, which grows down when the pages
+    /// right below it are accessed, like `MAP_GROWSDOWN`.
+    ///
+    /// `populate` and `replace` are the same as [`Self::map`].
+    pub fn map_stack(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        populate: bool,
+        replace: bool,
+    ) -> AxResult {
+        self.map_area(
+            VmArea {
+                start,
+                end: start + size,
+                flags,
+                backend: Backend::Anonymous,
+                grows_down: true,
+            },
+            populate,
+            replace,
+        )
+    }
+
+    fn map_area(&mut self, area: VmArea, populate: bool, replace: bool) -> AxResult {
+        let (start, size) = (area.start, area.end - area.start);
+        self.check_range(start, size)?;
+        if replace {
+            self.unmap(start, size)?;
+        } else if self.overlaps(start, size) {
+            return Err(AxError::AlreadyExists);
+        }
+        self.areas.insert(start, area);
+        if populate {
+            self.populate(start, size)?;
+        }
+        Ok(())
+    }
+
+    /// Set up an empty heap at `start`, which should be just past the
+    /// program.
+    pub fn init_heap(&mut self, start: VirtAddr) {
+        self.heap_start = start.align_up_4k();
+        self.brk = self.heap_start;
+    }
+
+    /// The start of the heap.
+    pub fn heap_start(&self) -> VirtAddr {
+        self.heap_start
+    }
+
+    /// The program break.
+    pub fn brk(&self) -> VirtAddr {
+        self.brk
+    }
+
+    /// Move the program break to `new_brk`, growing or shrinking the heap.
+    ///
+    /// The pages of the heap are allocated on the first access. Returns
+    /// `InvalidInput` if `new_brk` is below the start of the heap, or
+    /// `NoMemory` if the heap cannot grow into other mappings.
+    pub fn set_brk(&mut self, new_brk: VirtAddr) -> AxResult {
+        if new_brk < self.heap_start || new_brk > self.end() {
+            return Err(AxError::InvalidInput);
+        }
+        let old_end = self.brk.align_up_4k();
+        let new_end = new_brk.align_up_4k();
+        if new_end > old_end {
+            let size = new_end - old_end;
+            if self.overlaps(old_end, size) {
+                return Err(AxError::NoMemory);
+            }
+            let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+            // Extend the last area of the heap if it is still intact.
+            let last = (old_end > self.heap_start)
+                .then(|| self.find_area(old_end - 1))
+                .flatten()
+                .filter(|area| {
+                    area.end == old_end
+                        && area.flags == flags
+                        && matches!(area.backend, Backend::Anonymous)
+                })
+                .map(|area| area.start);
+            match last {
+                Some(start) => self.areas.get_mut(&start).unwrap().end = new_end,
+                None => self.map_anonymous(old_end, size, flags, false)?,
+            }
+        } else if new_end < old_end {
+            self.unmap(new_end, old_end - new_end)?;
+        }
+        self.brk = new_brk;
+        Ok(())
+    }
+
+    /// Remove the mappings in `[start, start + size)`, splitting the areas
+    /// that are partially covered. Unmapped parts of the range are skipped.
+    ///
+    /// The dirty pages of shared file mappings are written back to the
+    /// files.
+    pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        self.check_range(start, size)?;
+        let end = start + size;
+        self.sync_range(start, end)?;
+        self.split_area(start);
+        self.split_area(end);
+        let starts = self
+            .areas
+            .range(start..end)
+            .map(|(&vaddr, _)| vaddr)
+            .collect::<Vec<_>>();
+        for vaddr in starts {
+            self.areas.remove(&vaddr);
+        }
+        self.free_pages(start, end);
+        Ok(())
+    }
+
+    /// Change the permissions of `[start, start + size)` to `flags`.
+    ///
+    /// Returns `NoMemory` if any part of the range is not mapped, or
+    /// `PermissionDenied` if a shared mapping of a file not opened for
+    /// writing would become writable, like `mprotect` of Linux.
+    pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
+        self.check_range(start, size)?;
+        let end = start + size;
+        if !self.is_fully_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        let first = self.find_area(start).unwrap().start;
+        if flags.contains(MappingFlags::WRITE)
+            && self
+                .areas
+                .range(first..end)
+                .any(|(_, area)| !area.backend.may_write())
+        {
+            return Err(AxError::PermissionDenied);
+        }
+        self.split_area(start);
+        self.split_area(end);
+        for (_, area) in self.areas.range_mut(start..end) {
+            area.flags = flags;
+        }
+        let pages = self
+            .pages
+            .range(start..end)
+            .map(|(&vaddr, frame)| (vaddr, frame.clone()))
+            .collect::<Vec<_>>();
+        for (vaddr, frame) in pages {
+            // The extra reference is held by `pages`.
+            let page_flags = self
+                .find_area(vaddr)
+                .unwrap()
+                .page_flags(vaddr, Arc::strong_count(&frame) > 2);
+            self.remap_page(vaddr, &frame, page_flags)?;
+        }
+        Ok(())
+    }
+
+    /// Resize the area at `[old_start, old_start + old_size)` to `new_size`
+    /// bytes, and return its new start address.
+    ///
+    /// The area is grown in place if possible, or moved to a free range if
+    /// `may_move` is `true`. Returns `BadAddress` if the old range is not
+    /// inside a single area, or `NoMemory` if the area cannot be grown.
+    pub fn remap(
+        &mut self,
+        old_start: VirtAddr,
+        old_size: usize,
+        new_size: usize,
+        may_move: bool,
+    ) -> AxResult<VirtAddr> {
+        self.check_range(old_start, old_size)?;
+        if !memory_addr::is_aligned_4k(new_size) || new_size == 0 {
+            return Err(AxError::InvalidInput);
+        }
+        let old_end = old_start + old_size;
+        let (area_start, flags, backend, grows_down) = match self.find_area(old_start) {
+            Some(area) if area.end >= old_end => (
+                area.start,
+                area.flags,
+                area.backend.advance(old_start - area.start),
+                area.grows_down,
+            ),
+            _ => return Err(AxError::BadAddress),
+        };
+        if new_size <= old_size {
+            if new_size < old_size {
+                self.unmap(old_start + new_size, old_size - new_size)?;
+            }
+            return Ok(old_start);
+        }
+
+        // The area can only grow in place if it ends at `old_end` and is
+        // followed by enough free space.
+        let grow = new_size - old_size;
+        if self.check_range(old_end, grow).is_ok() && !self.overlaps(old_end, grow) {
+            self.areas.get_mut(&area_start).unwrap().end = old_start + new_size;
+            return Ok(old_start);
+        }
+        if !may_move {
+            return Err(AxError::NoMemory);
+        }
+
+        let new_start = self
+            .find_free_area(self.base(), new_size)
+            .ok_or(AxError::NoMemory)?;
+        let pages = self
+            .pages
+            .range(old_start..old_end)
+            .map(|(&vaddr, frame)| (vaddr, frame.clone()))
+            .collect::<Vec<_>>();
+        self.unmap(old_start, old_size)?;
+        let area = VmArea {
+            start: new_start,
+            end: new_start + new_size,
+            flags,
+            backend,
+            grows_down,
+        };
+        for (vaddr, frame) in pages {
+            let vaddr = new_start + (vaddr - old_start);
+            // The old mapping has been removed, so the only extra reference
+            // is held by `frame` itself.
+            let page_flags = area.page_flags(vaddr, Arc::strong_count(&frame) > 1);
+            self.map_page(vaddr, frame, page_flags)?;
+        }
+        self.areas.insert(new_start, area);
+        Ok(new_start)
+    }
+
+    /// Free the pages in `[start, start + size)`, so that they read as
+    /// zeros on the next access, like `MADV_DONTNEED`.
+    ///
+    /// Returns `NoMemory` if any part of the range is not mapped.
+    pub fn discard(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        self.check_range(start, size)?;
+        let end = start + size;
+        if !self.is_fully_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        self.free_pages(start, end);
+        Ok(())
+    }
+
+    /// Write the dirty pages of shared file mappings in
+    /// `[start, start + size)` back to the files, like `msync`.
+    ///
+    /// Returns `NoMemory` if any part of the range is not mapped.
+    pub fn sync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !start.is_aligned_4k() {
+            return Err(AxError::InvalidInput);
+        }
+        let end = start
+            .as_usize()
+            .checked_add(size)
+            .ok_or(AxError::NoMemory)?
+            .into();
+        if !self.is_fully_mapped(start, end) {
+            return Err(AxError::NoMemory);
+        }
+        self.sync_range(start, end)
+    }
+
+    /// Make sure all pages in `[start, start + size)` are present and owned
+    /// exclusively by this address space.
+    ///
+    /// The pages of shared mappings are only made present, so that they are
+    /// not marked dirty.
+    pub fn populate(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        let end = (start + size).align_up_4k();
+        let mut vaddr = start.align_down_4k();
+        while vaddr < end {
+            let area = self.find_area(vaddr).ok_or(AxError::BadAddress)?;
+            if area.backend.is_shared() {
+                self.make_page_present(vaddr)?;
+            } else {
+                self.make_page_exclusive(vaddr)?;
+            }
+            vaddr += PAGE_SIZE_4K;
+        }
+        Ok(())
+    }
+
+    /// Copy `data` into the address space at `start`, regardless of the
+    /// permissions of the areas.
+    pub fn write(&mut self, start: VirtAddr, data: &[u8]) -> AxResult {
+        let mut vaddr = start;
+        let mut data = data;
+        while !data.is_empty() {
+            let page = vaddr.align_down_4k();
+            let offset = vaddr.align_offset_4k();
+            let len = data.len().min(PAGE_SIZE_4K - offset);
+            self.make_page_exclusive(page)?.write(offset, &data[..len]);
+            vaddr += len;
+            data = &data[len..];
+        }
+        Ok(())
+    }
+
+    /// The physical address that `vaddr` is mapped to.
+    ///
+    /// The page is allocated if it is not present yet. A writable page is
+    /// also made exclusive, so that the returned address does not change
+    /// when the page is written later.
+    pub fn resolve(&mut self, vaddr: VirtAddr) -> AxResult<PhysAddr> {
+        let page = vaddr.align_down_4k();
+        let flags = self.find_area(page).ok_or(AxError::BadAddress)?.flags;
+        let frame = if flags.contains(MappingFlags::WRITE) {
+            self.make_page_exclusive(page)?
+        } else {
+            self.make_page_present(page)?
+        };
+        Ok(frame.start_paddr() + vaddr.align_offset_4k())
+    }
+
+    /// Remove all areas and free all pages that are not shared.
+    ///
+    /// The dirty pages of shared file mappings are written back to the
+    /// files.
+    pub fn clear(&mut self) {
+        if let Err(err) = self.sync_range(self.base(), self.end()) {
+            warn!("failed to write back shared mappings: {:?}", err);
+        }
+        for &vaddr in self.pages.keys() {
+            self.aspace.unmap(vaddr, PAGE_SIZE_4K).ok();
+        }
+        self.pages.clear();
+        self.areas.clear();
+    }
+
+    /// Replace the mappings with those of `new`, e.g. a new image built by
+    /// `execve`.
+    ///
+    /// The page table is kept, since it may be in use by the current CPU,
+    /// and the frames of `new` are moved into it.
+    pub fn replace(&mut self, mut new: Self) -> AxResult {
+        self.clear();
+        self.areas = core::mem::take(&mut new.areas);
+        self.heap_start = new.heap_start;
+        self.brk = new.brk;
+        for (vaddr, frame) in core::mem::take(&mut new.pages) {
+            let area = self.find_area(vaddr).unwrap();
+            let flags = area.page_flags(vaddr, Arc::strong_count(&frame) > 1);
+            self.map_page(vaddr, frame, flags)?;
+        }
+        Ok(())
+    }
+
+    /// Duplicate the address space for `fork`.
+    ///
+    /// No page is copied: the frames are shared by both address spaces, and
+    /// the writable private ones are mapped read-only until either side
+    /// writes to them (copy-on-write). Shared mappings stay shared.
+    pub fn try_clone(&mut self) -> AxResult<Self> {
+        let mut new_space = Self::new_empty()?;
+        new_space.areas = self.areas.clone();
+        new_space.heap_start = self.heap_start;
+        new_space.brk = self.brk;
+        let pages = self
+            .pages
+            .iter()
+            .map(|(&vaddr, frame)| (vaddr, frame.clone()))
+            .collect::<BTreeMap<_, _>>();
+        for (vaddr, frame) in pages {
+            let area = self.find_area(vaddr).unwrap();
+            let flags = area.page_flags(vaddr, true);
+            if flags != area.flags {
+                self.remap_page(vaddr, &frame, flags)?;
+            }
+            new_space.map_page(vaddr, frame, flags)?;
+        }
+        Ok(new_space)
+    }
+
+    /// Handle a page fault at `vaddr` caused by an access with `access_flags`.
+    ///
+    /// Returns `false` if the access is not allowed by the area.
+    pub fn handle_page_fault(&mut self, vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
+        match self.check_access(vaddr, 1, access_flags) {
+            Ok(()) => true,
+            Err(AxError::BadAddress) => false,
+            Err(err) => {
+                warn!("failed to handle page fault at {:#x}: {:?}", vaddr, err);
+                false
+            }
+        }
+    }
+
+    /// Check that `[start, start + size)` is mapped by areas that allow
+    /// `access_flags`, and make the pages present so that the kernel can
+    /// access them directly.
+    ///
+    /// The pages are also made exclusive if `access_flags` contains `WRITE`.
+    /// Returns `BadAddress` if any page is not accessible.
+    pub fn check_access(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        access_flags: MappingFlags,
+    ) -> AxResult {
+        let end = start
+            .as_usize()
+            .checked_add(size)
+            .ok_or(AxError::BadAddress)?;
+        let mut vaddr = start.align_down_4k();
+        if self.find_area(vaddr).is_none() {
+            self.grow_stack(vaddr);
+        }
+        while vaddr.as_usize() < end {
+            match self.find_area(vaddr) {
+                Some(area) if area.flags.contains(access_flags) => {}
+                _ => return Err(AxError::BadAddress),
+            }
+            if access_flags.contains(MappingFlags::WRITE) {
+                self.make_page_exclusive(vaddr)?;
+            } else {
+                self.make_page_present(vaddr)?;
+            }
+            vaddr += PAGE_SIZE_4K;
+        }
+        Ok(())
+    }
+
+    /// Write back the shared file mappings in `[start, end)`.
+    fn sync_range(&self, start: VirtAddr, end: VirtAddr) -> AxResult {
+        let first = self.find_area(start).map_or(start, |area| area.start);
+        for area in self.areas.range(first..end).map(|(_, area)| area) {
+            area.sync(start, end)?;
+        }
+        Ok(())
+    }
+
+    /// Extend the `MAP_GROWSDOWN` area right above the unmapped page
+    /// `vaddr` down to it. Returns `false` if there is no such area, or it
+    /// cannot grow that far.
+    fn grow_stack(&mut self, vaddr: VirtAddr) -> bool {
+        let Some((&start, area)) = self.areas.range(vaddr..).next() else {
+            return false;
+        };
+        if !area.grows_down || area.end - vaddr > MAX_STACK_SIZE {
+            return false;
+        }
+        // Keep a guard page between the stack and the area below it.
+        if vaddr < self.base() + PAGE_SIZE_4K
+            || self.overlaps(vaddr - PAGE_SIZE_4K, start - vaddr + PAGE_SIZE_4K)
+        {
+            return false;
+        }
+        let mut area = self.areas.remove(&start).unwrap();
+        area.start = vaddr;
+        self.areas.insert(vaddr, area);
+        true
+    }
+
+    /// Whether every page in `[start, end)` belongs to an area.
+    fn is_fully_mapped(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let mut vaddr = start;
+        while vaddr < end {
+            match self.find_area(vaddr) {
+                Some(area) => vaddr = area.end,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Split the area containing `vaddr` into two at `vaddr`, so that no
+    /// area crosses it.
+    fn split_area(&mut self, vaddr: VirtAddr) {
+        let Some(area) = self.find_area(vaddr) else {
+            return;
+        };
+        if area.start == vaddr {
+            return;
+        }
+        let mut upper = area.clone();
+        upper.start = vaddr;
+        upper.backend = area.backend.advance(vaddr - area.start);
+        let lower_start = area.start;
+        self.areas.get_mut(&lower_start).unwrap().end = vaddr;
+        self.areas.insert(vaddr, upper);
+    }
+
+    /// Unmap and release the present pages in `[start, end)`.
+    fn free_pages(&mut self, start: VirtAddr, end: VirtAddr) {
+        let vaddrs = self
+            .pages
+            .range(start..end)
+            .map(|(&vaddr, _)| vaddr)
+            .collect::<Vec<_>>();
+        for vaddr in vaddrs {
+            self.aspace.unmap(vaddr, PAGE_SIZE_4K).ok();
+            self.pages.remove(&vaddr);
+        }
+    }
+
+    fn map_page(
+        &mut self,
+        vaddr: VirtAddr,
+        frame: Arc<PhysFrame>,
+        flags: MappingFlags,
+    ) -> AxResult {
+        self.aspace
+            .map_linear(vaddr, frame.start_paddr(), PAGE_SIZE_4K, flags)?;
+        self.pages.insert(vaddr, frame);
+        Ok(())
+    }
+
+    fn remap_page(&mut self, vaddr: VirtAddr, frame: &PhysFrame, flags: MappingFlags) -> AxResult {
+        self.aspace.unmap(vaddr, PAGE_SIZE_4K)?;
+        self.aspace
+            .map_linear(vaddr, frame.start_paddr(), PAGE_SIZE_4K, flags)
+    }
+
+    /// Allocate the page at `vaddr` if it is not present yet.
+    fn make_page_present(&mut self, vaddr: VirtAddr) -> AxResult<Arc<PhysFrame>> {
+        if let Some(frame) = self.pages.get(&vaddr) {
+            return Ok(frame.clone());
+        }
+        let area = self.find_area(vaddr).ok_or(AxError::BadAddress)?;
+        let frame = area.backend.load_page(vaddr - area.start)?;
+        let flags = area.page_flags(vaddr, false);
+        self.map_page(vaddr, frame.clone(), flags)?;
+        Ok(frame)
+    }
+
+    /// Make the page at `vaddr` present and not shared with other address
+    /// spaces, copying the frame if needed.
+    ///
+    /// The pages of shared mappings are never copied, but they are marked
+    /// dirty if the area is writable.
+    fn make_page_exclusive(&mut self, vaddr: VirtAddr) -> AxResult<Arc<PhysFrame>> {
+        let frame = self.make_page_present(vaddr)?;
+        let area = self.find_area(vaddr).unwrap();
+        let (flags, shared) = (area.flags, area.backend.is_shared());
+        if shared && flags.contains(MappingFlags::WRITE) {
+            area.backend.mark_dirty(vaddr - area.start);
+        }
+        // One reference is held by `self.pages`, the other one by `frame`.
+        let frame = if !shared && Arc::strong_count(&frame) > 2 {
+            let new_frame = Arc::new(frame.try_clone()?);
+            self.pages.insert(vaddr, new_frame.clone());
+            new_frame
+        } else if self.is_mapped_with(vaddr, flags) {
+            // Already exclusive and mapped with the full permissions.
+            return Ok(frame);
+        } else {
+            frame
+        };
+        self.remap_page(vaddr, &frame, flags)?;
+        Ok(frame)
+    }
+
+    /// Whether the page at `vaddr` is mapped with all of `flags` in the page
+    /// table.
+    fn is_mapped_with(&self, vaddr: VirtAddr, flags: MappingFlags) -> bool {
+        self.aspace
+            .page_table()
+            .query(vaddr)
+            .is_ok_and(|(_, mapped_flags, _)| mapped_flags.contains(flags))
+    }
+}
@@ -0,0 +1,110 @@
+use alloc::{collections::btree_map::BTreeMap, vec, vec::Vec};
+use core::mem::size_of;
+
+use memory_addr::VirtAddr;
+
+use crate::{
+    loader::{
+        AT_CLKTCK, AT_EGID, AT_EUID, AT_EXECFN, AT_GID, AT_HWCAP, AT_MINSIGSTKSZ, AT_NULL,
+        AT_RANDOM, AT_SECURE, AT_UID,
+    },
+    random,
+    signal::MIN_SIGNAL_STACK_SIZE,
+};
+
+/// The CPU features in `AT_HWCAP`, which are the feature bits in `EDX` of
+/// CPUID leaf 1.
+#[cfg(target_arch = "x86_64")]
+fn hwcap() -> usize {
+    x86::cpuid::native_cpuid::cpuid_count(1, 0).edx as usize
+}
+
+/// The CPU features in `AT_HWCAP`: the floating point and SIMD units, which
+/// every ARMv8 CPU that Linux runs on has.
+#[cfg(target_arch = "aarch64")]
+fn hwcap() -> usize {
+    const HWCAP_FP: usize = 1 << 0;
+    const HWCAP_ASIMD: usize = 1 << 1;
+    HWCAP_FP | HWCAP_ASIMD
+}
+
+/// The CPU features in `AT_HWCAP`, which are a bit for each single-letter
+/// extension: those of RV64GC.
+#[cfg(target_arch = "riscv64")]
+fn hwcap() -> usize {
+    b"imafdc"
+        .iter()
+        .fold(0, |hwcap, &ext| hwcap | 1 << (ext - b'a'))
+}
+
+/// Build the initial user stack of an app, which ends at `stack_top`.
+///
+/// From the top down, the stack holds the strings of the path of the app,
+/// the environment variables and the arguments, 16 random bytes, then
+/// `auxv`, `envp`, `argv` and `argc` at the returned stack pointer. `auxv`
+/// is completed with the entries that the ELF file does not determine.
+/// There is no vDSO, so `AT_SYSINFO_EHDR` is left out, and the C libraries
+/// make real syscalls for the time instead.
+///
+/// # Returns
+/// The content of the stack from the stack pointer up, and the stack
+/// pointer.
+pub(super) fn app_stack(
+    args: &[Vec<u8>],
+    envs: &[Vec<u8>],
+    execfn: &str,
+    mut auxv: BTreeMap<u8, usize>,
+    stack_top: VirtAddr,
+) -> (Vec<u8>, VirtAddr) {
+    // The strings and the random bytes, from the lowest address up.
+    let mut info = vec![0; 16];
+    random::fill_bytes(&mut info);
+    let mut offsets = Vec::with_capacity(args.len() + envs.len() + 1);
+    for s in args
+        .iter()
+        .chain(envs)
+        .map(Vec::as_slice)
+        .chain([execfn.as_bytes()])
+    {
+        offsets.push(info.len());
+        info.extend_from_slice(s);
+        info.push(0);
+    }
+    let info_start = stack_top.as_usize() - info.len();
+    let mut addrs = offsets.into_iter().map(|offset| info_start + offset);
+    let argv: Vec<usize> = addrs.by_ref().take(args.len()).collect();
+    let envp: Vec<usize> = addrs.by_ref().take(envs.len()).collect();
+    let execfn_addr = addrs.next().unwrap();
+
+    // All processes run as root, and none gains privileges by `execve`.
+    for key in [AT_UID, AT_EUID, AT_GID, AT_EGID, AT_SECURE] {
+        auxv.insert(key, 0);
+    }
+    auxv.insert(AT_HWCAP, hwcap());
+    // `USER_HZ`, as in procfs.
+    auxv.insert(AT_CLKTCK, 100);
+    auxv.insert(AT_MINSIGSTKSZ, MIN_SIGNAL_STACK_SIZE);
+    auxv.insert(AT_RANDOM, info_start);
+    auxv.insert(AT_EXECFN, execfn_addr);
+
+    // `argc`, `argv`, `envp` and `auxv`, with their terminating entries.
+    let mut words = vec![args.len()];
+    words.extend(argv);
+    words.push(0);
+    words.extend(envp);
+    words.push(0);
+    for (&key, &value) in auxv.iter().filter(|(&key, _)| key != AT_NULL) {
+        words.extend([key as usize, value]);
+    }
+    words.extend([AT_NULL as usize, 0]);
+
+    // The stack pointer is aligned to 16 bytes when the app starts.
+    let sp = (info_start - words.len() * size_of::<usize>()) & !0xf;
+    let mut data = Vec::with_capacity(stack_top.as_usize() - sp);
+    for word in words {
+        data.extend_from_slice(&word.to_ne_bytes());
+    }
+    data.resize(info_start - sp, 0);
+    data.extend_from_slice(&info);
+    (data, VirtAddr::from(sp))
+}
@@ -0,0 +1,292 @@
+//! Checked access to user memory.
+//!
+//! Syscall handlers receive user pointers as plain integers and must not
+//! dereference them directly. The types here check the addresses against
+//! the address space of the current process, fault the pages in, and return
+//! `EFAULT` for invalid addresses instead of crashing the kernel.
+//!
+//! User memory is only accessed by short copies while the address space is
+//! locked, so that other threads cannot unmap the pages in the meantime.
+//! Nothing may block during the copies.
+
+use alloc::{string::String, vec::Vec};
+use core::{marker::PhantomData, mem::size_of};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axtask::{current, TaskExtRef};
+use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+
+/// Check that the current process can access `[start, start + size)` with
+/// `access_flags`, make the pages present, and run `f` to access them.
+///
+/// The address space stays locked while `f` runs, so `f` must not block.
+fn with_region<R>(
+    start: usize,
+    size: usize,
+    access_flags: MappingFlags,
+    f: impl FnOnce() -> R,
+) -> LinuxResult<R> {
+    let curr = current();
+    let mut aspace = curr.task_ext().process.aspace.lock();
+    if size != 0 {
+        aspace
+            .check_access(VirtAddr::from(start), size, access_flags)
+            .map_err(|_| LinuxError::EFAULT)?;
+    }
+    Ok(f())
+}
+
+/// A pointer to a value of type `T` in user space.
+#[repr(transparent)]
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> From<usize> for UserPtr<T> {
+    fn from(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> UserPtr<T> {
+    /// The address of the pointer.
+    pub fn address(&self) -> usize {
+        self.addr
+    }
+
+    /// Whether the pointer is NULL.
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    /// The pointer to the `count`-th element after this one.
+    pub fn add(self, count: usize) -> Self {
+        Self::from(self.addr.wrapping_add(count * size_of::<T>()))
+    }
+
+    fn check(&self) -> LinuxResult {
+        if self.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        Ok(())
+    }
+
+    /// Read the value.
+    pub fn read(&self) -> LinuxResult<T>
+    where
+        T: Copy,
+    {
+        self.check()?;
+        let ptr = self.addr as *const T;
+        // Like Linux, misaligned pointers are accepted.
+        with_region(self.addr, size_of::<T>(), MappingFlags::READ, || unsafe {
+            ptr.read_unaligned()
+        })
+    }
+
+    /// Write `value` to the pointer.
+    pub fn write(&self, value: T) -> LinuxResult {
+        self.check()?;
+        let ptr = self.addr as *mut T;
+        with_region(self.addr, size_of::<T>(), MappingFlags::WRITE, || unsafe {
+            ptr.write_unaligned(value)
+        })
+    }
+}
+
+/// A slice of `len` values of type `T` in user space.
+pub struct UserSlice<T> {
+    ptr: UserPtr<T>,
+    len: usize,
+}
+
+impl<T> UserSlice<T> {
+    /// Create a slice starting at `addr` with `len` elements.
+    pub fn new(addr: usize, len: usize) -> Self {
+        Self {
+            ptr: UserPtr::from(addr),
+            len,
+        }
+    }
+
+    /// The number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the slice has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The slice of at most `len` elements from the `start`-th one on.
+    pub fn slice(&self, start: usize, len: usize) -> Self {
+        let start = start.min(self.len);
+        Self {
+            ptr: self.ptr.add(start),
+            len: len.min(self.len - start),
+        }
+    }
+
+    /// Run `f` on the bytes of the elements while they are accessible with
+    /// `access_flags`. The pointer passed to `f` may be misaligned for `T`,
+    /// which Linux accepts.
+    fn access<R>(
+        &self,
+        access_flags: MappingFlags,
+        f: impl FnOnce(*mut u8, usize) -> R,
+    ) -> LinuxResult<R> {
+        if self.ptr.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let size = self
+            .len
+            .checked_mul(size_of::<T>())
+            .ok_or(LinuxError::EFAULT)?;
+        let ptr = self.ptr.address() as *mut u8;
+        with_region(self.ptr.address(), size, access_flags, || f(ptr, size))
+    }
+
+    /// Check that the slice can be written, so that a syscall can fail
+    /// before it has any effect.
+    pub fn check_writable(&self) -> LinuxResult {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.access(MappingFlags::WRITE, |_, _| ())
+    }
+
+    /// Read the elements into a new vector.
+    pub fn read_vec(&self) -> LinuxResult<Vec<T>>
+    where
+        T: Copy,
+    {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.access(MappingFlags::READ, |ptr, size| {
+            let mut vec = Vec::<T>::with_capacity(self.len);
+            unsafe {
+                core::ptr::copy_nonoverlapping(ptr, vec.as_mut_ptr() as *mut u8, size);
+                vec.set_len(self.len);
+            }
+            vec
+        })
+    }
+
+    /// Write `data` to the first elements of the slice.
+    ///
+    /// Returns `EFAULT` if `data` is longer than the slice.
+    pub fn write(&self, data: &[T]) -> LinuxResult
+    where
+        T: Copy,
+    {
+        if data.len() > self.len {
+            return Err(LinuxError::EFAULT);
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        let dest = Self {
+            ptr: self.ptr,
+            len: data.len(),
+        };
+        dest.access(MappingFlags::WRITE, |ptr, size| unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, ptr, size)
+        })
+    }
+}
+
+/// The maximum size of a path including the terminating NUL, like
+/// `PATH_MAX` of Linux.
+pub const PATH_MAX: usize = 4096;
+
+/// A NUL-terminated string in user space.
+#[derive(Clone, Copy)]
+pub struct UserCStr(UserPtr<u8>);
+
+impl From<usize> for UserCStr {
+    fn from(addr: usize) -> Self {
+        Self(UserPtr::from(addr))
+    }
+}
+
+impl UserCStr {
+    /// Whether the pointer is NULL.
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+
+    /// Read the bytes of the string without the terminating NUL, or `None`
+    /// if there is no NUL in the first `max_len` bytes.
+    fn read_bytes(&self, max_len: usize) -> LinuxResult<Option<Vec<u8>>> {
+        if self.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let mut bytes = Vec::new();
+        let mut addr = VirtAddr::from(self.0.address());
+        while bytes.len() < max_len {
+            // Check one page at a time, since the string may end before the
+            // next page, which can be unmapped.
+            let len = (PAGE_SIZE_4K - addr.align_offset_4k()).min(max_len - bytes.len());
+            let chunk = UserSlice::<u8>::new(addr.as_usize(), len).read_vec()?;
+            if let Some(nul) = chunk.iter().position(|&b| b == 0) {
+                bytes.extend_from_slice(&chunk[..nul]);
+                return Ok(Some(bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+            addr += len;
+        }
+        Ok(None)
+    }
+
+    /// Read the string as a path, without the terminating NUL.
+    ///
+    /// Paths may hold any bytes, but the names in the filesystems are UTF-8,
+    /// so invalid sequences are replaced with `U+FFFD`. Returns `EFAULT` if
+    /// the string is not accessible, or `ENAMETOOLONG` if it does not fit in
+    /// [`PATH_MAX`] bytes.
+    pub fn read_path(&self) -> LinuxResult<String> {
+        let bytes = self.read_bytes(PATH_MAX)?.ok_or(LinuxError::ENAMETOOLONG)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Read a NULL-terminated array of strings, such as `argv` and `envp`,
+    /// keeping their bytes as they are.
+    ///
+    /// An empty array is returned if `array` is NULL. `budget` is the number
+    /// of bytes that the strings and the pointers to them may take, and it
+    /// is reduced by them. Returns `E2BIG` if they take more.
+    pub fn read_array(array: UserPtr<usize>, budget: &mut usize) -> LinuxResult<Vec<Vec<u8>>> {
+        let mut strs = Vec::new();
+        if array.is_null() {
+            return Ok(strs);
+        }
+        for i in 0.. {
+            *budget = budget
+                .checked_sub(size_of::<usize>())
+                .ok_or(LinuxError::E2BIG)?;
+            let str_ptr = UserCStr::from(array.add(i).read()?);
+            if str_ptr.is_null() {
+                break;
+            }
+            let s = str_ptr.read_bytes(*budget)?.ok_or(LinuxError::E2BIG)?;
+            *budget -= s.len() + 1;
+            strs.push(s);
+        }
+        Ok(strs)
+    }
+}
@@ -0,0 +1,31 @@
+//! The random number generator of the kernel, behind `getrandom` and
+//! `/dev/random`.
+//!
+//! It is a SplitMix64 generator whose state is perturbed by the time of each
+//! request. It is good enough to seed the generators and hash tables of user
+//! programs, but it is not cryptographically secure.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The increment of the state, which is the golden ratio in fixed point.
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+fn next_u64() -> u64 {
+    let state = STATE
+        .fetch_add(GAMMA, Ordering::Relaxed)
+        .wrapping_add(GAMMA);
+    let mut z = state ^ axhal::time::monotonic_time_nanos();
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Fill `buf` with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = next_u64().to_ne_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
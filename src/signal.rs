@@ -0,0 +1,1313 @@
+//! POSIX signals.
+//!
+//! Signal handlers are per process, while signal masks are per thread.
+//! Signals can be pending on a thread (e.g. sent by `tgkill` or raised by a
+//! fault) or on the whole process (e.g. sent by `kill`), in which case any
+//! thread that does not block them may take them.
+//!
+//! Signals are delivered when a thread returns to user space from a syscall
+//! or a page fault. To run a handler, the user context, including the
+//! FP/SIMD registers, is saved in a [`SignalFrame`] on the user stack, and
+//! `rt_sigreturn` restores it.
+//! Syscalls interrupted by signals are restarted if the handler has
+//! `SA_RESTART` or no handler runs, and stop signals stop the whole process
+//! until SIGCONT.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axerrno::LinuxError;
+use axhal::arch::{TrapFrame, UspaceContext};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    config,
+    ptr::UserPtr,
+    task::{exit_current, exit_if_killed, wstatus_signaled, Process},
+};
+
+/// The number of signals.
+pub const NSIG: usize = 64;
+
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGKILL: u32 = 9;
+pub const SIGSEGV: u32 = 11;
+pub const SIGPIPE: u32 = 13;
+pub const SIGCHLD: u32 = 17;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+pub const SIGTTIN: u32 = 21;
+pub const SIGTTOU: u32 = 22;
+pub const SIGURG: u32 = 23;
+pub const SIGWINCH: u32 = 28;
+
+/// `si_code` of signals sent by `kill`.
+pub const SI_USER: i32 = 0;
+/// `si_code` of signals sent by the kernel.
+pub const SI_KERNEL: i32 = 0x80;
+/// `si_code` of signals sent by `tgkill`.
+pub const SI_TKILL: i32 = -6;
+/// `si_code` of SIGSEGV for addresses that are not mapped.
+pub const SEGV_MAPERR: i32 = 1;
+/// `si_code` of SIGSEGV for accesses that are not allowed.
+pub const SEGV_ACCERR: i32 = 2;
+
+/// The return value of a syscall interrupted by a signal that may be
+/// restarted, which never reaches user space: it becomes `EINTR` if a
+/// handler without `SA_RESTART` runs, or the syscall is restarted.
+pub const ERESTARTSYS: isize = 512;
+
+/// The default action of SIG_DFL.
+const SIG_DFL: usize = 0;
+/// Ignore the signal.
+const SIG_IGN: usize = 1;
+
+bitflags::bitflags! {
+    /// Flags of [`SigAction`]
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/signal-defs.h>
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct SigActionFlags: u64 {
+        /// Do not send SIGCHLD when children stop.
+        const SA_NOCLDSTOP = 0x0000_0001;
+        /// Do not turn children into zombies.
+        const SA_NOCLDWAIT = 0x0000_0002;
+        /// The handler takes three arguments.
+        const SA_SIGINFO = 0x0000_0004;
+        /// `restorer` is set.
+        const SA_RESTORER = 0x0400_0000;
+        /// Run the handler on the alternate signal stack.
+        const SA_ONSTACK = 0x0800_0000;
+        /// Restart syscalls interrupted by the signal.
+        const SA_RESTART = 0x1000_0000;
+        /// Do not block the signal while its handler runs.
+        const SA_NODEFER = 0x4000_0000;
+        /// Reset the handler to SIG_DFL when it is invoked.
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
+/// A set of signals, where bit `signo - 1` stands for signal `signo`.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignalSet(pub u64);
+
+impl SignalSet {
+    /// The signals that can be neither caught nor blocked.
+    pub const UNBLOCKABLE: Self = Self((1 << (SIGKILL - 1)) | (1 << (SIGSTOP - 1)));
+
+    /// A set with only `signo`.
+    pub const fn single(signo: u32) -> Self {
+        Self(1 << (signo - 1))
+    }
+
+    pub fn contains(&self, signo: u32) -> bool {
+        self.0 & (1 << (signo - 1)) != 0
+    }
+
+    pub fn add(&mut self, signo: u32) {
+        self.0 |= 1 << (signo - 1);
+    }
+
+    pub fn remove(&mut self, signo: u32) {
+        self.0 &= !(1 << (signo - 1));
+    }
+}
+
+/// The signal action used by `rt_sigaction`, in the layout of the kernel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigAction {
+    /// The handler, or SIG_DFL or SIG_IGN.
+    pub handler: usize,
+    pub flags: SigActionFlags,
+    /// The function that calls `rt_sigreturn` after the handler returns.
+    #[cfg(not(target_arch = "riscv64"))]
+    pub restorer: usize,
+    /// The signals blocked while the handler runs.
+    pub mask: SignalSet,
+}
+
+/// What happens to a signal with the default action.
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+}
+
+impl DefaultAction {
+    fn of(signo: u32) -> Self {
+        match signo {
+            SIGCHLD | SIGCONT | SIGURG | SIGWINCH => Self::Ignore,
+            SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => Self::Stop,
+            _ => Self::Terminate,
+        }
+    }
+}
+
+/// The signal actions of a process, shared by processes created with
+/// `CLONE_SIGHAND`.
+#[derive(Clone)]
+pub struct SignalActions([SigAction; NSIG]);
+
+impl SignalActions {
+    pub fn new() -> Self {
+        Self([SigAction::default(); NSIG])
+    }
+
+    pub fn get(&self, signo: u32) -> &SigAction {
+        &self.0[signo as usize - 1]
+    }
+
+    pub fn get_mut(&mut self, signo: u32) -> &mut SigAction {
+        &mut self.0[signo as usize - 1]
+    }
+
+    /// Reset the handlers to SIG_DFL for `execve`, keeping the ignored
+    /// signals ignored.
+    pub fn reset_on_exec(&mut self) {
+        for action in self.0.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::default();
+            }
+        }
+    }
+
+    /// Whether the signal is ignored, either explicitly or by default.
+    pub fn is_ignored(&self, signo: u32) -> bool {
+        match self.get(signo).handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(DefaultAction::of(signo), DefaultAction::Ignore),
+            _ => false,
+        }
+    }
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `siginfo_t`, with the fields used by this kernel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    _pad: i32,
+    /// The union of the fields that depend on the signal, such as `si_pid`
+    /// and `si_uid`, or `si_addr`.
+    fields: [u32; 28],
+}
+
+impl SigInfo {
+    pub fn new(signo: u32, code: i32) -> Self {
+        Self {
+            signo: signo as _,
+            errno: 0,
+            code,
+            _pad: 0,
+            fields: [0; 28],
+        }
+    }
+
+    /// A signal sent by the process `pid`.
+    pub fn from_process(signo: u32, code: i32, pid: u64) -> Self {
+        let mut info = Self::new(signo, code);
+        info.fields[0] = pid as u32;
+        info
+    }
+
+    /// Set `si_status` of SIGCHLD sent by the process, after
+    /// [`SigInfo::from_process`].
+    pub fn set_status(&mut self, status: i32) {
+        // `si_pid` and `si_uid` come first.
+        self.fields[2] = status as u32;
+    }
+
+    /// A signal raised by a fault at `addr`.
+    pub fn from_fault(signo: u32, code: i32, addr: usize) -> Self {
+        let mut info = Self::new(signo, code);
+        info.fields[0] = addr as u32;
+        info.fields[1] = (addr as u64 >> 32) as u32;
+        info
+    }
+
+    fn signo(&self) -> u32 {
+        self.signo as u32
+    }
+}
+
+/// The signals pending on a thread or a process.
+///
+/// The set of pending signals can be read without locking, e.g. in the
+/// conditions of wait queues.
+#[derive(Default)]
+pub struct PendingSignals {
+    set: AtomicU64,
+    /// Standard signals are queued at most once, while real-time signals
+    /// are queued every time they are sent.
+    queue: Mutex<VecDeque<SigInfo>>,
+}
+
+impl PendingSignals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The set of pending signals.
+    pub fn set(&self) -> SignalSet {
+        SignalSet(self.set.load(Ordering::Acquire))
+    }
+
+    /// Add a signal. Returns `false` if it is a standard signal that is
+    /// already pending.
+    pub fn push(&self, info: SigInfo) -> bool {
+        let signo = info.signo();
+        let mut queue = self.queue.lock();
+        if signo < 32 && self.set().contains(signo) {
+            return false;
+        }
+        queue.push_back(info);
+        self.set
+            .fetch_or(SignalSet::single(signo).0, Ordering::AcqRel);
+        true
+    }
+
+    /// Take the pending signal with the lowest number in `set`.
+    pub fn pop(&self, set: SignalSet) -> Option<SigInfo> {
+        let mut queue = self.queue.lock();
+        let ready = self.set().0 & set.0;
+        if ready == 0 {
+            return None;
+        }
+        let signo = ready.trailing_zeros() + 1;
+        let idx = queue.iter().position(|info| info.signo() == signo)?;
+        let info = queue.remove(idx)?;
+        if !queue.iter().any(|info| info.signo() == signo) {
+            self.set
+                .fetch_and(!SignalSet::single(signo).0, Ordering::AcqRel);
+        }
+        Some(info)
+    }
+
+    /// Remove all pending instances of `signo`.
+    pub fn discard(&self, signo: u32) {
+        let mut queue = self.queue.lock();
+        queue.retain(|info| info.signo() != signo);
+        self.set
+            .fetch_and(!SignalSet::single(signo).0, Ordering::AcqRel);
+    }
+}
+
+/// `stack_t`, describing the alternate signal stack.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct SignalStack {
+    sp: usize,
+    flags: i32,
+    size: usize,
+}
+
+/// `struct sigcontext` of x86_64, the user registers saved in a signal
+/// frame.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct MContext {
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rdi: u64,
+    rsi: u64,
+    rbp: u64,
+    rbx: u64,
+    rdx: u64,
+    rax: u64,
+    rcx: u64,
+    rsp: u64,
+    rip: u64,
+    eflags: u64,
+    /// `cs`, `gs`, `fs` and `ss`, 16 bits each.
+    segments: u64,
+    err: u64,
+    trapno: u64,
+    oldmask: u64,
+    cr2: u64,
+    /// The address of the saved [`FpState`] in the signal frame.
+    fpstate: u64,
+    __reserved1: [u64; 8],
+}
+
+#[cfg(target_arch = "x86_64")]
+impl MContext {
+    /// The flags that user space may change by `rt_sigreturn`, i.e.
+    /// `FIX_EFLAGS` of Linux.
+    const USER_EFLAGS: u64 = 0x0005_0dd5;
+
+    fn new(tf: &TrapFrame) -> Self {
+        Self {
+            r8: tf.r8,
+            r9: tf.r9,
+            r10: tf.r10,
+            r11: tf.r11,
+            r12: tf.r12,
+            r13: tf.r13,
+            r14: tf.r14,
+            r15: tf.r15,
+            rdi: tf.rdi,
+            rsi: tf.rsi,
+            rbp: tf.rbp,
+            rbx: tf.rbx,
+            rdx: tf.rdx,
+            rax: tf.rax,
+            rcx: tf.rcx,
+            rsp: tf.rsp,
+            rip: tf.rip,
+            eflags: tf.rflags,
+            segments: tf.cs | (tf.ss << 48),
+            err: tf.error_code,
+            trapno: tf.vector,
+            ..Default::default()
+        }
+    }
+
+    /// Restore the registers into `tf`, which keeps its segments and
+    /// privileged flags.
+    fn restore(&self, tf: &mut TrapFrame) {
+        tf.r8 = self.r8;
+        tf.r9 = self.r9;
+        tf.r10 = self.r10;
+        tf.r11 = self.r11;
+        tf.r12 = self.r12;
+        tf.r13 = self.r13;
+        tf.r14 = self.r14;
+        tf.r15 = self.r15;
+        tf.rdi = self.rdi;
+        tf.rsi = self.rsi;
+        tf.rbp = self.rbp;
+        tf.rbx = self.rbx;
+        tf.rdx = self.rdx;
+        tf.rax = self.rax;
+        tf.rcx = self.rcx;
+        tf.rsp = self.rsp;
+        tf.rip = self.rip;
+        tf.rflags = (tf.rflags & !Self::USER_EFLAGS) | (self.eflags & Self::USER_EFLAGS);
+    }
+}
+
+/// The x87 FPU and SSE state saved by `fxsave64`, i.e. `struct _fpstate_64`
+/// of Linux.
+///
+/// The kernel does not use these registers, so they still hold the state of
+/// the user code while it runs.
+#[cfg(target_arch = "x86_64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct FpState([u8; 512]);
+
+#[cfg(target_arch = "x86_64")]
+impl FpState {
+    /// The offset of `MXCSR` in the state.
+    const MXCSR_OFFSET: usize = 24;
+    /// The offset of `MXCSR_MASK`, the writable bits of `MXCSR`.
+    const MXCSR_MASK_OFFSET: usize = 28;
+    /// The writable bits of `MXCSR` if the CPU reports none.
+    const DEFAULT_MXCSR_MASK: u32 = 0xffbf;
+
+    fn word(&self, offset: usize) -> u32 {
+        u32::from_ne_bytes(self.0[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Save the FP/SIMD registers of the current CPU.
+    fn save() -> Self {
+        let mut state = Self([0; 512]);
+        // SAFETY: The buffer is 512 bytes aligned to 16, as `fxsave64` needs.
+        unsafe {
+            core::arch::asm!(
+                "fxsave64 [{}]",
+                in(reg) state.0.as_mut_ptr(),
+                options(nostack, preserves_flags),
+            )
+        };
+        state
+    }
+
+    /// Load the FP/SIMD registers from the state.
+    ///
+    /// The reserved bits of `MXCSR` are cleared, since a forged state would
+    /// make `fxrstor64` fault in the kernel otherwise.
+    fn restore(&self) {
+        let mut state = *self;
+        let mask = match Self::save().word(Self::MXCSR_MASK_OFFSET) {
+            0 => Self::DEFAULT_MXCSR_MASK,
+            mask => mask,
+        };
+        let mxcsr = state.word(Self::MXCSR_OFFSET) & mask;
+        state.0[Self::MXCSR_OFFSET..Self::MXCSR_OFFSET + 4].copy_from_slice(&mxcsr.to_ne_bytes());
+        // SAFETY: The buffer is aligned as `fxrstor64` needs, and `MXCSR`
+        // is valid.
+        unsafe {
+            core::arch::asm!(
+                "fxrstor64 [{}]",
+                in(reg) state.0.as_ptr(),
+                options(nostack, preserves_flags),
+            )
+        };
+    }
+}
+
+/// `struct sigcontext` of riscv64, the user registers saved in a signal
+/// frame.
+#[cfg(target_arch = "riscv64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    pc: usize,
+    /// `ra` to `t6`, i.e. `x1` to `x31`.
+    regs: [usize; 31],
+    fpregs: FpState,
+}
+
+#[cfg(target_arch = "riscv64")]
+impl MContext {
+    fn new(tf: &TrapFrame) -> Self {
+        // SAFETY: `GeneralRegisters` is `repr(C)` with 32 `usize` fields,
+        // `zero` to `t6`.
+        let regs: [usize; 32] = unsafe { core::mem::transmute(tf.regs) };
+        let mut mcontext = Self {
+            pc: tf.sepc,
+            regs: [0; 31],
+            fpregs: FpState::save(),
+        };
+        mcontext.regs.copy_from_slice(&regs[1..]);
+        mcontext
+    }
+
+    /// Restore the registers into `tf`, which keeps its `sstatus` except
+    /// that the FP state becomes dirty if it is restored.
+    fn restore(&self, tf: &mut TrapFrame) {
+        let mut regs = [0; 32];
+        regs[1..].copy_from_slice(&self.regs);
+        // SAFETY: See `new`.
+        tf.regs = unsafe { core::mem::transmute::<[usize; 32], _>(regs) };
+        tf.sepc = self.pc;
+        if self.fpregs.restore() {
+            tf.sstatus |= FpState::SSTATUS_FS;
+        }
+    }
+}
+
+/// `struct __riscv_d_ext_state` of Linux in the room of the Q extension,
+/// the FP state saved in a signal frame.
+///
+/// The FP registers of the user code are only accessed if its `sstatus.FS`
+/// is not off, which the kernel runs with until it returns to user space.
+#[cfg(target_arch = "riscv64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct FpState {
+    f: [u64; 32],
+    fcsr: u32,
+    __reserved: [u32; 67],
+}
+
+#[cfg(target_arch = "riscv64")]
+impl FpState {
+    /// The bits of `FS` in `sstatus`, which are all clear if the FP unit is
+    /// off, and all set if the FP state is dirty.
+    const SSTATUS_FS: usize = 0x6000;
+
+    /// Whether the FP unit is enabled for the user code.
+    fn is_enabled() -> bool {
+        let sstatus: usize;
+        // SAFETY: Reading `sstatus` has no side effects.
+        unsafe { core::arch::asm!("csrr {}, sstatus", out(reg) sstatus) };
+        sstatus & Self::SSTATUS_FS != 0
+    }
+
+    /// Save the FP registers of the current CPU, or zeros if the FP unit is
+    /// off.
+    fn save() -> Self {
+        let mut state = Self {
+            f: [0; 32],
+            fcsr: 0,
+            __reserved: [0; 67],
+        };
+        if !Self::is_enabled() {
+            return state;
+        }
+        let fcsr: usize;
+        // SAFETY: `f` has room for the 32 registers.
+        unsafe {
+            core::arch::asm!(
+                "fsd f0, 0({0})",
+                "fsd f1, 8({0})",
+                "fsd f2, 16({0})",
+                "fsd f3, 24({0})",
+                "fsd f4, 32({0})",
+                "fsd f5, 40({0})",
+                "fsd f6, 48({0})",
+                "fsd f7, 56({0})",
+                "fsd f8, 64({0})",
+                "fsd f9, 72({0})",
+                "fsd f10, 80({0})",
+                "fsd f11, 88({0})",
+                "fsd f12, 96({0})",
+                "fsd f13, 104({0})",
+                "fsd f14, 112({0})",
+                "fsd f15, 120({0})",
+                "fsd f16, 128({0})",
+                "fsd f17, 136({0})",
+                "fsd f18, 144({0})",
+                "fsd f19, 152({0})",
+                "fsd f20, 160({0})",
+                "fsd f21, 168({0})",
+                "fsd f22, 176({0})",
+                "fsd f23, 184({0})",
+                "fsd f24, 192({0})",
+                "fsd f25, 200({0})",
+                "fsd f26, 208({0})",
+                "fsd f27, 216({0})",
+                "fsd f28, 224({0})",
+                "fsd f29, 232({0})",
+                "fsd f30, 240({0})",
+                "fsd f31, 248({0})",
+                "frcsr {1}",
+                in(reg) state.f.as_mut_ptr(),
+                out(reg) fcsr,
+                options(nostack),
+            )
+        };
+        state.fcsr = fcsr as u32;
+        state
+    }
+
+    /// Load the FP registers from the state. Returns `false` if the FP unit
+    /// is off, so nothing is loaded.
+    fn restore(&self) -> bool {
+        if !Self::is_enabled() {
+            return false;
+        }
+        // SAFETY: `f` holds the 32 registers, and `fscsr` ignores the
+        // reserved bits.
+        unsafe {
+            core::arch::asm!(
+                "fld f0, 0({0})",
+                "fld f1, 8({0})",
+                "fld f2, 16({0})",
+                "fld f3, 24({0})",
+                "fld f4, 32({0})",
+                "fld f5, 40({0})",
+                "fld f6, 48({0})",
+                "fld f7, 56({0})",
+                "fld f8, 64({0})",
+                "fld f9, 72({0})",
+                "fld f10, 80({0})",
+                "fld f11, 88({0})",
+                "fld f12, 96({0})",
+                "fld f13, 104({0})",
+                "fld f14, 112({0})",
+                "fld f15, 120({0})",
+                "fld f16, 128({0})",
+                "fld f17, 136({0})",
+                "fld f18, 144({0})",
+                "fld f19, 152({0})",
+                "fld f20, 160({0})",
+                "fld f21, 168({0})",
+                "fld f22, 176({0})",
+                "fld f23, 184({0})",
+                "fld f24, 192({0})",
+                "fld f25, 200({0})",
+                "fld f26, 208({0})",
+                "fld f27, 216({0})",
+                "fld f28, 224({0})",
+                "fld f29, 232({0})",
+                "fld f30, 240({0})",
+                "fld f31, 248({0})",
+                "fscsr {1}",
+                in(reg) self.f.as_ptr(),
+                in(reg) self.fcsr as usize,
+                options(nostack),
+            )
+        };
+        true
+    }
+}
+
+/// `struct sigcontext` of aarch64, the user registers saved in a signal
+/// frame.
+#[cfg(target_arch = "aarch64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    fault_address: u64,
+    regs: [u64; 31],
+    sp: u64,
+    pc: u64,
+    pstate: u64,
+    /// The first record of the extra state, which is the only one saved by
+    /// this kernel.
+    fpsimd: FpState,
+    /// The rest of the records, which end with an empty one.
+    __reserved: [u8; 4096 - size_of::<FpState>()],
+}
+
+#[cfg(target_arch = "aarch64")]
+impl MContext {
+    /// The condition flags, which user space may change by `rt_sigreturn`.
+    const USER_PSTATE: u64 = 0xf000_0000;
+
+    fn new(tf: &TrapFrame) -> Self {
+        Self {
+            fault_address: 0,
+            regs: tf.r,
+            sp: tf.usp,
+            pc: tf.elr,
+            pstate: tf.spsr,
+            fpsimd: FpState::save(),
+            __reserved: [0; 4096 - size_of::<FpState>()],
+        }
+    }
+
+    /// Restore the registers into `tf`, which keeps the exception level and
+    /// the interrupt masks in its `spsr`.
+    fn restore(&self, tf: &mut TrapFrame) {
+        tf.r = self.regs;
+        tf.usp = self.sp;
+        tf.elr = self.pc;
+        tf.spsr = (tf.spsr & !Self::USER_PSTATE) | (self.pstate & Self::USER_PSTATE);
+        if self.fpsimd.is_valid() {
+            self.fpsimd.restore();
+        }
+    }
+}
+
+/// `struct fpsimd_context` of Linux, the record of the FP/SIMD state in a
+/// signal frame.
+///
+/// The kernel does not use these registers, so they still hold the state of
+/// the user code while it runs.
+#[cfg(target_arch = "aarch64")]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct FpState {
+    magic: u32,
+    size: u32,
+    fpsr: u32,
+    fpcr: u32,
+    vregs: [u128; 32],
+}
+
+#[cfg(target_arch = "aarch64")]
+impl FpState {
+    /// The magic number of the record, `FPSIMD_MAGIC`.
+    const MAGIC: u32 = 0x4650_8001;
+
+    /// Whether the record has the magic number and size of this state.
+    fn is_valid(&self) -> bool {
+        self.magic == Self::MAGIC && self.size as usize == size_of::<Self>()
+    }
+
+    /// Save the FP/SIMD registers of the current CPU.
+    fn save() -> Self {
+        let mut state = Self {
+            magic: Self::MAGIC,
+            size: size_of::<Self>() as u32,
+            fpsr: 0,
+            fpcr: 0,
+            vregs: [0; 32],
+        };
+        let (fpsr, fpcr): (u64, u64);
+        // SAFETY: `vregs` has room for the 32 registers.
+        unsafe {
+            core::arch::asm!(
+                ".arch_extension fp",
+                ".arch_extension simd",
+                "stp q0, q1, [{0}, #0]",
+                "stp q2, q3, [{0}, #32]",
+                "stp q4, q5, [{0}, #64]",
+                "stp q6, q7, [{0}, #96]",
+                "stp q8, q9, [{0}, #128]",
+                "stp q10, q11, [{0}, #160]",
+                "stp q12, q13, [{0}, #192]",
+                "stp q14, q15, [{0}, #224]",
+                "stp q16, q17, [{0}, #256]",
+                "stp q18, q19, [{0}, #288]",
+                "stp q20, q21, [{0}, #320]",
+                "stp q22, q23, [{0}, #352]",
+                "stp q24, q25, [{0}, #384]",
+                "stp q26, q27, [{0}, #416]",
+                "stp q28, q29, [{0}, #448]",
+                "stp q30, q31, [{0}, #480]",
+                "mrs {1}, fpsr",
+                "mrs {2}, fpcr",
+                in(reg) state.vregs.as_mut_ptr(),
+                out(reg) fpsr,
+                out(reg) fpcr,
+                options(nostack, preserves_flags),
+            )
+        };
+        state.fpsr = fpsr as u32;
+        state.fpcr = fpcr as u32;
+        state
+    }
+
+    /// Load the FP/SIMD registers from the state.
+    fn restore(&self) {
+        // SAFETY: `vregs` holds the 32 registers.
+        unsafe {
+            core::arch::asm!(
+                ".arch_extension fp",
+                ".arch_extension simd",
+                "ldp q0, q1, [{0}, #0]",
+                "ldp q2, q3, [{0}, #32]",
+                "ldp q4, q5, [{0}, #64]",
+                "ldp q6, q7, [{0}, #96]",
+                "ldp q8, q9, [{0}, #128]",
+                "ldp q10, q11, [{0}, #160]",
+                "ldp q12, q13, [{0}, #192]",
+                "ldp q14, q15, [{0}, #224]",
+                "ldp q16, q17, [{0}, #256]",
+                "ldp q18, q19, [{0}, #288]",
+                "ldp q20, q21, [{0}, #320]",
+                "ldp q22, q23, [{0}, #352]",
+                "ldp q24, q25, [{0}, #384]",
+                "ldp q26, q27, [{0}, #416]",
+                "ldp q28, q29, [{0}, #448]",
+                "ldp q30, q31, [{0}, #480]",
+                "msr fpsr, {1}",
+                "msr fpcr, {2}",
+                in(reg) self.vregs.as_ptr(),
+                in(reg) self.fpsr as u64,
+                in(reg) self.fpcr as u64,
+                options(nostack, preserves_flags),
+            )
+        };
+    }
+}
+
+/// `struct ucontext` of x86_64.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UContext {
+    flags: usize,
+    link: usize,
+    stack: SignalStack,
+    mcontext: MContext,
+    sigmask: SignalSet,
+}
+
+/// `struct ucontext` of riscv64 and aarch64, where the signal mask has room
+/// for 1024 signals before the registers.
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UContext {
+    flags: usize,
+    link: usize,
+    stack: SignalStack,
+    sigmask: SignalSet,
+    __unused: [u8; 1024 / 8 - size_of::<SignalSet>()],
+    mcontext: MContext,
+}
+
+impl UContext {
+    /// The context of the interrupted user code `tf`, with the signal mask
+    /// to restore.
+    fn new(tf: &TrapFrame, sigmask: SignalSet) -> Self {
+        Self {
+            flags: 0,
+            link: 0,
+            stack: SignalStack::default(),
+            mcontext: MContext::new(tf),
+            sigmask,
+            #[cfg(not(target_arch = "x86_64"))]
+            __unused: [0; 1024 / 8 - size_of::<SignalSet>()],
+        }
+    }
+}
+
+/// The data pushed onto the user stack when a signal handler is invoked.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalFrame {
+    info: SigInfo,
+    ucontext: UContext,
+    /// The FP/SIMD state, which `ucontext` points to.
+    #[cfg(target_arch = "x86_64")]
+    fpstate: FpState,
+}
+
+impl SignalFrame {
+    /// The frame for a handler of `info`, which interrupted the user code
+    /// `tf` with the signal mask `sigmask`.
+    fn new(info: SigInfo, tf: &TrapFrame, sigmask: SignalSet) -> Self {
+        Self {
+            info,
+            ucontext: UContext::new(tf, sigmask),
+            #[cfg(target_arch = "x86_64")]
+            fpstate: FpState::save(),
+        }
+    }
+
+    /// Point the context to the FP/SIMD state, for the frame placed at
+    /// `addr`.
+    #[cfg(target_arch = "x86_64")]
+    fn link_fpstate(&mut self, addr: usize) {
+        self.ucontext.mcontext.fpstate = (addr + core::mem::offset_of!(Self, fpstate)) as u64;
+    }
+
+    /// Point the context to the FP/SIMD state, for the frame placed at
+    /// `addr`. The state is inside the context on this architecture.
+    #[cfg(not(target_arch = "x86_64"))]
+    fn link_fpstate(&mut self, _addr: usize) {}
+}
+
+/// `MINSIGSTKSZ` of the architecture.
+#[cfg(target_arch = "aarch64")]
+const ARCH_MIN_SIGNAL_STACK_SIZE: usize = 5120;
+/// `MINSIGSTKSZ` of the architecture.
+#[cfg(not(target_arch = "aarch64"))]
+const ARCH_MIN_SIGNAL_STACK_SIZE: usize = 2048;
+
+/// The minimum size of a stack for signal handlers, which holds at least
+/// the red zone and a [`SignalFrame`] aligned to 16 bytes.
+pub const MIN_SIGNAL_STACK_SIZE: usize = {
+    let frame_size = 128 + size_of::<SignalFrame>() + 16;
+    if frame_size > ARCH_MIN_SIGNAL_STACK_SIZE {
+        frame_size
+    } else {
+        ARCH_MIN_SIGNAL_STACK_SIZE
+    }
+};
+
+/// The code that calls `rt_sigreturn`, which the signal handlers return to
+/// unless `SA_RESTORER` is set.
+#[cfg(target_arch = "x86_64")]
+pub const SIGRETURN_TRAMPOLINE: &[u8] = &[
+    0xb8, 0x0f, 0x00, 0x00, 0x00, // mov eax, 15
+    0x0f, 0x05, // syscall
+];
+
+/// The code that calls `rt_sigreturn`, which the signal handlers return to
+/// unless `SA_RESTORER` is set.
+#[cfg(target_arch = "riscv64")]
+pub const SIGRETURN_TRAMPOLINE: &[u8] = &[
+    0x93, 0x08, 0xb0, 0x08, // li a7, 139
+    0x73, 0x00, 0x00, 0x00, // ecall
+];
+
+/// The code that calls `rt_sigreturn`, which the signal handlers return to
+/// unless `SA_RESTORER` is set.
+#[cfg(target_arch = "aarch64")]
+pub const SIGRETURN_TRAMPOLINE: &[u8] = &[
+    0x68, 0x11, 0x80, 0xd2, // mov x8, #139
+    0x01, 0x00, 0x00, 0xd4, // svc #0
+];
+
+/// Set the registers to call the signal handler with the three arguments,
+/// and to return to `restorer`. `sp` points to the signal frame.
+#[cfg(target_arch = "x86_64")]
+fn setup_handler_context(
+    uctx: &mut UspaceContext,
+    handler: usize,
+    args: [usize; 3],
+    restorer: usize,
+    sp: usize,
+) -> axerrno::LinuxResult {
+    // The return address is pushed as if the handler was called.
+    let sp = sp - size_of::<usize>();
+    UserPtr::<usize>::from(sp).write(restorer)?;
+    uctx.rdi = args[0] as _;
+    uctx.rsi = args[1] as _;
+    uctx.rdx = args[2] as _;
+    uctx.set_sp(sp);
+    uctx.set_ip(handler);
+    Ok(())
+}
+
+/// Set the registers to call the signal handler with the three arguments,
+/// and to return to `restorer`. `sp` points to the signal frame.
+#[cfg(target_arch = "riscv64")]
+fn setup_handler_context(
+    uctx: &mut UspaceContext,
+    handler: usize,
+    args: [usize; 3],
+    restorer: usize,
+    sp: usize,
+) -> axerrno::LinuxResult {
+    uctx.regs.a0 = args[0];
+    uctx.regs.a1 = args[1];
+    uctx.regs.a2 = args[2];
+    uctx.regs.ra = restorer;
+    uctx.set_sp(sp);
+    uctx.set_ip(handler);
+    Ok(())
+}
+
+/// Set the registers to call the signal handler with the three arguments,
+/// and to return to `restorer`. `sp` points to the signal frame.
+#[cfg(target_arch = "aarch64")]
+fn setup_handler_context(
+    uctx: &mut UspaceContext,
+    handler: usize,
+    args: [usize; 3],
+    restorer: usize,
+    sp: usize,
+) -> axerrno::LinuxResult {
+    uctx.r[0] = args[0] as _;
+    uctx.r[1] = args[1] as _;
+    uctx.r[2] = args[2] as _;
+    uctx.r[30] = restorer as _;
+    uctx.set_sp(sp);
+    uctx.set_ip(handler);
+    Ok(())
+}
+
+/// The address of the signal frame when the handler returns, which is the
+/// stack pointer when `rt_sigreturn` is called.
+#[cfg(target_arch = "x86_64")]
+fn frame_address(uctx: &UspaceContext) -> usize {
+    // The return address pushed in `setup_handler_context` has been popped.
+    uctx.get_sp()
+}
+
+/// The address of the signal frame when the handler returns, which is the
+/// stack pointer when `rt_sigreturn` is called.
+#[cfg(not(target_arch = "x86_64"))]
+fn frame_address(uctx: &UspaceContext) -> usize {
+    uctx.get_sp()
+}
+
+/// The restorer of an action, which falls back to the trampoline.
+#[cfg(not(target_arch = "riscv64"))]
+fn restorer_of(action: &SigAction) -> usize {
+    if action.flags.contains(SigActionFlags::SA_RESTORER) {
+        action.restorer
+    } else {
+        config::SIGNAL_TRAMPOLINE
+    }
+}
+
+/// The restorer of an action, which falls back to the trampoline.
+#[cfg(target_arch = "riscv64")]
+fn restorer_of(_action: &SigAction) -> usize {
+    config::SIGNAL_TRAMPOLINE
+}
+
+/// The trap frame saved when the current task entered the kernel from user
+/// space, which sits at the top of its kernel stack.
+pub fn user_trap_frame() -> &'static TrapFrame {
+    let kstack_top = current().kernel_stack_top().unwrap();
+    unsafe { &*((kstack_top.as_usize() - size_of::<TrapFrame>()) as *const TrapFrame) }
+}
+
+/// Wake up the threads of `process` blocked in the kernel, so that they can
+/// notice a new signal.
+fn interrupt_threads(process: &Process) {
+    for task in process.threads() {
+        task.task_ext().signal_wq.notify_all(false);
+    }
+    process.interrupt_waiters();
+}
+
+/// Apply the effects that sending `signo` to `process` has right away:
+/// SIGCONT resumes the stopped process and discards the pending stop
+/// signals, and stop signals discard the pending SIGCONT.
+fn prepare_signal(process: &Process, signo: u32) {
+    let discarded: &[u32] = if signo == SIGCONT {
+        process.resume();
+        &[SIGSTOP, SIGTSTP, SIGTTIN, SIGTTOU]
+    } else if matches!(DefaultAction::of(signo), DefaultAction::Stop) {
+        &[SIGCONT]
+    } else {
+        return;
+    };
+    for &signo in discarded {
+        process.pending_signals.discard(signo);
+        for task in process.threads() {
+            task.task_ext().pending_signals.discard(signo);
+        }
+    }
+}
+
+/// Send a signal to the thread `task`.
+pub fn send_signal_to_thread(task: &axtask::AxTaskRef, info: SigInfo) {
+    let ext = task.task_ext();
+    let signo = info.signo();
+    if signo == SIGKILL {
+        kill_process(&ext.process);
+        return;
+    }
+    prepare_signal(&ext.process, signo);
+    if ext.process.signal_actions.lock().is_ignored(signo) {
+        return;
+    }
+    if ext.pending_signals.push(info) {
+        ext.signal_wq.notify_all(false);
+        ext.process.interrupt_waiters();
+    }
+}
+
+/// Send a signal to the process, to be taken by any of its threads.
+pub fn send_signal_to_process(process: &Arc<Process>, info: SigInfo) {
+    let signo = info.signo();
+    if process.is_zombie() {
+        return;
+    }
+    if signo == SIGKILL {
+        kill_process(process);
+        return;
+    }
+    prepare_signal(process, signo);
+    if process.signal_actions.lock().is_ignored(signo) {
+        return;
+    }
+    if process.pending_signals.push(info) {
+        interrupt_threads(process);
+    }
+}
+
+/// Terminate the process by SIGKILL.
+fn kill_process(process: &Process) {
+    process.group_exit(wstatus_signaled(SIGKILL));
+    if core::ptr::eq(process, &*current().task_ext().process) {
+        exit_current(0);
+    }
+}
+
+/// Take a pending signal of the current thread in `set`, preferring the
+/// signals sent to the thread itself.
+pub fn take_signal(set: SignalSet) -> Option<SigInfo> {
+    let curr = current();
+    let ext = curr.task_ext();
+    let info = ext.pending_signals.pop(set);
+    info.or_else(|| ext.process.pending_signals.pop(set))
+}
+
+/// Deliver the pending signals of the current thread.
+///
+/// Signals with the default action terminate, stop or ignore the process.
+/// If a handler is to be invoked, the user context `uctx` is saved on the
+/// user stack and changed to run the handler, and `true` is returned.
+///
+/// If `restart` is `true`, `uctx` is the context at the entry of a syscall
+/// that returned [`ERESTARTSYS`]. The syscall is restarted after the handler
+/// if it has `SA_RESTART`, or fails with `EINTR` otherwise.
+fn deliver_signals(uctx: &mut UspaceContext, restart: bool) -> bool {
+    let curr = current();
+    let ext = curr.task_ext();
+    loop {
+        let mask = ext.signal_mask();
+        let Some(info) = take_signal(SignalSet(!mask.0)) else {
+            return false;
+        };
+        let signo = info.signo();
+        let action = *ext.process.signal_actions.lock().get(signo);
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL => match DefaultAction::of(signo) {
+                DefaultAction::Ignore => continue,
+                DefaultAction::Stop => {
+                    info!("{}: stopped by signal {}", curr.id_name(), signo);
+                    ext.process.stop(signo);
+                    ext.process.wait_while_stopped();
+                    exit_if_killed();
+                    continue;
+                }
+                DefaultAction::Terminate => {
+                    info!("{}: terminated by signal {}", curr.id_name(), signo);
+                    ext.process.group_exit(wstatus_signaled(signo));
+                    exit_current(0);
+                }
+            },
+            _ => {}
+        }
+
+        if restart {
+            if action.flags.contains(SigActionFlags::SA_RESTART) {
+                restart_syscall(uctx);
+            } else {
+                set_syscall_return(uctx, -LinuxError::EINTR.code() as isize);
+            }
+        }
+
+        // Skip the red zone of x86_64, and align the frame to 16 bytes.
+        let sp = (uctx.get_sp() - 128 - size_of::<SignalFrame>()) & !0xf;
+        let mut frame = SignalFrame::new(info, uctx, mask);
+        frame.link_fpstate(sp);
+        let frame_ptr = UserPtr::<SignalFrame>::from(sp);
+        let res = frame_ptr.write(frame).and_then(|_| {
+            setup_handler_context(
+                uctx,
+                action.handler,
+                [
+                    signo as usize,
+                    sp + core::mem::offset_of!(SignalFrame, info),
+                    sp + core::mem::offset_of!(SignalFrame, ucontext),
+                ],
+                restorer_of(&action),
+                sp,
+            )
+        });
+        if res.is_err() {
+            warn!(
+                "{}: failed to set up the frame of signal {}",
+                curr.id_name(),
+                signo
+            );
+            ext.process.group_exit(wstatus_signaled(SIGSEGV));
+            exit_current(0);
+        }
+
+        let mut new_mask = SignalSet(mask.0 | action.mask.0);
+        if !action.flags.contains(SigActionFlags::SA_NODEFER) {
+            new_mask.add(signo);
+        }
+        ext.set_signal_mask(new_mask);
+        if action.flags.contains(SigActionFlags::SA_RESETHAND) {
+            *ext.process.signal_actions.lock().get_mut(signo) = SigAction::default();
+        }
+        return true;
+    }
+}
+
+/// Return to user space with `uctx` from the current kernel stack.
+fn enter_user(uctx: &UspaceContext) -> ! {
+    let curr = current();
+    curr.task_ext().leave_kernel();
+    let kstack_top = curr.kernel_stack_top().unwrap();
+    unsafe { uctx.enter_uspace(kstack_top) }
+}
+
+/// Make the user context `uctx` at the entry of a syscall return `ret`
+/// from it.
+fn set_syscall_return(uctx: &mut UspaceContext, ret: isize) {
+    uctx.set_retval(ret as usize);
+    // The trap handler skips the `ecall` instruction after the syscall
+    // returns, which does not happen when entering user space directly.
+    #[cfg(target_arch = "riscv64")]
+    uctx.set_ip(uctx.get_ip() + 4);
+}
+
+/// Make the user context `uctx` at the entry of a syscall execute the
+/// syscall again. The registers of the arguments and the syscall number
+/// are still intact.
+#[cfg(not(target_arch = "riscv64"))]
+fn restart_syscall(uctx: &mut UspaceContext) {
+    // Point back to `syscall` or `svc #0`.
+    let len = if cfg!(target_arch = "x86_64") { 2 } else { 4 };
+    uctx.set_ip(uctx.get_ip() - len);
+}
+
+/// Make the user context `uctx` at the entry of a syscall execute the
+/// syscall again, which it does already: `sepc` still points to `ecall`.
+#[cfg(target_arch = "riscv64")]
+fn restart_syscall(_uctx: &mut UspaceContext) {}
+
+/// Deliver the pending signals before returning to user space with the
+/// trap frame `tf`, after waiting while the process is stopped.
+///
+/// `syscall_ret` is the return value if the kernel was entered by a syscall.
+/// The function does not return if a signal handler is invoked, or if the
+/// syscall returned [`ERESTARTSYS`].
+pub fn handle_signals(tf: &TrapFrame, syscall_ret: Option<isize>) {
+    let curr = current();
+    let ext = curr.task_ext();
+    if ext.process.is_stopped() {
+        ext.process.wait_while_stopped();
+        exit_if_killed();
+    }
+    let restart = syscall_ret == Some(-ERESTARTSYS);
+    if !restart && !ext.has_deliverable_signal() {
+        return;
+    }
+    let mut uctx = UspaceContext::from(tf);
+    if let Some(ret) = syscall_ret.filter(|_| !restart) {
+        set_syscall_return(&mut uctx, ret);
+    }
+    if deliver_signals(&mut uctx, restart) {
+        enter_user(&uctx);
+    }
+    if restart {
+        // No handler has run, so the syscall is restarted.
+        restart_syscall(&mut uctx);
+        enter_user(&uctx);
+    }
+}
+
+/// Raise a signal caused by a fault of the current thread in user space,
+/// e.g. a segmentation fault.
+///
+/// Like Linux, the signal cannot be blocked or ignored: the process is
+/// terminated unless there is a handler.
+///
+/// TODO: raise SIGILL, SIGFPE and SIGTRAP for illegal instructions,
+/// arithmetic faults and breakpoints too. `axhal::trap` only has hooks for
+/// `PAGE_FAULT`, `SYSCALL` and `IRQ`, and panics on the other exceptions
+/// from user space.
+pub fn raise_fault_signal(signo: u32, info: SigInfo) -> ! {
+    let curr = current();
+    let ext = curr.task_ext();
+    {
+        let mut actions = ext.process.signal_actions.lock();
+        if actions.get(signo).handler == SIG_IGN {
+            actions.get_mut(signo).handler = SIG_DFL;
+        }
+    }
+    let mut mask = ext.signal_mask();
+    if mask.contains(signo) {
+        mask.remove(signo);
+        ext.set_signal_mask(mask);
+    }
+    ext.pending_signals.push(info);
+    handle_signals(user_trap_frame(), None);
+    // The signal has been taken by the handler set up above, or terminated
+    // the process.
+    unreachable!();
+}
+
+/// Return from a signal handler, restoring the context saved in the signal
+/// frame.
+pub fn sigreturn(tf: &TrapFrame) -> ! {
+    let curr = current();
+    let ext = curr.task_ext();
+    let frame_ptr = UserPtr::<SignalFrame>::from(frame_address(&UspaceContext::from(tf)));
+    let Ok(frame) = frame_ptr.read() else {
+        warn!("{}: invalid signal frame", curr.id_name());
+        raise_fault_signal(SIGSEGV, SigInfo::new(SIGSEGV, SI_KERNEL));
+    };
+    let mut mask = frame.ucontext.sigmask;
+    mask.0 &= !SignalSet::UNBLOCKABLE.0;
+    ext.set_signal_mask(mask);
+    // Only the registers that user code may change are restored from user
+    // memory, so a forged frame cannot return to the kernel mode.
+    let mut uctx = UspaceContext::from(tf);
+    frame.ucontext.mcontext.restore(&mut uctx);
+    // The FP/SIMD state of x86_64 is where the frame points to, which user
+    // space may change.
+    #[cfg(target_arch = "x86_64")]
+    if frame.ucontext.mcontext.fpstate != 0 {
+        match UserPtr::<FpState>::from(frame.ucontext.mcontext.fpstate as usize).read() {
+            Ok(fpstate) => fpstate.restore(),
+            Err(_) => raise_fault_signal(SIGSEGV, SigInfo::new(SIGSEGV, SI_KERNEL)),
+        }
+    }
+    deliver_signals(&mut uctx, false);
+    enter_user(&uctx);
+}
@@ -1,6 +1,17 @@
-use core::ffi::c_void;
+use alloc::vec;
 
-use crate::syscall_body;
+use axerrno::LinuxError;
+use axtask::{current, TaskExtRef};
+
+use super::fd_ops::UMASK;
+use crate::{
+    fs::{
+        absolute_path, get_file, is_mount_point, lookup, lookup_link, lookup_parent, NodeType,
+        SeekFrom,
+    },
+    ptr::{UserCStr, UserPtr, UserSlice},
+    syscall_body,
+};
 
 /// The ioctl() system call manipulates the underlying device parameters
 /// of special files.
@@ -9,10 +20,235 @@ use crate::syscall_body;
 /// * `fd` - The file descriptor
 /// * `op` - The request code. It is of type unsigned long in glibc and BSD,
 /// and of type int in musl and other UNIX systems.
-/// * `argp` - The argument to the request. It is a pointer to a memory location
-pub(crate) fn sys_ioctl(_fd: i32, _op: usize, _argp: *mut c_void) -> i32 {
+/// * `arg` - The argument to the request, which is usually a pointer to a
+/// memory location
+pub(crate) fn sys_ioctl(fd: i32, op: usize, arg: usize) -> isize {
     syscall_body!(sys_ioctl, {
-        warn!("Unimplemented syscall: SYS_IOCTL");
+        debug!("sys_ioctl <= fd: {}, op: {:#x}, arg: {:#x}", fd, op, arg);
+        get_file(fd)?.ioctl(op as u32, arg)
+    })
+}
+
+/// The size of `struct linux_dirent64` without the name.
+const DIRENT64_HEADER_SIZE: usize = 19;
+
+/// The maximum number of bytes of records that `getdents64` returns at a
+/// time.
+const DIRENTS_MAX_SIZE: usize = 0x10000;
+
+/// Read the entries of the directory `fd` into `dirp` as `struct
+/// linux_dirent64` records, and return the number of bytes read, or 0 at
+/// the end of the directory.
+///
+/// # Arguments
+/// * `fd` - The directory
+/// * `dirp` - The buffer for the records
+/// * `count` - The size of the buffer
+pub(crate) fn sys_getdents64(fd: i32, dirp: UserPtr<u8>, count: usize) -> isize {
+    syscall_body!(sys_getdents64, {
+        let file = get_file(fd)?;
+        let dirp = UserSlice::<u8>::new(dirp.address(), count);
+        dirp.check_writable()?;
+        // The records are copied out at once, after the directory is read.
+        let mut buf = vec![0; count.min(DIRENTS_MAX_SIZE)];
+
+        let mut pos = file.seek(SeekFrom::Current(0)).unwrap_or(0);
+        let mut len = 0;
+        let mut too_small = false;
+        file.read_dir(&mut |entry| {
+            let name = entry.name.as_bytes();
+            let reclen = (DIRENT64_HEADER_SIZE + name.len() + 1).next_multiple_of(8);
+            let Some(record) = buf.get_mut(len..len + reclen) else {
+                too_small = len == 0;
+                return false;
+            };
+            pos += 1;
+            record[..8].copy_from_slice(&entry.ino.to_ne_bytes());
+            record[8..16].copy_from_slice(&pos.to_ne_bytes());
+            record[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+            record[18] = entry.node_type.dirent_type();
+            record[DIRENT64_HEADER_SIZE..][..name.len()].copy_from_slice(name);
+            record[DIRENT64_HEADER_SIZE + name.len()..].fill(0);
+            len += reclen;
+            true
+        })?;
+        if too_small {
+            return Err(LinuxError::EINVAL);
+        }
+        dirp.write(&buf[..len])?;
+        Ok(len)
+    })
+}
+
+/// Create the directory `path` relative to `dirfd`.
+///
+/// # Arguments
+/// * `dirfd` - The directory that a relative `path` starts from, or
+///   `AT_FDCWD` for the current directory
+/// * `path` - The path of the new directory
+/// * `mode` - The permission bits of the new directory
+pub(crate) fn sys_mkdirat(dirfd: i32, path: UserCStr, mode: u32) -> isize {
+    syscall_body!(sys_mkdirat, {
+        let path = absolute_path(dirfd, &path.read_path()?)?;
+        debug!("sys_mkdirat <= path: {:?}, mode: {:#o}", path, mode);
+        if lookup(&path).is_ok() {
+            return Err(LinuxError::EEXIST);
+        }
+        let (parent, name) = lookup_parent(&path)?;
+        parent.create(name, NodeType::Dir, mode & 0o7777 & !UMASK)?;
+        Ok(0)
+    })
+}
+
+/// Create the directory `path` relative to the current directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_mkdir(path: UserCStr, mode: u32) -> isize {
+    sys_mkdirat(crate::fs::AT_FDCWD, path, mode)
+}
+
+/// Remove a directory instead of a file in `unlinkat`.
+const AT_REMOVEDIR: u32 = 0x200;
+
+/// Remove the file `path` relative to `dirfd`, or the empty directory if
+/// `flags` contains `AT_REMOVEDIR`.
+pub(crate) fn sys_unlinkat(dirfd: i32, path: UserCStr, flags: u32) -> isize {
+    syscall_body!(sys_unlinkat, {
+        if flags & !AT_REMOVEDIR != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = absolute_path(dirfd, &path.read_path()?)?;
+        debug!("sys_unlinkat <= path: {:?}, flags: {:#x}", path, flags);
+        if is_mount_point(&path) {
+            return Err(LinuxError::EBUSY);
+        }
+        let (parent, name) = lookup_parent(&path)?;
+        let is_dir = parent.lookup(name)?.stat()?.node_type == NodeType::Dir;
+        match (is_dir, flags & AT_REMOVEDIR != 0) {
+            (false, true) => return Err(LinuxError::ENOTDIR),
+            (true, false) => return Err(LinuxError::EISDIR),
+            _ => {}
+        }
+        parent.unlink(name)?;
+        Ok(0)
+    })
+}
+
+/// Remove the file `path` relative to the current directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_unlink(path: UserCStr) -> isize {
+    sys_unlinkat(crate::fs::AT_FDCWD, path, 0)
+}
+
+/// Remove the empty directory `path` relative to the current directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_rmdir(path: UserCStr) -> isize {
+    sys_unlinkat(crate::fs::AT_FDCWD, path, AT_REMOVEDIR)
+}
+
+/// Fail with `EEXIST` instead of replacing an existing target in `renameat2`.
+const RENAME_NOREPLACE: u32 = 1;
+
+/// Move the file `oldpath` relative to `olddirfd` to `newpath` relative to
+/// `newdirfd`, replacing the existing target if any.
+///
+/// Only `RENAME_NOREPLACE` is supported in `flags`.
+pub(crate) fn sys_renameat2(
+    olddirfd: i32,
+    oldpath: UserCStr,
+    newdirfd: i32,
+    newpath: UserCStr,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_renameat2, {
+        if flags & !RENAME_NOREPLACE != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let old_path = absolute_path(olddirfd, &oldpath.read_path()?)?;
+        let new_path = absolute_path(newdirfd, &newpath.read_path()?)?;
+        debug!("sys_renameat2 <= {:?} -> {:?}", old_path, new_path);
+
+        let (old_parent, old_name) = lookup_parent(&old_path)?;
+        old_parent.lookup(old_name)?;
+        if old_path == new_path {
+            return Ok(0);
+        }
+        if is_mount_point(&old_path) || is_mount_point(&new_path) {
+            return Err(LinuxError::EBUSY);
+        }
+        if new_path.starts_with(&old_path) && new_path[old_path.len()..].starts_with('/') {
+            // A directory cannot be moved into itself.
+            return Err(LinuxError::EINVAL);
+        }
+        let (new_parent, new_name) = lookup_parent(&new_path)?;
+        if flags & RENAME_NOREPLACE != 0 && new_parent.lookup(new_name).is_ok() {
+            return Err(LinuxError::EEXIST);
+        }
+        old_parent.rename(old_name, &new_parent, new_name)?;
+        Ok(0)
+    })
+}
+
+/// Move the file `oldpath` to `newpath`, both relative to the current
+/// directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_rename(oldpath: UserCStr, newpath: UserCStr) -> isize {
+    sys_renameat2(
+        crate::fs::AT_FDCWD,
+        oldpath,
+        crate::fs::AT_FDCWD,
+        newpath,
+        0,
+    )
+}
+
+/// Copy the target of the symbolic link at `path` relative to `dirfd` into
+/// `buf` without a terminating NUL, and return its length.
+///
+/// The target is truncated if `bufsiz` is too small.
+pub(crate) fn sys_readlinkat(dirfd: i32, path: UserCStr, buf: UserPtr<u8>, bufsiz: isize) -> isize {
+    syscall_body!(sys_readlinkat, {
+        if bufsiz <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = absolute_path(dirfd, &path.read_path()?)?;
+        let target = lookup_link(&path)?.read_link()?;
+        let len = target.len().min(bufsiz as usize);
+        UserSlice::<u8>::new(buf.address(), len).write(&target.as_bytes()[..len])?;
+        Ok(len)
+    })
+}
+
+/// Read the target of the symbolic link at `path` relative to the current
+/// directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_readlink(path: UserCStr, buf: UserPtr<u8>, bufsiz: isize) -> isize {
+    sys_readlinkat(crate::fs::AT_FDCWD, path, buf, bufsiz)
+}
+
+/// Copy the absolute path of the current directory with the terminating NUL
+/// into `buf`, and return its length.
+///
+/// Returns `ERANGE` if `size` is too small.
+pub(crate) fn sys_getcwd(buf: UserPtr<u8>, size: usize) -> isize {
+    syscall_body!(sys_getcwd, {
+        let mut cwd = current().task_ext().process.cwd.lock().clone();
+        cwd.push('\0');
+        if cwd.len() > size {
+            return Err(LinuxError::ERANGE);
+        }
+        UserSlice::<u8>::new(buf.address(), cwd.len()).write(cwd.as_bytes())?;
+        Ok(cwd.len())
+    })
+}
+
+/// Change the current directory to `path`.
+pub(crate) fn sys_chdir(path: UserCStr) -> isize {
+    syscall_body!(sys_chdir, {
+        let path = absolute_path(crate::fs::AT_FDCWD, &path.read_path()?)?;
+        if lookup(&path)?.stat()?.node_type != NodeType::Dir {
+            return Err(LinuxError::ENOTDIR);
+        }
+        *current().task_ext().process.cwd.lock() = path;
         Ok(0)
     })
 }
@@ -0,0 +1,313 @@
+use alloc::sync::Arc;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    fs::{
+        absolute_path,
+        lock::{self, LockKind, RecordLock},
+        lookup_in_fs, lookup_parent, File, FileLike, NodeType, OpenFlags, SeekFrom, MAX_FDS,
+    },
+    ptr::{UserCStr, UserPtr},
+    syscall_body,
+};
+
+/// The permission bits masked out of the files created by `openat` and
+/// `mkdirat`.
+pub(super) const UMASK: u32 = 0o022;
+
+/// Open the file at `path` relative to `dirfd`, and return a new file
+/// descriptor.
+///
+/// # Arguments
+/// * `dirfd` - The directory that a relative `path` starts from, or
+///   `AT_FDCWD` for the current directory
+/// * `path` - The path of the file
+/// * `flags` - The access mode, with the creation and status flags
+/// * `mode` - The permission bits of the file if it is created
+pub(crate) fn sys_openat(dirfd: i32, path: UserCStr, flags: u32, mode: u32) -> isize {
+    syscall_body!(sys_openat, {
+        let path = absolute_path(dirfd, &path.read_path()?)?;
+        let flags = OpenFlags::from_bits_truncate(flags);
+        debug!("sys_openat <= path: {:?}, flags: {:?}", path, flags);
+
+        let (node, fs_id) = match lookup_in_fs(&path) {
+            Ok(_) if flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL) => {
+                return Err(LinuxError::EEXIST);
+            }
+            Ok(found) => found,
+            Err(LinuxError::ENOENT) if flags.contains(OpenFlags::O_CREAT) => {
+                let (parent, name) = lookup_parent(&path)?;
+                parent.create(name, NodeType::File, mode & 0o7777 & !UMASK)?;
+                // Look the new file up for the filesystem it is in.
+                lookup_in_fs(&path)?
+            }
+            Err(err) => return Err(err),
+        };
+        let node_type = node.stat()?.node_type;
+        if node_type == NodeType::Dir && flags.writable() {
+            return Err(LinuxError::EISDIR);
+        }
+        if node_type != NodeType::Dir && flags.contains(OpenFlags::O_DIRECTORY) {
+            return Err(LinuxError::ENOTDIR);
+        }
+        if node_type == NodeType::File && flags.contains(OpenFlags::O_TRUNC) && flags.writable() {
+            node.truncate(0)?;
+        }
+
+        let cloexec = flags.contains(OpenFlags::O_CLOEXEC);
+        let flags = flags - OpenFlags::O_CLOEXEC;
+        let file: Arc<dyn FileLike> = match node.open(flags) {
+            Some(file) => file,
+            None => Arc::new(File::new(node, path, fs_id, flags)),
+        };
+        current()
+            .task_ext()
+            .process
+            .fd_table
+            .lock()
+            .add(file, cloexec)
+    })
+}
+
+/// Open the file at `path` relative to the current directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_open(path: UserCStr, flags: u32, mode: u32) -> isize {
+    sys_openat(crate::fs::AT_FDCWD, path, flags, mode)
+}
+
+/// Close the file descriptor `fd`.
+///
+/// The record locks of the process on the file are released, even if other
+/// descriptors still refer to it.
+pub(crate) fn sys_close(fd: i32) -> isize {
+    syscall_body!(sys_close, {
+        let curr = current();
+        let process = &curr.task_ext().process;
+        process.fd_table.lock().close(fd, process.pid())?;
+        Ok(0)
+    })
+}
+
+/// Duplicate `fd` with the lowest free file descriptor, and return it.
+pub(crate) fn sys_dup(fd: i32) -> isize {
+    syscall_body!(sys_dup, {
+        let curr = current();
+        let mut fd_table = curr.task_ext().process.fd_table.lock();
+        let file = fd_table.get(fd)?;
+        fd_table.add(file, false)
+    })
+}
+
+/// Make `newfd` refer to the file of `oldfd`, closing the file that `newfd`
+/// referred to.
+///
+/// # Arguments
+/// * `oldfd` - The file descriptor to duplicate
+/// * `newfd` - The new file descriptor, which must differ from `oldfd`
+/// * `flags` - `O_CLOEXEC` for the new file descriptor
+pub(crate) fn sys_dup3(oldfd: i32, newfd: i32, flags: u32) -> isize {
+    syscall_body!(sys_dup3, {
+        let flags = OpenFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if !(flags - OpenFlags::O_CLOEXEC).is_empty() || oldfd == newfd {
+            return Err(LinuxError::EINVAL);
+        }
+        let cloexec = flags.contains(OpenFlags::O_CLOEXEC);
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let mut fd_table = process.fd_table.lock();
+        let file = fd_table.get(oldfd)?;
+        fd_table.set(newfd, file, cloexec, process.pid())?;
+        Ok(newfd)
+    })
+}
+
+/// Make `newfd` refer to the file of `oldfd` like `dup3`, but do nothing if
+/// they are equal.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_dup2(oldfd: i32, newfd: i32) -> isize {
+    if oldfd != newfd {
+        return sys_dup3(oldfd, newfd, 0);
+    }
+    syscall_body!(sys_dup2, {
+        crate::fs::get_file(oldfd)?;
+        Ok(newfd)
+    })
+}
+
+/// Commands for sys_fcntl
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/fcntl.h>
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u32)]
+enum FcntlCmd {
+    /// Duplicate the file descriptor.
+    DupFd = 0,
+    /// Get the file descriptor flags.
+    GetFd = 1,
+    /// Set the file descriptor flags.
+    SetFd = 2,
+    /// Get the access mode and the status flags.
+    GetFl = 3,
+    /// Set the status flags.
+    SetFl = 4,
+    /// Get the first lock that prevents the given one.
+    GetLk = 5,
+    /// Set or release a lock, failing on conflicts.
+    SetLk = 6,
+    /// Set or release a lock, waiting on conflicts.
+    SetLkW = 7,
+    /// Duplicate the file descriptor with the close-on-exec flag.
+    DupFdCloexec = 1030,
+}
+
+/// The close-on-exec flag of file descriptors.
+const FD_CLOEXEC: usize = 1;
+
+/// `l_type` of [`Flock`] for read locks.
+const F_RDLCK: i16 = 0;
+/// `l_type` of [`Flock`] for write locks.
+const F_WRLCK: i16 = 1;
+/// `l_type` of [`Flock`] for releasing locks.
+const F_UNLCK: i16 = 2;
+
+/// `struct flock`, which describes a record lock.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Flock {
+    l_type: i16,
+    l_whence: i16,
+    l_start: i64,
+    l_len: i64,
+    l_pid: i32,
+}
+
+/// The range `[start, end)` locked by `flock` in `file`.
+fn lock_range(file: &dyn FileLike, flock: &Flock) -> LinuxResult<(u64, u64)> {
+    let base = match flock.l_whence {
+        0 => 0,
+        1 => file.seek(SeekFrom::Current(0))? as i64,
+        2 => file.stat()?.size as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let start = base
+        .checked_add(flock.l_start)
+        .ok_or(LinuxError::EOVERFLOW)?;
+    // A length of 0 extends the range to the end of the file, and a negative
+    // length ends the range at `start`.
+    let (start, end) = match flock.l_len {
+        0 => (start, None),
+        len if len > 0 => (start, start.checked_add(len)),
+        len => (
+            start.checked_add(len).ok_or(LinuxError::EOVERFLOW)?,
+            Some(start),
+        ),
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let end = match end {
+        Some(end) => end as u64,
+        None if flock.l_len == 0 => u64::MAX,
+        None => return Err(LinuxError::EOVERFLOW),
+    };
+    Ok((start as u64, end))
+}
+
+/// Handle the lock commands of `fcntl`.
+fn fcntl_lock(file: &dyn FileLike, cmd: FcntlCmd, flock_ptr: UserPtr<Flock>) -> LinuxResult {
+    let mut flock = flock_ptr.read()?;
+    let (start, end) = lock_range(file, &flock)?;
+    let key = file.lock_key()?;
+    let pid = current().task_ext().process.pid();
+    let kind = match flock.l_type {
+        F_RDLCK => LockKind::Read,
+        F_WRLCK => LockKind::Write,
+        F_UNLCK if cmd != FcntlCmd::GetLk => {
+            lock::unlock(key, pid, start, end);
+            return Ok(());
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let record = RecordLock {
+        pid,
+        start,
+        end,
+        kind,
+    };
+    if cmd == FcntlCmd::GetLk {
+        match lock::find_conflict(key, &record) {
+            Some(other) => {
+                flock.l_type = match other.kind {
+                    LockKind::Read => F_RDLCK,
+                    LockKind::Write => F_WRLCK,
+                };
+                flock.l_whence = 0;
+                flock.l_start = other.start as i64;
+                flock.l_len = if other.end == u64::MAX {
+                    0
+                } else {
+                    (other.end - other.start) as i64
+                };
+                flock.l_pid = other.pid as i32;
+            }
+            None => flock.l_type = F_UNLCK,
+        }
+        return flock_ptr.write(flock);
+    }
+    let flags = file.status_flags();
+    if (kind == LockKind::Read && !flags.readable())
+        || (kind == LockKind::Write && !flags.writable())
+    {
+        return Err(LinuxError::EBADF);
+    }
+    lock::set_lock(key, record, cmd == FcntlCmd::SetLkW)
+}
+
+/// Manipulate the file descriptor `fd`.
+///
+/// # Arguments
+/// * `fd` - The file descriptor
+/// * `cmd` - The command, such as `F_DUPFD` or `F_SETFL`
+/// * `arg` - The argument of the command, which is an integer or a pointer
+///   to `struct flock`
+pub(crate) fn sys_fcntl(fd: i32, cmd: u32, arg: usize) -> isize {
+    syscall_body!(sys_fcntl, {
+        let cmd = FcntlCmd::try_from(cmd).map_err(|_| LinuxError::EINVAL)?;
+        debug!("sys_fcntl <= fd: {}, cmd: {:?}, arg: {:#x}", fd, cmd, arg);
+        let curr = current();
+        let mut fd_table = curr.task_ext().process.fd_table.lock();
+        let file = fd_table.get(fd)?;
+        match cmd {
+            FcntlCmd::DupFd | FcntlCmd::DupFdCloexec => {
+                if arg >= MAX_FDS {
+                    return Err(LinuxError::EINVAL);
+                }
+                let fd = fd_table.add_from(arg, file, cmd == FcntlCmd::DupFdCloexec)?;
+                Ok(fd as isize)
+            }
+            FcntlCmd::GetFd => Ok(if fd_table.cloexec(fd)? {
+                FD_CLOEXEC as isize
+            } else {
+                0
+            }),
+            FcntlCmd::SetFd => {
+                fd_table.set_cloexec(fd, arg & FD_CLOEXEC != 0)?;
+                Ok(0)
+            }
+            FcntlCmd::GetFl => Ok(file.status_flags().bits() as isize),
+            FcntlCmd::SetFl => {
+                file.set_status_flags(OpenFlags::from_bits_truncate(arg as u32));
+                Ok(0)
+            }
+            FcntlCmd::GetLk | FcntlCmd::SetLk | FcntlCmd::SetLkW => {
+                // The lock may be waited for without holding the table.
+                drop(fd_table);
+                fcntl_lock(file.as_ref(), cmd, arg.into())?;
+                Ok(0)
+            }
+        }
+    })
+}
@@ -1,15 +1,185 @@
-use core::ffi::c_void;
+use alloc::vec;
 
-use arceos_posix_api as api;
+use arceos_posix_api::ctypes::iovec;
+use axerrno::{LinuxError, LinuxResult};
 
-pub(crate) fn sys_read(fd: i32, buf: *mut c_void, count: usize) -> isize {
-    api::sys_read(fd, buf, count)
+use crate::{
+    fs::{get_file, FileLike, NodeType, SeekFrom},
+    ptr::{UserPtr, UserSlice},
+    syscall_body,
+};
+
+/// The maximum number of bytes copied between user memory and a kernel
+/// buffer at a time.
+const IO_CHUNK_SIZE: usize = 0x10000;
+
+/// Read into the user buffer `buf` with `read`, which is given the offset
+/// in `buf` and a kernel buffer, and return the number of bytes read.
+///
+/// `read` may block, so the data is copied out after each call. Only
+/// regular files are read in more than one chunk, since streams would block
+/// for the next one. The bytes read so far are returned on errors.
+fn read_to_user(
+    file: &dyn FileLike,
+    buf: &UserSlice<u8>,
+    mut read: impl FnMut(usize, &mut [u8]) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    if buf.is_empty() {
+        return read(0, &mut []);
+    }
+    buf.check_writable()?;
+    let regular = file
+        .stat()
+        .is_ok_and(|stat| stat.node_type == NodeType::File);
+    let mut kbuf = vec![0; buf.len().min(IO_CHUNK_SIZE)];
+    let mut total = 0;
+    while total < buf.len() {
+        let len = (buf.len() - total).min(kbuf.len());
+        let res = read(total, &mut kbuf[..len])
+            .and_then(|n| buf.slice(total, n).write(&kbuf[..n]).map(|_| n));
+        let n = match res {
+            Ok(n) => n,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        };
+        total += n;
+        if n < len || !regular {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Write the user buffer `buf` with `write`, which is given the offset in
+/// `buf` and a kernel buffer, and return the number of bytes written.
+///
+/// The data is copied in before each call, since `write` may block. The
+/// bytes written so far are returned on errors.
+fn write_from_user(
+    buf: &UserSlice<u8>,
+    mut write: impl FnMut(usize, &[u8]) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    if buf.is_empty() {
+        return write(0, &[]);
+    }
+    let mut total = 0;
+    while total < buf.len() {
+        let len = (buf.len() - total).min(IO_CHUNK_SIZE);
+        let res = buf
+            .slice(total, len)
+            .read_vec()
+            .and_then(|data| write(total, &data));
+        let n = match res {
+            Ok(n) => n,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        };
+        total += n;
+        if n < len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+pub(crate) fn sys_read(fd: i32, buf: UserPtr<u8>, count: usize) -> isize {
+    syscall_body!(sys_read, {
+        let file = get_file(fd)?;
+        let buf = UserSlice::<u8>::new(buf.address(), count);
+        read_to_user(file.as_ref(), &buf, |_, kbuf| file.read(kbuf))
+    })
+}
+
+pub(crate) fn sys_write(fd: i32, buf: UserPtr<u8>, count: usize) -> isize {
+    syscall_body!(sys_write, {
+        let file = get_file(fd)?;
+        let buf = UserSlice::<u8>::new(buf.address(), count);
+        write_from_user(&buf, |_, kbuf| file.write(kbuf))
+    })
+}
+
+/// The I/O vectors of `readv` and `writev`.
+fn iovecs(iov: UserPtr<iovec>, iocnt: i32) -> LinuxResult<UserSlice<iovec>> {
+    if !(0..=1024).contains(&iocnt) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(UserSlice::new(iov.address(), iocnt as usize))
+}
+
+pub(crate) fn sys_readv(fd: i32, iov: UserPtr<iovec>, iocnt: i32) -> isize {
+    syscall_body!(sys_readv, {
+        let file = get_file(fd)?;
+        let mut total = 0;
+        for iov in iovecs(iov, iocnt)?.read_vec()? {
+            let buf = UserSlice::<u8>::new(iov.iov_base as usize, iov.iov_len as usize);
+            let len = read_to_user(file.as_ref(), &buf, |_, kbuf| file.read(kbuf))?;
+            total += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    })
+}
+
+pub(crate) fn sys_writev(fd: i32, iov: UserPtr<iovec>, iocnt: i32) -> isize {
+    syscall_body!(sys_writev, {
+        let file = get_file(fd)?;
+        let mut total = 0;
+        for iov in iovecs(iov, iocnt)?.read_vec()? {
+            let buf = UserSlice::<u8>::new(iov.iov_base as usize, iov.iov_len as usize);
+            let len = write_from_user(&buf, |_, kbuf| file.write(kbuf))?;
+            total += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    })
+}
+
+/// Read from the file at `offset` without changing the file offset.
+pub(crate) fn sys_pread64(fd: i32, buf: UserPtr<u8>, count: usize, offset: isize) -> isize {
+    syscall_body!(sys_pread64, {
+        if offset < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let file = get_file(fd)?;
+        let buf = UserSlice::<u8>::new(buf.address(), count);
+        read_to_user(file.as_ref(), &buf, |delta, kbuf| {
+            file.read_at(offset as u64 + delta as u64, kbuf)
+        })
+    })
 }
 
-pub(crate) fn sys_write(fd: i32, buf: *const c_void, count: usize) -> isize {
-    api::sys_write(fd, buf, count)
+/// Write to the file at `offset` without changing the file offset.
+pub(crate) fn sys_pwrite64(fd: i32, buf: UserPtr<u8>, count: usize, offset: isize) -> isize {
+    syscall_body!(sys_pwrite64, {
+        if offset < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let file = get_file(fd)?;
+        let buf = UserSlice::<u8>::new(buf.address(), count);
+        write_from_user(&buf, |delta, kbuf| {
+            file.write_at(offset as u64 + delta as u64, kbuf)
+        })
+    })
 }
 
-pub(crate) fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
-    unsafe { api::sys_writev(fd, iov, iocnt) }
+/// Move the file offset.
+///
+/// # Arguments
+/// * `fd` - The file descriptor
+/// * `offset` - The new offset relative to the position given by `whence`
+/// * `whence` - `SEEK_SET`, `SEEK_CUR` or `SEEK_END`
+pub(crate) fn sys_lseek(fd: i32, offset: isize, whence: i32) -> isize {
+    syscall_body!(sys_lseek, {
+        let pos = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset as i64),
+            2 => SeekFrom::End(offset as i64),
+            _ => return Err(LinuxError::EINVAL),
+        };
+        get_file(fd)?.seek(pos)
+    })
 }
@@ -1,5 +1,11 @@
 mod ctl;
+mod fd_ops;
 mod io;
+mod pipe;
+mod stat;
 
 pub(crate) use self::ctl::*;
+pub(crate) use self::fd_ops::*;
 pub(crate) use self::io::*;
+pub(crate) use self::pipe::*;
+pub(crate) use self::stat::*;
@@ -0,0 +1,51 @@
+use alloc::sync::Arc;
+
+use axerrno::LinuxError;
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    fs::{OpenFlags, Pipe},
+    ptr::UserPtr,
+    syscall_body,
+};
+
+/// Create a pipe, and store the file descriptors of its read end and write
+/// end in `fds`.
+///
+/// # Arguments
+/// * `fds` - Where to store the two file descriptors
+/// * `flags` - `O_NONBLOCK` and `O_CLOEXEC`, which apply to both ends
+pub(crate) fn sys_pipe2(fds: UserPtr<[i32; 2]>, flags: u32) -> isize {
+    syscall_body!(sys_pipe2, {
+        let flags = OpenFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if !(OpenFlags::O_NONBLOCK | OpenFlags::O_CLOEXEC).contains(flags) {
+            return Err(LinuxError::EINVAL);
+        }
+        let cloexec = flags.contains(OpenFlags::O_CLOEXEC);
+        let (reader, writer) = Pipe::new(flags);
+
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let mut fd_table = process.fd_table.lock();
+        let read_fd = fd_table.add(Arc::new(reader), cloexec)?;
+        let write_fd = match fd_table.add(Arc::new(writer), cloexec) {
+            Ok(fd) => fd,
+            Err(err) => {
+                fd_table.close(read_fd, process.pid())?;
+                return Err(err);
+            }
+        };
+        if let Err(err) = fds.write([read_fd, write_fd]) {
+            fd_table.close(read_fd, process.pid())?;
+            fd_table.close(write_fd, process.pid())?;
+            return Err(err);
+        }
+        Ok(0)
+    })
+}
+
+/// Create a pipe without flags.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_pipe(fds: UserPtr<[i32; 2]>) -> isize {
+    sys_pipe2(fds, 0)
+}
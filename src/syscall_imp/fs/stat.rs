@@ -0,0 +1,221 @@
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::{
+    fs::{absolute_path, get_file, lookup, lookup_link, NodeStat},
+    ptr::{UserCStr, UserPtr},
+    syscall_body,
+};
+
+/// Do not follow the symbolic link at the end of the path.
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+/// Operate on `dirfd` itself if the path is empty.
+const AT_EMPTY_PATH: u32 = 0x1000;
+/// The block size reported for all files.
+const BLOCK_SIZE: u64 = 512;
+
+/// `struct stat` of x86_64.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct Kstat {
+    st_dev: u64,
+    st_ino: u64,
+    st_nlink: u64,
+    st_mode: u32,
+    st_uid: u32,
+    st_gid: u32,
+    __pad0: u32,
+    st_rdev: u64,
+    st_size: i64,
+    st_blksize: i64,
+    st_blocks: i64,
+    st_atime: i64,
+    st_atime_nsec: i64,
+    st_mtime: i64,
+    st_mtime_nsec: i64,
+    st_ctime: i64,
+    st_ctime_nsec: i64,
+    __unused: [i64; 3],
+}
+
+/// `struct stat` of the generic architectures, such as riscv64 and aarch64.
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct Kstat {
+    st_dev: u64,
+    st_ino: u64,
+    st_mode: u32,
+    st_nlink: u32,
+    st_uid: u32,
+    st_gid: u32,
+    st_rdev: u64,
+    __pad1: u64,
+    st_size: i64,
+    st_blksize: i32,
+    __pad2: i32,
+    st_blocks: i64,
+    st_atime: i64,
+    st_atime_nsec: u64,
+    st_mtime: i64,
+    st_mtime_nsec: u64,
+    st_ctime: i64,
+    st_ctime_nsec: u64,
+    __unused4: u32,
+    __unused5: u32,
+}
+
+impl From<NodeStat> for Kstat {
+    fn from(stat: NodeStat) -> Self {
+        Self {
+            st_ino: stat.ino,
+            st_nlink: stat.nlink as _,
+            st_mode: stat.node_type.mode_bits() | stat.mode,
+            st_rdev: stat.rdev,
+            st_size: stat.size as _,
+            st_blksize: BLOCK_SIZE as _,
+            st_blocks: stat.size.div_ceil(BLOCK_SIZE) as _,
+            ..Default::default()
+        }
+    }
+}
+
+/// A timestamp of `struct statx`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct StatxTimestamp {
+    tv_sec: i64,
+    tv_nsec: u32,
+    __reserved: i32,
+}
+
+/// `struct statx`
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/stat.h>
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct Statx {
+    stx_mask: u32,
+    stx_blksize: u32,
+    stx_attributes: u64,
+    stx_nlink: u32,
+    stx_uid: u32,
+    stx_gid: u32,
+    stx_mode: u16,
+    __spare0: u16,
+    stx_ino: u64,
+    stx_size: u64,
+    stx_blocks: u64,
+    stx_attributes_mask: u64,
+    stx_atime: StatxTimestamp,
+    stx_btime: StatxTimestamp,
+    stx_ctime: StatxTimestamp,
+    stx_mtime: StatxTimestamp,
+    stx_rdev_major: u32,
+    stx_rdev_minor: u32,
+    stx_dev_major: u32,
+    stx_dev_minor: u32,
+    stx_mnt_id: u64,
+    stx_dio_mem_align: u32,
+    stx_dio_offset_align: u32,
+    __spare3: [u64; 12],
+}
+
+/// The fields of `struct stat` that are filled in `struct statx`.
+const STATX_BASIC_STATS: u32 = 0x7ff;
+
+impl From<NodeStat> for Statx {
+    fn from(stat: NodeStat) -> Self {
+        Self {
+            stx_mask: STATX_BASIC_STATS,
+            stx_blksize: BLOCK_SIZE as _,
+            stx_nlink: stat.nlink,
+            stx_mode: (stat.node_type.mode_bits() | stat.mode) as _,
+            stx_ino: stat.ino,
+            stx_size: stat.size,
+            stx_blocks: stat.size.div_ceil(BLOCK_SIZE),
+            stx_rdev_major: (stat.rdev >> 8) as u32 & 0xfff,
+            stx_rdev_minor: (stat.rdev & 0xff) as u32,
+            ..Default::default()
+        }
+    }
+}
+
+/// The attributes of the file at `path` relative to `dirfd`, or of `dirfd`
+/// itself if `path` is empty and `AT_EMPTY_PATH` is set.
+fn stat_at(dirfd: i32, path: UserCStr, flags: u32) -> LinuxResult<NodeStat> {
+    if flags & !(AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let path = path.read_path()?;
+    if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        return get_file(dirfd)?.stat();
+    }
+    let path = absolute_path(dirfd, &path)?;
+    if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        lookup_link(&path)?.stat()
+    } else {
+        lookup(&path)?.stat()
+    }
+}
+
+/// Get the attributes of the file `fd`.
+pub(crate) fn sys_fstat(fd: i32, statbuf: UserPtr<Kstat>) -> isize {
+    syscall_body!(sys_fstat, {
+        statbuf.write(get_file(fd)?.stat()?.into())?;
+        Ok(0)
+    })
+}
+
+/// Get the attributes of the file at `path` relative to `dirfd`.
+///
+/// # Arguments
+/// * `dirfd` - The directory that a relative `path` starts from, or
+///   `AT_FDCWD` for the current directory
+/// * `path` - The path of the file
+/// * `statbuf` - Where to store the attributes
+/// * `flags` - `AT_EMPTY_PATH` and `AT_SYMLINK_NOFOLLOW`
+pub(crate) fn sys_newfstatat(
+    dirfd: i32,
+    path: UserCStr,
+    statbuf: UserPtr<Kstat>,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_newfstatat, {
+        statbuf.write(stat_at(dirfd, path, flags)?.into())?;
+        Ok(0)
+    })
+}
+
+/// Get the attributes of the file at `path` relative to the current
+/// directory.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_stat(path: UserCStr, statbuf: UserPtr<Kstat>) -> isize {
+    sys_newfstatat(crate::fs::AT_FDCWD, path, statbuf, 0)
+}
+
+/// Get the attributes of the file at `path` relative to the current
+/// directory, without following symbolic links.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_lstat(path: UserCStr, statbuf: UserPtr<Kstat>) -> isize {
+    sys_newfstatat(crate::fs::AT_FDCWD, path, statbuf, AT_SYMLINK_NOFOLLOW)
+}
+
+/// Get the extended attributes of the file at `path` relative to `dirfd`.
+///
+/// All basic attributes are returned regardless of `mask`.
+pub(crate) fn sys_statx(
+    dirfd: i32,
+    path: UserCStr,
+    flags: u32,
+    _mask: u32,
+    statxbuf: UserPtr<Statx>,
+) -> isize {
+    syscall_body!(sys_statx, {
+        // The synchronization flags make no difference here.
+        const AT_STATX_SYNC_TYPE: u32 = 0x6000;
+        let stat = stat_at(dirfd, path, flags & !AT_STATX_SYNC_TYPE)?;
+        statxbuf.write(stat.into())?;
+        Ok(0)
+    })
+}
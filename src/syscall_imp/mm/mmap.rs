@@ -1,9 +1,16 @@
-use axerrno::LinuxError;
+use alloc::sync::Arc;
+
+use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
 use axtask::{current, TaskExtRef};
-use memory_addr::{VirtAddr, VirtAddrRange};
+use memory_addr::{VirtAddr, PAGE_SIZE_4K};
+use num_enum::TryFromPrimitive;
 
-use crate::syscall_body;
+use crate::{
+    fs::get_file,
+    mm::{Backend, MappedFile, SharedMemory},
+    syscall_body,
+};
 
 bitflags::bitflags! {
     /// permissions for sys_mmap
@@ -46,53 +53,327 @@ bitflags::bitflags! {
         const MAP_SHARED = 1 << 0;
         /// Changes private; copy pages on write.
         const MAP_PRIVATE = 1 << 1;
+        /// Share changes, and fail with unknown flags.
+        const MAP_SHARED_VALIDATE = 0x3;
         /// Map address must be exactly as requested, no matter whether it is available.
         const MAP_FIXED = 1 << 4;
         /// Don't use a file.
         const MAP_ANONYMOUS = 1 << 5;
+        /// The mapping grows down like a stack.
+        const MAP_GROWSDOWN = 1 << 8;
+        /// Ignored.
+        const MAP_DENYWRITE = 1 << 11;
+        /// Ignored.
+        const MAP_EXECUTABLE = 1 << 12;
+        /// Lock the pages in memory.
+        const MAP_LOCKED = 1 << 13;
         /// Don't check for reservations.
         const MAP_NORESERVE = 1 << 14;
+        /// Populate the pages right now.
+        const MAP_POPULATE = 1 << 15;
+        /// Do not block on IO when populating.
+        const MAP_NONBLOCK = 1 << 16;
         /// Allocation is for a stack.
         const MAP_STACK = 0x20000;
+        /// Like `MAP_FIXED`, but never replace existing mappings.
+        const MAP_FIXED_NOREPLACE = 0x100000;
+    }
+}
+
+/// The bits of `MmapFlags` that select the mapping type.
+const MAP_TYPE: i32 = 0xf;
+
+/// The file opened as `fd`, to be mapped into memory, and whether it is
+/// opened for writing.
+///
+/// The file must be readable, and also writable for writable shared
+/// mappings.
+fn mapped_file(fd: i32, shared_write: bool) -> LinuxResult<(Arc<dyn MappedFile>, bool)> {
+    let file = get_file(fd)?;
+    let flags = file.status_flags();
+    if !flags.readable() || (shared_write && !flags.writable()) {
+        return Err(LinuxError::EACCES);
     }
+    let mapped = file.mapped_file().ok_or(LinuxError::ENODEV)?;
+    Ok((mapped, flags.writable()))
 }
 
+/// Map files or anonymous memory into the address space.
+///
+/// # Arguments
+/// * `addr` - The address of the mapping if `MAP_FIXED` or
+///   `MAP_FIXED_NOREPLACE` is set, otherwise a hint
+/// * `length` - The size of the mapping, which is rounded up to pages
+/// * `prot` - The permissions of the mapping
+/// * `flags` - Exactly one of `MAP_SHARED`, `MAP_PRIVATE` and
+///   `MAP_SHARED_VALIDATE`, with other flags
+/// * `fd` - The file to map, unless `MAP_ANONYMOUS` is set
+/// * `offset` - The offset in the file, aligned to pages
 pub(crate) fn sys_mmap(
-    addr: *mut usize,
+    addr: usize,
     length: usize,
     prot: i32,
     flags: i32,
-    _fd: i32,
-    _offset: isize,
+    fd: i32,
+    offset: isize,
 ) -> usize {
     syscall_body!(sys_mmap, {
+        let permission_flags = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
+        let shared = match flags & MAP_TYPE {
+            t if t == MmapFlags::MAP_SHARED.bits() => true,
+            t if t == MmapFlags::MAP_PRIVATE.bits() => false,
+            t if t == MmapFlags::MAP_SHARED_VALIDATE.bits() => {
+                if MmapFlags::from_bits(flags).is_none() {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                true
+            }
+            _ => return Err(LinuxError::EINVAL),
+        };
+        // Unknown flags are ignored unless `MAP_SHARED_VALIDATE` is used.
+        let map_flags = MmapFlags::from_bits_truncate(flags);
+        if length == 0 || offset < 0 || !memory_addr::is_aligned_4k(offset as usize) {
+            return Err(LinuxError::EINVAL);
+        }
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::ENOMEM)?;
+        let fixed = map_flags.intersects(MmapFlags::MAP_FIXED | MmapFlags::MAP_FIXED_NOREPLACE);
+        if fixed && !memory_addr::is_aligned_4k(addr) {
+            return Err(LinuxError::EINVAL);
+        }
+        let anonymous = map_flags.contains(MmapFlags::MAP_ANONYMOUS);
+        let grows_down = map_flags.contains(MmapFlags::MAP_GROWSDOWN);
+        if grows_down && (shared || !anonymous) {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let backend = if anonymous {
+            if shared {
+                Backend::Shared {
+                    memory: Arc::new(SharedMemory::new_anonymous()),
+                    page_offset: 0,
+                }
+            } else {
+                Backend::Anonymous
+            }
+        } else {
+            let shared_write = shared && permission_flags.contains(MmapProt::PROT_WRITE);
+            let (file, writable) = mapped_file(fd, shared_write)?;
+            if shared {
+                Backend::Shared {
+                    memory: Arc::new(SharedMemory::new_file(file, offset as u64, writable)),
+                    page_offset: 0,
+                }
+            } else {
+                Backend::File {
+                    file,
+                    offset: offset as u64,
+                }
+            }
+        };
+
         let curr = current();
         let curr_ext = curr.task_ext();
-        let mut aspace = curr_ext.aspace.lock();
-        let permission_flags = MmapProt::from_bits_truncate(prot);
-        // TODO: check illegal flags for mmap
-        // An example is the flags contained none of MAP_PRIVATE, MAP_SHARED, or MAP_SHARED_VALIDATE.
-        let map_flags = MmapFlags::from_bits_truncate(flags);
+        let mut aspace = curr_ext.process.aspace.lock();
 
-        let start_addr = if map_flags.contains(MmapFlags::MAP_FIXED) {
-            VirtAddr::from(addr as usize)
+        let start_addr = if fixed {
+            VirtAddr::from(addr)
         } else {
             aspace
-                .find_free_area(
-                    VirtAddr::from(addr as usize),
-                    length,
-                    VirtAddrRange::new(aspace.base(), aspace.end()),
-                )
-                .or(aspace.find_free_area(
-                    aspace.base(),
-                    length,
-                    VirtAddrRange::new(aspace.base(), aspace.end()),
-                ))
+                .find_free_area(VirtAddr::from(addr), length)
+                .or(aspace.find_free_area(aspace.base(), length))
                 .ok_or(LinuxError::ENOMEM)?
         };
 
-        aspace.map_alloc(start_addr, length, permission_flags.into(), false)?;
+        let populate = map_flags.contains(MmapFlags::MAP_POPULATE);
+        // The old areas are replaced only once the new one is valid.
+        // `MAP_FIXED_NOREPLACE` fails with `EEXIST` over existing areas.
+        let replace = fixed && !map_flags.contains(MmapFlags::MAP_FIXED_NOREPLACE);
+        if grows_down {
+            aspace.map_stack(
+                start_addr,
+                length,
+                permission_flags.into(),
+                populate,
+                replace,
+            )?;
+        } else {
+            aspace.map(
+                start_addr,
+                length,
+                permission_flags.into(),
+                backend,
+                populate,
+                replace,
+            )?;
+        }
 
         Ok(start_addr.as_usize())
     })
 }
+
+/// Remove the mappings in `[addr, addr + length)`.
+///
+/// `addr` must be page-aligned, and `length` is rounded up to pages.
+pub(crate) fn sys_munmap(addr: usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        let curr = current();
+        let mut aspace = curr.task_ext().process.aspace.lock();
+        let length = memory_addr::align_up_4k(length);
+        aspace.unmap(VirtAddr::from(addr), length)?;
+        Ok(0)
+    })
+}
+
+/// Change the permissions of the pages in `[addr, addr + length)`.
+///
+/// Returns `ENOMEM` if any page in the range is not mapped, or `EACCES` if
+/// a shared mapping of a file opened read-only would become writable.
+pub(crate) fn sys_mprotect(addr: usize, length: usize, prot: i32) -> isize {
+    syscall_body!(sys_mprotect, {
+        let permission_flags = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
+        let curr = current();
+        let mut aspace = curr.task_ext().process.aspace.lock();
+        let length = memory_addr::align_up_4k(length);
+        aspace.protect(VirtAddr::from(addr), length, permission_flags.into())?;
+        Ok(0)
+    })
+}
+
+bitflags::bitflags! {
+    /// flags for sys_mremap
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/mman.h>
+    #[derive(Debug)]
+    struct MremapFlags: i32 {
+        /// The mapping may be moved to a new address.
+        const MREMAP_MAYMOVE = 1 << 0;
+        /// The mapping must be moved to `new_addr`.
+        const MREMAP_FIXED = 1 << 1;
+        /// Keep the old mapping after moving it.
+        const MREMAP_DONTUNMAP = 1 << 2;
+    }
+}
+
+/// Resize the mapping at `[old_addr, old_addr + old_size)` to `new_size`
+/// bytes, possibly moving it if `MREMAP_MAYMOVE` is set.
+///
+/// Only `MREMAP_MAYMOVE` is supported.
+pub(crate) fn sys_mremap(
+    old_addr: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: i32,
+    _new_addr: usize,
+) -> usize {
+    syscall_body!(sys_mremap, {
+        let flags = MremapFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if flags.intersects(MremapFlags::MREMAP_FIXED | MremapFlags::MREMAP_DONTUNMAP) {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current();
+        let mut aspace = curr.task_ext().process.aspace.lock();
+        let new_start = aspace.remap(
+            VirtAddr::from(old_addr),
+            memory_addr::align_up_4k(old_size),
+            memory_addr::align_up_4k(new_size),
+            flags.contains(MremapFlags::MREMAP_MAYMOVE),
+        )?;
+        Ok(new_start.as_usize())
+    })
+}
+
+/// Advice for sys_madvise
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/mman-common.h>
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(i32)]
+enum MadviseAdvice {
+    /// No special treatment.
+    Normal = 0,
+    /// Expect random page references.
+    Random = 1,
+    /// Expect sequential page references.
+    Sequential = 2,
+    /// The pages will be needed soon.
+    WillNeed = 3,
+    /// The pages are not needed any more, and can be freed.
+    DontNeed = 4,
+    /// The pages can be freed lazily.
+    Free = 8,
+}
+
+/// Give advice about the use of the pages in `[addr, addr + length)`.
+///
+/// Only `MADV_DONTNEED` and `MADV_FREE` take effect, by freeing the pages.
+pub(crate) fn sys_madvise(addr: usize, length: usize, advice: i32) -> isize {
+    syscall_body!(sys_madvise, {
+        let advice = MadviseAdvice::try_from(advice).map_err(|_| LinuxError::EINVAL)?;
+        if length == 0 {
+            return Ok(0);
+        }
+        let curr = current();
+        let mut aspace = curr.task_ext().process.aspace.lock();
+        let start = VirtAddr::from(addr);
+        let length = memory_addr::align_up_4k(length);
+        match advice {
+            MadviseAdvice::DontNeed | MadviseAdvice::Free => aspace.discard(start, length)?,
+            _ => {}
+        }
+        Ok(0)
+    })
+}
+
+bitflags::bitflags! {
+    /// flags for sys_msync
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/mman-common.h>
+    #[derive(Debug)]
+    struct MsyncFlags: i32 {
+        /// Schedule the write-back and return.
+        const MS_ASYNC = 1 << 0;
+        /// Invalidate other mappings of the same file.
+        const MS_INVALIDATE = 1 << 1;
+        /// Wait until the write-back is done.
+        const MS_SYNC = 1 << 2;
+    }
+}
+
+/// Write the changes to the shared file mappings in `[addr, addr + length)`
+/// back to the files.
+///
+/// The write-back is always done synchronously, even with `MS_ASYNC`.
+pub(crate) fn sys_msync(addr: usize, length: usize, flags: i32) -> isize {
+    syscall_body!(sys_msync, {
+        let flags = MsyncFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC) {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current();
+        let mut aspace = curr.task_ext().process.aspace.lock();
+        let length = memory_addr::align_up_4k(length);
+        aspace.sync(VirtAddr::from(addr), length)?;
+        Ok(0)
+    })
+}
+
+/// Set the program break to `addr`, and return the new program break.
+///
+/// The current program break is returned if `addr` is 0 or the heap cannot
+/// be changed, like the raw syscall of Linux.
+pub(crate) fn sys_brk(addr: usize) -> usize {
+    syscall_body!(sys_brk, {
+        let curr = current();
+        let mut aspace = curr.task_ext().process.aspace.lock();
+        if addr != 0 {
+            if let Err(err) = aspace.set_brk(VirtAddr::from(addr)) {
+                debug!(
+                    "sys_brk: failed to move the break to {:#x}: {:?}",
+                    addr, err
+                );
+            }
+        }
+        Ok(aspace.brk().as_usize())
+    })
+}
@@ -1,5 +1,7 @@
 mod fs;
 mod mm;
+mod random;
+mod signal;
 mod task;
 mod time;
 
@@ -8,12 +10,19 @@ use axhal::{
     arch::TrapFrame,
     trap::{register_trap_handler, SYSCALL},
 };
+use axtask::TaskExtRef;
 use syscalls::Sysno;
 
 use self::fs::*;
 use self::mm::*;
+use self::random::*;
+use self::signal::*;
 use self::task::*;
 use self::time::*;
+use crate::{
+    signal::{handle_signals, ERESTARTSYS},
+    task::exit_if_killed,
+};
 
 /// Macro to generate syscall body
 ///
@@ -37,11 +46,109 @@ macro_rules! syscall_body {
     }};
 }
 
+/// Whether the syscall is restarted when it is interrupted by a signal,
+/// unless a handler without `SA_RESTART` runs. The others fail with `EINTR`,
+/// like those with timeouts on Linux.
+fn is_restartable(sysno: Sysno) -> bool {
+    matches!(
+        sysno,
+        Sysno::read
+            | Sysno::write
+            | Sysno::readv
+            | Sysno::writev
+            | Sysno::pread64
+            | Sysno::pwrite64
+            | Sysno::ioctl
+            | Sysno::fcntl
+            | Sysno::wait4
+            | Sysno::waitid
+    )
+}
+
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
-    match Sysno::from(syscall_num as u32) {
-        Sysno::read => sys_read(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
-        Sysno::write => sys_write(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+    let curr = axtask::current();
+    curr.task_ext().enter_kernel();
+    let sysno = Sysno::from(syscall_num as u32);
+    let ret = match sysno {
+        Sysno::read => sys_read(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::write => sys_write(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::pread64 => sys_pread64(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::pwrite64 => sys_pwrite64(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        Sysno::lseek => sys_lseek(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::openat => sys_openat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::open => sys_open(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::close => sys_close(tf.arg0() as _),
+        Sysno::dup => sys_dup(tf.arg0() as _),
+        Sysno::dup3 => sys_dup3(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::dup2 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
+        Sysno::fcntl => sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::pipe => sys_pipe(tf.arg0().into()),
+        Sysno::fstat => sys_fstat(tf.arg0() as _, tf.arg1().into()),
+        Sysno::newfstatat => sys_newfstatat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::stat => sys_stat(tf.arg0().into(), tf.arg1().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::lstat => sys_lstat(tf.arg0().into(), tf.arg1().into()),
+        Sysno::statx => sys_statx(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::getdents64 => sys_getdents64(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::mkdirat => sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::mkdir => sys_mkdir(tf.arg0().into(), tf.arg1() as _),
+        Sysno::unlinkat => sys_unlinkat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::unlink => sys_unlink(tf.arg0().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::rmdir => sys_rmdir(tf.arg0().into()),
+        Sysno::renameat2 => sys_renameat2(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::rename => sys_rename(tf.arg0().into(), tf.arg1().into()),
+        Sysno::readlinkat => sys_readlinkat(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::readlink => sys_readlink(tf.arg0().into(), tf.arg1().into(), tf.arg2() as _),
+        Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
+        Sysno::chdir => sys_chdir(tf.arg0().into()),
         Sysno::mmap => sys_mmap(
             tf.arg0() as _,
             tf.arg1() as _,
@@ -50,20 +157,115 @@ fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
             tf.arg4() as _,
             tf.arg5() as _,
         ) as _,
-        Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _) as _,
-        Sysno::writev => sys_writev(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::brk => sys_brk(tf.arg0() as _) as _,
+        Sysno::munmap => sys_munmap(tf.arg0() as _, tf.arg1() as _),
+        Sysno::mprotect => sys_mprotect(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::mremap => sys_mremap(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ) as _,
+        Sysno::msync => sys_msync(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::madvise => sys_madvise(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::readv => sys_readv(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::writev => sys_writev(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
         Sysno::sched_yield => sys_sched_yield() as isize,
-        Sysno::nanosleep => sys_nanosleep(tf.arg0() as _, tf.arg1() as _) as _,
+        Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()) as _,
         Sysno::getpid => sys_getpid() as isize,
+        Sysno::getppid => sys_getppid() as isize,
+        Sysno::gettid => sys_gettid() as isize,
+        Sysno::getpgid => sys_getpgid(tf.arg0() as _) as _,
+        Sysno::setpgid => sys_setpgid(tf.arg0() as _, tf.arg1() as _) as _,
+        #[cfg(target_arch = "x86_64")]
+        Sysno::getpgrp => sys_getpgid(0) as _,
         Sysno::exit => sys_exit(tf.arg0() as _),
         #[cfg(target_arch = "x86_64")]
+        Sysno::clone => sys_clone(
+            tf,
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg4() as _,
+            tf.arg3() as _,
+        ),
+        #[cfg(not(target_arch = "x86_64"))]
+        Sysno::clone => sys_clone(
+            tf,
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        ),
+        Sysno::execve => sys_execve(tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::fork => sys_fork(tf),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::vfork => sys_vfork(tf),
+        #[cfg(target_arch = "x86_64")]
         Sysno::arch_prctl => sys_arch_prctl(tf.arg0() as _, tf.arg1() as _),
-        Sysno::set_tid_address => sys_set_tid_address(tf.arg0() as _),
-        Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1() as _) as _,
+        Sysno::futex => sys_futex(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3().into(),
+            tf.arg4() as _,
+            tf.arg5() as _,
+        ),
+        Sysno::set_tid_address => sys_set_tid_address(tf.arg0().into()),
+        Sysno::getrandom => sys_getrandom(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()) as _,
         Sysno::exit_group => sys_exit_group(tf.arg0() as _),
+        Sysno::wait4 => sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3().into(),
+        ),
+        Sysno::waitid => sys_waitid(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3() as _,
+            tf.arg4().into(),
+        ),
+        Sysno::kill => sys_kill(tf.arg0() as _, tf.arg1() as _),
+        Sysno::tkill => sys_tkill(tf.arg0() as _, tf.arg1() as _),
+        Sysno::tgkill => sys_tgkill(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::rt_sigaction => sys_rt_sigaction(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        Sysno::rt_sigprocmask => sys_rt_sigprocmask(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
+        Sysno::rt_sigreturn => sys_rt_sigreturn(tf),
+        Sysno::rt_sigtimedwait => sys_rt_sigtimedwait(
+            tf.arg0().into(),
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        ),
         _ => {
             warn!("Unimplemented syscall: {}", syscall_num);
-            axtask::exit(LinuxError::ENOSYS as _)
+            -LinuxError::ENOSYS.code() as _
         }
-    }
+    };
+    let ret = if ret == -LinuxError::EINTR.code() as isize && is_restartable(sysno) {
+        -ERESTARTSYS
+    } else {
+        ret
+    };
+    exit_if_killed();
+    handle_signals(tf, Some(ret));
+    curr.task_ext().leave_kernel();
+    ret
 }
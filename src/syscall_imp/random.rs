@@ -0,0 +1,37 @@
+use alloc::vec;
+
+use axerrno::LinuxError;
+
+use crate::{
+    ptr::{UserPtr, UserSlice},
+    random, syscall_body,
+};
+
+/// Do not block if no randomness is available.
+const GRND_NONBLOCK: u32 = 1;
+/// Draw from the blocking pool, which is the same as the other here.
+const GRND_RANDOM: u32 = 2;
+/// Return possibly insecure randomness, which is the same here.
+const GRND_INSECURE: u32 = 4;
+
+/// The maximum number of bytes filled at a time.
+const GETRANDOM_MAX_SIZE: usize = 0x10000;
+
+/// Fill `buf` with `len` random bytes, and return the number of bytes
+/// filled, which is at most 64 KiB at a time.
+///
+/// The generator never runs out of randomness, so it never blocks.
+pub(crate) fn sys_getrandom(buf: UserPtr<u8>, len: usize, flags: u32) -> isize {
+    syscall_body!(sys_getrandom, {
+        if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+            || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let len = len.min(GETRANDOM_MAX_SIZE);
+        let mut bytes = vec![0; len];
+        random::fill_bytes(&mut bytes);
+        UserSlice::<u8>::new(buf.address(), len).write(&bytes)?;
+        Ok(len)
+    })
+}
@@ -0,0 +1,267 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{mem::size_of, time::Duration};
+
+use arceos_posix_api::ctypes::timespec;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axtask::{current, TaskExtRef};
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    ptr::UserPtr,
+    signal::{
+        send_signal_to_process, send_signal_to_thread, sigreturn, take_signal, SigAction, SigInfo,
+        SignalSet, NSIG, SI_TKILL, SI_USER,
+    },
+    syscall_body,
+    task::{find_process, find_thread, processes, Process},
+};
+
+/// How sys_rt_sigprocmask changes the signal mask
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(i32)]
+enum SigprocmaskHow {
+    /// Add the signals to the mask.
+    Block = 0,
+    /// Remove the signals from the mask.
+    Unblock = 1,
+    /// Replace the mask.
+    SetMask = 2,
+}
+
+/// Check that `signo` is a valid signal number.
+fn check_signo(signo: i32) -> LinuxResult<u32> {
+    if signo < 1 || signo as usize > NSIG {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(signo as u32)
+}
+
+/// Check the size of the signal sets passed by user space.
+fn check_sigsetsize(sigsetsize: usize) -> LinuxResult {
+    if sigsetsize != size_of::<SignalSet>() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(())
+}
+
+/// Examine and change the action of a signal.
+///
+/// # Arguments
+/// * `signo` - The signal, which cannot be SIGKILL or SIGSTOP if `act` is set
+/// * `act` - The new action, can be NULL
+/// * `oldact` - Where to store the old action, can be NULL
+/// * `sigsetsize` - The size of the signal sets, which must be 8
+pub(crate) fn sys_rt_sigaction(
+    signo: i32,
+    act: UserPtr<SigAction>,
+    oldact: UserPtr<SigAction>,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigaction, {
+        check_sigsetsize(sigsetsize)?;
+        let signo = check_signo(signo)?;
+        let new_action = if act.is_null() {
+            None
+        } else {
+            Some(act.read()?)
+        };
+        if new_action.is_some() && SignalSet::UNBLOCKABLE.contains(signo) {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let mut actions = process.signal_actions.lock();
+        let old_action = *actions.get(signo);
+        if let Some(mut action) = new_action {
+            action.mask.0 &= !SignalSet::UNBLOCKABLE.0;
+            *actions.get_mut(signo) = action;
+            // Ignoring a signal discards its pending instances.
+            if actions.is_ignored(signo) {
+                process.pending_signals.discard(signo);
+                for thread in process.threads() {
+                    thread.task_ext().pending_signals.discard(signo);
+                }
+            }
+        }
+        drop(actions);
+
+        if !oldact.is_null() {
+            oldact.write(old_action)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Examine and change the signals blocked by the calling thread.
+///
+/// # Arguments
+/// * `how` - `SIG_BLOCK`, `SIG_UNBLOCK` or `SIG_SETMASK`
+/// * `set` - The signals to change, can be NULL
+/// * `oldset` - Where to store the old mask, can be NULL
+/// * `sigsetsize` - The size of the signal sets, which must be 8
+pub(crate) fn sys_rt_sigprocmask(
+    how: i32,
+    set: UserPtr<SignalSet>,
+    oldset: UserPtr<SignalSet>,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigprocmask, {
+        check_sigsetsize(sigsetsize)?;
+        let curr = current();
+        let old_mask = curr.task_ext().signal_mask();
+        if !set.is_null() {
+            let set = set.read()?;
+            let mut mask = match SigprocmaskHow::try_from(how).map_err(|_| LinuxError::EINVAL)? {
+                SigprocmaskHow::Block => SignalSet(old_mask.0 | set.0),
+                SigprocmaskHow::Unblock => SignalSet(old_mask.0 & !set.0),
+                SigprocmaskHow::SetMask => set,
+            };
+            mask.0 &= !SignalSet::UNBLOCKABLE.0;
+            curr.task_ext().set_signal_mask(mask);
+        }
+        if !oldset.is_null() {
+            oldset.write(old_mask)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Return from a signal handler and restore the context before the signal.
+pub(crate) fn sys_rt_sigreturn(tf: &TrapFrame) -> ! {
+    sigreturn(tf)
+}
+
+/// Send a signal to processes.
+///
+/// # Arguments
+/// * `pid` - The process to send the signal to if positive, or all processes
+///   in the process group `-pid` if less than -1, or all processes in the
+///   same process group if 0, or all processes except init and the caller if -1
+/// * `signo` - The signal, or 0 to only check whether the processes exist
+pub(crate) fn sys_kill(pid: i32, signo: i32) -> isize {
+    syscall_body!(sys_kill, {
+        if signo != 0 {
+            check_signo(signo)?;
+        }
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let in_group = |pgid| {
+            processes()
+                .into_iter()
+                .filter(|p| p.pgid() == pgid)
+                .collect::<Vec<_>>()
+        };
+        let targets: Vec<Arc<Process>> = match pid {
+            pid if pid > 0 => vec![find_process(pid as _).ok_or(LinuxError::ESRCH)?],
+            0 => in_group(process.pgid()),
+            -1 => processes()
+                .into_iter()
+                .filter(|p| p.pid() != 1 && !Arc::ptr_eq(p, process))
+                .collect(),
+            pid => in_group(-(pid as i64) as _),
+        };
+        if targets.is_empty() {
+            return Err(LinuxError::ESRCH);
+        }
+        if signo == 0 {
+            return Ok(0);
+        }
+        for target in targets {
+            let info = SigInfo::from_process(signo as _, SI_USER, process.pid());
+            send_signal_to_process(&target, info);
+        }
+        Ok(0)
+    })
+}
+
+/// Send a signal to the thread `tid` in the process `tgid`.
+pub(crate) fn sys_tgkill(tgid: i32, tid: i32, signo: i32) -> isize {
+    syscall_body!(sys_tgkill, {
+        if tgid <= 0 || tid <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        send_to_thread(tid, signo, Some(tgid))
+    })
+}
+
+/// Send a signal to the thread `tid`.
+pub(crate) fn sys_tkill(tid: i32, signo: i32) -> isize {
+    syscall_body!(sys_tkill, {
+        if tid <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        send_to_thread(tid, signo, None)
+    })
+}
+
+fn send_to_thread(tid: i32, signo: i32, tgid: Option<i32>) -> LinuxResult<isize> {
+    if signo != 0 {
+        check_signo(signo)?;
+    }
+    let thread = find_thread(tid as _)
+        .filter(|t| tgid.map_or(true, |tgid| t.task_ext().process.pid() == tgid as u64))
+        .ok_or(LinuxError::ESRCH)?;
+    if signo != 0 {
+        let pid = current().task_ext().process.pid();
+        send_signal_to_thread(&thread, SigInfo::from_process(signo as _, SI_TKILL, pid));
+    }
+    Ok(0)
+}
+
+/// Wait for one of the signals in `set` to be pending, and take it.
+///
+/// # Arguments
+/// * `set` - The signals to wait for
+/// * `info` - Where to store the information about the signal, can be NULL
+/// * `timeout` - The maximum time to wait, can be NULL to wait forever
+/// * `sigsetsize` - The size of the signal sets, which must be 8
+pub(crate) fn sys_rt_sigtimedwait(
+    set: UserPtr<SignalSet>,
+    info: UserPtr<SigInfo>,
+    timeout: UserPtr<timespec>,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigtimedwait, {
+        check_sigsetsize(sigsetsize)?;
+        let mut set = set.read()?;
+        set.0 &= !SignalSet::UNBLOCKABLE.0;
+        let timeout = if timeout.is_null() {
+            None
+        } else {
+            let ts = timeout.read()?;
+            if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(Duration::new(ts.tv_sec as _, ts.tv_nsec as _))
+        };
+
+        let curr = current();
+        let ext = curr.task_ext();
+        let signal = take_signal(set).or_else(|| {
+            let condition = || {
+                let pending = ext.pending_signals.set().0 | ext.process.pending_signals.set().0;
+                pending & set.0 != 0 || ext.is_interrupted()
+            };
+            match timeout {
+                Some(timeout) => {
+                    ext.signal_wq.wait_timeout_until(timeout, condition);
+                }
+                None => ext.signal_wq.wait_until(condition),
+            }
+            take_signal(set)
+        });
+        let Some(signal) = signal else {
+            return Err(if ext.is_interrupted() {
+                LinuxError::EINTR
+            } else {
+                LinuxError::EAGAIN
+            });
+        };
+        if !info.is_null() {
+            info.write(signal)?;
+        }
+        Ok(signal.signo as isize)
+    })
+}
@@ -0,0 +1,198 @@
+use alloc::sync::Arc;
+
+use axerrno::LinuxError;
+use axhal::arch::{TrapFrame, UspaceContext};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    ptr::UserPtr,
+    syscall_body,
+    task::{new_user_task, spawn_user_task, Process},
+};
+
+bitflags::bitflags! {
+    /// Flags for sys_clone
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/sched.h>
+    #[derive(Debug, Clone, Copy)]
+    struct CloneFlags: u32 {
+        /// The calling process and the child process run in the same memory space.
+        const CLONE_VM = 0x0000_0100;
+        /// The caller and the child process share the same filesystem information.
+        const CLONE_FS = 0x0000_0200;
+        /// The calling process and the child process share the same file descriptor table.
+        const CLONE_FILES = 0x0000_0400;
+        /// The calling process and the child process share the same table of signal handlers.
+        const CLONE_SIGHAND = 0x0000_0800;
+        /// The calling process is suspended until the child calls `execve` or exits.
+        const CLONE_VFORK = 0x0000_4000;
+        /// The parent of the new child will be the same as that of the calling process.
+        const CLONE_PARENT = 0x0000_8000;
+        /// The child is placed in the same thread group as the calling process.
+        const CLONE_THREAD = 0x0001_0000;
+        /// The TLS (Thread Local Storage) descriptor is set to `tls`.
+        const CLONE_SETTLS = 0x0008_0000;
+        /// Store the child thread ID in the parent's memory at `parent_tid`.
+        const CLONE_PARENT_SETTID = 0x0010_0000;
+        /// Clear the child thread ID at `child_tid` in the child's memory when the child exits.
+        const CLONE_CHILD_CLEARTID = 0x0020_0000;
+        /// Store the child thread ID in the child's memory at `child_tid`.
+        const CLONE_CHILD_SETTID = 0x0100_0000;
+    }
+}
+
+/// The mask of the signal sent to the parent when the child exits.
+const CSIGNAL: u32 = 0xff;
+
+/// The thread pointer of the current task in user space.
+#[cfg(not(target_arch = "riscv64"))]
+fn current_tls(_tf: &TrapFrame) -> usize {
+    axhal::arch::read_thread_pointer()
+}
+
+/// The thread pointer of the current task in user space.
+#[cfg(target_arch = "riscv64")]
+fn current_tls(tf: &TrapFrame) -> usize {
+    tf.regs.tp
+}
+
+/// Create a child process or thread.
+///
+/// Unlike Linux, `CLONE_VM` is ignored with `CLONE_VFORK`: the child gets a
+/// copy-on-write address space, so its writes to memory before `execve` or
+/// `_exit` are not seen by the parent, which is still suspended until then.
+///
+/// # Arguments
+/// * `tf` - The trap frame of the calling task, which is copied to the child
+/// * `flags` - The clone flags, with the exit signal in the lowest byte
+/// * `stack` - The stack pointer of the child, or 0 to use the same stack as the caller
+/// * `parent_tid` - Where to store the child TID in the parent's memory
+/// * `tls` - The thread pointer of the child
+/// * `child_tid` - Where to store or clear the child TID in the child's memory
+pub(crate) fn sys_clone(
+    tf: &TrapFrame,
+    flags: u32,
+    stack: usize,
+    parent_tid: usize,
+    tls: usize,
+    child_tid: usize,
+) -> isize {
+    syscall_body!(sys_clone, {
+        let mut clone_flags = CloneFlags::from_bits_truncate(flags & !CSIGNAL);
+        if clone_flags.contains(CloneFlags::CLONE_THREAD)
+            && !clone_flags.contains(CloneFlags::CLONE_VM | CloneFlags::CLONE_SIGHAND)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if clone_flags.contains(CloneFlags::CLONE_SIGHAND)
+            && !clone_flags.contains(CloneFlags::CLONE_VM)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if clone_flags.contains(CloneFlags::CLONE_VFORK) {
+            // Known incompatibility: the child of `vfork` gets a copy-on-write
+            // address space instead of borrowing the parent's one, since
+            // `execve` replaces the address space in place and cannot give
+            // the child a fresh one.
+            clone_flags.remove(CloneFlags::CLONE_VM);
+        }
+        debug!("sys_clone <= flags: {:?}, stack: {:#x}", clone_flags, stack);
+
+        let curr = current();
+        let process = &curr.task_ext().process;
+
+        let mut uctx = UspaceContext::from(tf);
+        if stack != 0 {
+            uctx.set_sp(stack);
+        }
+        // The trap handler skips the `ecall` instruction after the syscall
+        // returns, which does not happen for the child.
+        #[cfg(target_arch = "riscv64")]
+        uctx.set_ip(uctx.get_ip() + 4);
+        uctx.set_retval(0);
+
+        let tls = if clone_flags.contains(CloneFlags::CLONE_SETTLS) {
+            tls
+        } else {
+            current_tls(tf)
+        };
+
+        let new_task = if clone_flags.contains(CloneFlags::CLONE_THREAD) {
+            new_user_task(curr.name(), uctx, tls, |_| process.clone())
+        } else {
+            let parent = if clone_flags.contains(CloneFlags::CLONE_PARENT) {
+                process.parent()
+            } else {
+                Some(process.clone())
+            };
+            let aspace = if clone_flags.contains(CloneFlags::CLONE_VM) {
+                process.aspace.clone()
+            } else {
+                Arc::new(Mutex::new(process.aspace.lock().try_clone()?))
+            };
+            let signal_actions = if clone_flags.contains(CloneFlags::CLONE_SIGHAND) {
+                process.signal_actions.clone()
+            } else {
+                Arc::new(Mutex::new(process.signal_actions.lock().clone()))
+            };
+            let fd_table = if clone_flags.contains(CloneFlags::CLONE_FILES) {
+                process.fd_table.clone()
+            } else {
+                Arc::new(Mutex::new(process.fd_table.lock().clone()))
+            };
+            let cwd = if clone_flags.contains(CloneFlags::CLONE_FS) {
+                process.cwd.clone()
+            } else {
+                Arc::new(Mutex::new(process.cwd.lock().clone()))
+            };
+            let new_task = new_user_task(curr.name(), uctx, tls, |pid| {
+                Process::new(pid, parent.as_ref(), aspace, signal_actions, fd_table, cwd)
+            });
+            let new_process = &new_task.task_ext().process;
+            new_process.set_exit_signal(flags & CSIGNAL);
+            new_process.set_exec_info(process.exec_info());
+            new_task
+        };
+        new_task
+            .task_ext()
+            .set_signal_mask(curr.task_ext().signal_mask());
+        let tid = new_task.id().as_u64();
+
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+            new_task.task_ext().set_set_child_tid(child_tid as _);
+        }
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+            new_task.task_ext().set_clear_child_tid(child_tid as _);
+        }
+        if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
+            UserPtr::<u32>::from(parent_tid).write(tid as u32)?;
+        }
+
+        let new_task = spawn_user_task(new_task);
+        if clone_flags.contains(CloneFlags::CLONE_VFORK) {
+            new_task.task_ext().process.wait_vfork_done();
+        }
+        Ok(tid)
+    })
+}
+
+/// Create a child process that is a copy of the calling process.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_fork(tf: &TrapFrame) -> isize {
+    sys_clone(tf, 0, 0, 0, 0, 0)
+}
+
+/// Create a child process and suspend the calling process until the child
+/// calls `execve` or exits.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_vfork(tf: &TrapFrame) -> isize {
+    sys_clone(
+        tf,
+        (CloneFlags::CLONE_VM | CloneFlags::CLONE_VFORK).bits(),
+        0,
+        0,
+        0,
+        0,
+    )
+}
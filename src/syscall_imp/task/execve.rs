@@ -0,0 +1,100 @@
+use alloc::sync::Arc;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::UspaceContext;
+use axtask::{current, TaskExtRef};
+use memory_addr::VirtAddr;
+
+use crate::{
+    loader,
+    mm::{self, UserSpace},
+    ptr::{UserCStr, UserPtr},
+    task::{exit_current, ExecInfo},
+};
+
+/// The maximum size of the arguments and the environment variables of
+/// `execve`, including the pointers to them, like `ARG_MAX` of Linux.
+const ARG_MAX: usize = 128 * 1024;
+
+/// Replace the image of the current process with the program at `path`.
+///
+/// Returns the entry point and the user stack pointer of the new program.
+fn exec_current(
+    path: UserCStr,
+    argv: UserPtr<usize>,
+    envp: UserPtr<usize>,
+) -> LinuxResult<(VirtAddr, VirtAddr)> {
+    let path = path.read_path()?;
+    let mut budget = ARG_MAX;
+    let mut args = UserCStr::read_array(argv, &mut budget)?;
+    let envs = UserCStr::read_array(envp, &mut budget)?;
+    if args.is_empty() {
+        args.push(path.clone().into_bytes());
+    }
+    info!("sys_execve <= path: {:?}, argc: {}", path, args.len());
+
+    let curr = current();
+    let process = &curr.task_ext().process;
+    if Arc::strong_count(&process.aspace) > 1 {
+        warn!("sys_execve: the address space is shared with another process");
+        return Err(LinuxError::EAGAIN);
+    }
+
+    let (abs_path, elf_data, args) = loader::read_exec(&path, args)?;
+    // Build the new image aside, so that errors can still be returned to
+    // the old one.
+    let mut new_space = UserSpace::new_empty()?;
+    let (entry, ustack_top) = mm::load_user_app(&mut new_space, &elf_data, &path, &args, &envs)?;
+
+    // Every check that may fail is done above, as the other threads are
+    // killed for good.
+    if process.thread_count() > 1 && !process.exec_kill_other_threads() {
+        // Another thread is exiting the whole process.
+        return Err(LinuxError::EINTR);
+    }
+
+    process.signal_actions.lock().reset_on_exec();
+    process.fd_table.lock().close_on_exec(process.pid());
+    let res = process.aspace.lock().replace(new_space);
+    process.notify_vfork_done();
+    if let Err(err) = res {
+        // The old image is gone, so there is nothing to return to.
+        error!("sys_execve: failed to map {}: {:?}", path, err);
+        exit_current(-1);
+    }
+    process.set_exec_info(ExecInfo {
+        path: abs_path,
+        args,
+        envs,
+    });
+    Ok((entry, ustack_top))
+}
+
+/// Execute the program at `path`, replacing the image of the calling process.
+///
+/// On success, the function does not return but enters the new program
+/// directly from the current kernel stack.
+///
+/// # Arguments
+/// * `path` - The path of the program
+/// * `argv` - The arguments passed to the program, terminated by NULL
+/// * `envp` - The environment variables passed to the program, terminated by NULL
+pub(crate) fn sys_execve(path: UserCStr, argv: UserPtr<usize>, envp: UserPtr<usize>) -> isize {
+    let (entry, ustack_top) = match exec_current(path, argv, envp) {
+        Ok(res) => res,
+        Err(err) => {
+            info!("sys_execve => {:?}", err);
+            return -err.code() as _;
+        }
+    };
+
+    let curr = current();
+    curr.task_ext().leave_kernel();
+    let kstack_top = curr.kernel_stack_top().unwrap();
+    info!(
+        "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
+        entry, ustack_top, kstack_top,
+    );
+    let uctx = UspaceContext::new(entry.as_usize(), ustack_top, 0);
+    unsafe { uctx.enter_uspace(kstack_top) };
+}
@@ -0,0 +1,150 @@
+use core::time::Duration;
+
+use arceos_posix_api::ctypes::timespec;
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+use num_enum::TryFromPrimitive;
+
+use crate::{
+    futex::{futex_requeue, futex_wait, futex_wake, FUTEX_BITSET_MATCH_ANY},
+    ptr::UserPtr,
+    syscall_body,
+};
+
+/// Futex operations
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/futex.h>
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u32)]
+enum FutexOp {
+    /// Wait if the word still contains the expected value.
+    Wait = 0,
+    /// Wake up waiters.
+    Wake = 1,
+    /// Wake up waiters and move the others to another futex.
+    Requeue = 3,
+    /// Like `Requeue`, but only if the word still contains the expected value.
+    CmpRequeue = 4,
+    /// Like `Wait`, with a bitset and an absolute timeout.
+    WaitBitset = 9,
+    /// Wake up waiters whose bitsets intersect with the given one.
+    WakeBitset = 10,
+}
+
+/// The futex is only used by the calling process.
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// The absolute timeout of `FUTEX_WAIT_BITSET` is measured by
+/// `CLOCK_REALTIME` instead of `CLOCK_MONOTONIC`.
+const FUTEX_CLOCK_REALTIME: u32 = 256;
+
+/// Read a timeout from user space and convert it into a duration relative to
+/// now.
+fn read_timeout(
+    timeout: UserPtr<timespec>,
+    absolute: bool,
+    realtime: bool,
+) -> LinuxResult<Duration> {
+    let ts = timeout.read()?;
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    let dur = Duration::new(ts.tv_sec as _, ts.tv_nsec as _);
+    if !absolute {
+        return Ok(dur);
+    }
+    let now = if realtime {
+        axhal::time::wall_time()
+    } else {
+        axhal::time::monotonic_time()
+    };
+    Ok(dur.saturating_sub(now))
+}
+
+/// Operate on the futex at `uaddr`.
+///
+/// # Arguments
+/// * `uaddr` - The address of the futex word, aligned to 4 bytes
+/// * `futex_op` - The operation, optionally with `FUTEX_PRIVATE_FLAG` and
+///   `FUTEX_CLOCK_REALTIME`
+/// * `val` - The expected value for waits, or the number of waiters to wake up
+/// * `timeout` - The timeout for waits (can be NULL), or the number of
+///   waiters to requeue
+/// * `uaddr2` - The futex to requeue the waiters to
+/// * `val3` - The bitset for bitset operations, or the expected value for
+///   `FUTEX_CMP_REQUEUE`
+pub(crate) fn sys_futex(
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: UserPtr<timespec>,
+    uaddr2: usize,
+    val3: u32,
+) -> isize {
+    syscall_body!(sys_futex, {
+        let private = futex_op & FUTEX_PRIVATE_FLAG != 0;
+        let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+        let op = FutexOp::try_from(futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME))
+            .map_err(|_| LinuxError::ENOSYS)?;
+        if realtime && !matches!(op, FutexOp::Wait | FutexOp::WaitBitset) {
+            return Err(LinuxError::ENOSYS);
+        }
+        debug!(
+            "sys_futex <= uaddr: {:#x}, op: {:?}, val: {}",
+            uaddr, op, val
+        );
+
+        let curr = current();
+        let process = &curr.task_ext().process;
+        match op {
+            FutexOp::Wait | FutexOp::WaitBitset => {
+                let bitset = if op == FutexOp::Wait {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let timeout = if timeout.is_null() {
+                    None
+                } else {
+                    Some(read_timeout(timeout, op == FutexOp::WaitBitset, realtime)?)
+                };
+                futex_wait(process, uaddr, val, private, bitset, timeout)?;
+                Ok(0)
+            }
+            FutexOp::Wake | FutexOp::WakeBitset => {
+                let bitset = if op == FutexOp::Wake {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                futex_wake(process, uaddr, private, bitset, val as usize)
+            }
+            FutexOp::Requeue | FutexOp::CmpRequeue => {
+                let nr_requeue = timeout.address() as u32;
+                if (val as i32) < 0 || (nr_requeue as i32) < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let cmp = (op == FutexOp::CmpRequeue).then_some(val3);
+                let (woken, requeued) = futex_requeue(
+                    process,
+                    uaddr,
+                    uaddr2,
+                    private,
+                    val as usize,
+                    nr_requeue as usize,
+                    cmp,
+                )?;
+                if op == FutexOp::Requeue {
+                    Ok(woken)
+                } else {
+                    Ok(woken + requeued)
+                }
+            }
+        }
+    })
+}
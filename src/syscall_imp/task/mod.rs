@@ -1,5 +1,13 @@
+mod clone;
+mod execve;
+mod futex;
 mod schedule;
 mod thread;
+mod wait;
 
+pub(crate) use self::clone::*;
+pub(crate) use self::execve::*;
+pub(crate) use self::futex::*;
 pub(crate) use self::schedule::*;
 pub(crate) use self::thread::*;
+pub(crate) use self::wait::*;
@@ -1,12 +1,33 @@
 use arceos_posix_api as api;
+use axerrno::LinuxError;
+
+use crate::{ptr::UserPtr, syscall_body};
 
 pub(crate) fn sys_sched_yield() -> i32 {
     api::sys_sched_yield()
 }
 
 pub(crate) fn sys_nanosleep(
-    req: *const api::ctypes::timespec,
-    rem: *mut api::ctypes::timespec,
+    req: UserPtr<api::ctypes::timespec>,
+    rem: UserPtr<api::ctypes::timespec>,
 ) -> i32 {
-    unsafe { api::sys_nanosleep(req, rem) }
+    syscall_body!(sys_nanosleep, {
+        let req = req.read()?;
+        // The remaining time is copied out afterwards, since no user memory
+        // may be accessed while sleeping.
+        let mut remain = api::ctypes::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let remain_ptr = if rem.is_null() {
+            core::ptr::null_mut()
+        } else {
+            &mut remain as *mut _
+        };
+        let ret = unsafe { api::sys_nanosleep(&req, remain_ptr) };
+        if ret == -LinuxError::EINTR.code() && !rem.is_null() {
+            rem.write(remain)?;
+        }
+        Ok(ret)
+    })
 }
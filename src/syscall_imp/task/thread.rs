@@ -1,8 +1,13 @@
-use arceos_posix_api::{self as api};
 use axtask::{current, TaskExtRef};
 use num_enum::TryFromPrimitive;
 
-use crate::syscall_body;
+use axerrno::LinuxError;
+
+use crate::{
+    ptr::UserPtr,
+    syscall_body,
+    task::{exit_current, find_process, wstatus_exited},
+};
 
 /// ARCH_PRCTL codes
 ///
@@ -26,35 +31,80 @@ enum ArchPrctlCode {
 }
 
 pub(crate) fn sys_getpid() -> i32 {
-    api::sys_getpid()
+    syscall_body!(sys_getpid, Ok(current().task_ext().process.pid()))
 }
 
-pub(crate) fn sys_exit(status: i32) -> ! {
-    let curr = current();
-    let clear_child_tid = curr.task_ext().clear_child_tid() as *mut i32;
-    if !clear_child_tid.is_null() {
-        // TODO: check whether the address is valid
-        unsafe {
-            // TODO: Encapsulate all operations that access user-mode memory into a unified function
-            *(clear_child_tid) = 0;
+pub(crate) fn sys_getppid() -> i32 {
+    syscall_body!(sys_getppid, Ok(current().task_ext().process.ppid()))
+}
+
+/// Get the process group ID of the process `pid`, or of the calling process
+/// if `pid` is 0.
+pub(crate) fn sys_getpgid(pid: i32) -> i32 {
+    syscall_body!(sys_getpgid, {
+        let curr = current();
+        let process = &curr.task_ext().process;
+        if pid == 0 || pid as u64 == process.pid() {
+            return Ok(process.pgid());
         }
-        // TODO: wake up threads, which are blocked by futex, and waiting for the address pointed by clear_child_tid
-    }
-    axtask::exit(status);
+        find_process(pid as _)
+            .map(|p| p.pgid())
+            .ok_or(LinuxError::ESRCH)
+    })
+}
+
+/// Move the process `pid` (or the calling process if `pid` is 0) to the
+/// process group `pgid` (or the group whose ID is the PID of the process if
+/// `pgid` is 0).
+///
+/// Only the calling process and its children can be moved.
+pub(crate) fn sys_setpgid(pid: i32, pgid: i32) -> i32 {
+    syscall_body!(sys_setpgid, {
+        if pid < 0 || pgid < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let target = if pid == 0 || pid as u64 == process.pid() {
+            process.clone()
+        } else {
+            find_process(pid as _)
+                .filter(|p| p.ppid() == process.pid())
+                .ok_or(LinuxError::ESRCH)?
+        };
+        let pgid = if pgid == 0 { target.pid() } else { pgid as u64 };
+        target.set_pgid(pgid);
+        Ok(0)
+    })
+}
+
+pub(crate) fn sys_gettid() -> i32 {
+    syscall_body!(sys_gettid, Ok(current().id().as_u64()))
+}
+
+pub(crate) fn sys_exit(status: i32) -> ! {
+    exit_current(status);
 }
 
+/// Terminate all threads of the calling process.
+///
+/// The other threads exit when they return from their current syscall, and
+/// the last exiting thread reports `status` to the parent.
 pub(crate) fn sys_exit_group(status: i32) -> ! {
-    warn!("Temporarily replace sys_exit_group with sys_exit");
-    axtask::exit(status);
+    current()
+        .task_ext()
+        .process
+        .group_exit(wstatus_exited(status));
+    exit_current(status);
 }
 
 /// To set the clear_child_tid field in the task extended data.
 ///
 /// The set_tid_address() always succeeds
-pub(crate) fn sys_set_tid_address(tid_ptd: *const i32) -> isize {
+pub(crate) fn sys_set_tid_address(tid_ptd: UserPtr<i32>) -> isize {
     syscall_body!(sys_set_tid_address, {
         let curr = current();
-        curr.task_ext().set_clear_child_tid(tid_ptd as _);
+        curr.task_ext().set_clear_child_tid(tid_ptd.address() as _);
         Ok(curr.id().as_u64() as isize)
     })
 }
@@ -64,7 +114,6 @@ pub(crate) fn sys_arch_prctl(code: i32, addr: u64) -> isize {
     use axerrno::LinuxError;
     syscall_body!(sys_arch_prctl, {
         match ArchPrctlCode::try_from(code) {
-            // TODO: check the legality of the address
             Ok(ArchPrctlCode::SetFs) => {
                 unsafe {
                     axhal::arch::write_thread_pointer(addr as usize);
@@ -72,9 +121,8 @@ pub(crate) fn sys_arch_prctl(code: i32, addr: u64) -> isize {
                 Ok(0)
             }
             Ok(ArchPrctlCode::GetFs) => {
-                unsafe {
-                    *(addr as *mut u64) = axhal::arch::read_thread_pointer() as u64;
-                }
+                UserPtr::<u64>::from(addr as usize)
+                    .write(axhal::arch::read_thread_pointer() as u64)?;
                 Ok(0)
             }
             Ok(ArchPrctlCode::SetGs) => {
@@ -84,9 +132,8 @@ pub(crate) fn sys_arch_prctl(code: i32, addr: u64) -> isize {
                 Ok(0)
             }
             Ok(ArchPrctlCode::GetGs) => {
-                unsafe {
-                    *(addr as *mut u64) = x86::msr::rdmsr(x86::msr::IA32_KERNEL_GSBASE);
-                }
+                UserPtr::<u64>::from(addr as usize)
+                    .write(unsafe { x86::msr::rdmsr(x86::msr::IA32_KERNEL_GSBASE) })?;
                 Ok(0)
             }
             _ => Err(LinuxError::ENOSYS),
@@ -0,0 +1,279 @@
+use alloc::sync::Arc;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    ptr::UserPtr,
+    syscall_body,
+    task::{
+        wstatus_stopped, ChildEvent, CpuTime, Process, WaitEvents, WaitTarget, CLD_CONTINUED,
+        CLD_STOPPED, WSTATUS_CONTINUED,
+    },
+};
+
+bitflags::bitflags! {
+    /// Options for sys_wait4 and sys_waitid
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/wait.h>
+    #[derive(Debug, Clone, Copy)]
+    struct WaitOptions: u32 {
+        /// Return immediately if no child has exited.
+        const WNOHANG = 0x0000_0001;
+        /// Also return if a child has stopped.
+        const WUNTRACED = 0x0000_0002;
+        /// Wait for children that have terminated.
+        const WEXITED = 0x0000_0004;
+        /// Also return if a stopped child has been resumed.
+        const WCONTINUED = 0x0000_0008;
+        /// Leave the child in a waitable state.
+        const WNOWAIT = 0x0100_0000;
+        /// Do not wait for children of other threads in the same thread group.
+        const __WNOTHREAD = 0x2000_0000;
+        /// Wait for all children, regardless of type.
+        const __WALL = 0x4000_0000;
+        /// Wait for "clone" children only.
+        const __WCLONE = 0x8000_0000;
+    }
+}
+
+/// The `idtype` argument of sys_waitid
+#[derive(Debug, Eq, PartialEq, num_enum::TryFromPrimitive)]
+#[repr(u32)]
+enum WaitIdType {
+    /// Wait for any child.
+    All = 0,
+    /// Wait for the child whose process ID matches `id`.
+    Pid = 1,
+    /// Wait for any child whose process group ID matches `id`.
+    Pgid = 2,
+}
+
+/// The `si_code` of SIGCHLD when the child has exited.
+const CLD_EXITED: i32 = 1;
+/// The `si_code` of SIGCHLD when the child was killed by a signal.
+const CLD_KILLED: i32 = 2;
+/// The signal number of SIGCHLD.
+const SIGCHLD: i32 = 17;
+/// The signal number of SIGCONT.
+const SIGCONT: i32 = 18;
+
+/// The number of clock ticks per second, in which `si_utime` and `si_stime`
+/// are measured.
+const USER_HZ: u128 = 100;
+
+/// `struct timeval`, used in [`Rusage`].
+#[repr(C)]
+#[derive(Default)]
+struct TimeVal {
+    tv_sec: i64,
+    tv_usec: i64,
+}
+
+impl From<core::time::Duration> for TimeVal {
+    fn from(value: core::time::Duration) -> Self {
+        Self {
+            tv_sec: value.as_secs() as _,
+            tv_usec: value.subsec_micros() as _,
+        }
+    }
+}
+
+/// Resource usage reported by sys_wait4
+///
+/// Only the CPU times are filled, the other fields are always zero.
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct Rusage {
+    ru_utime: TimeVal,
+    ru_stime: TimeVal,
+    ru_maxrss: i64,
+    ru_ixrss: i64,
+    ru_idrss: i64,
+    ru_isrss: i64,
+    ru_minflt: i64,
+    ru_majflt: i64,
+    ru_nswap: i64,
+    ru_inblock: i64,
+    ru_oublock: i64,
+    ru_msgsnd: i64,
+    ru_msgrcv: i64,
+    ru_nsignals: i64,
+    ru_nvcsw: i64,
+    ru_nivcsw: i64,
+}
+
+impl From<&CpuTime> for Rusage {
+    fn from(value: &CpuTime) -> Self {
+        Self {
+            ru_utime: value.utime().into(),
+            ru_stime: value.stime().into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The part of `siginfo_t` filled by sys_waitid, padded to the full size.
+#[repr(C)]
+pub(crate) struct WaitIdInfo {
+    si_signo: i32,
+    si_errno: i32,
+    si_code: i32,
+    _pad: i32,
+    si_pid: i32,
+    si_uid: u32,
+    si_status: i32,
+    _pad2: i32,
+    si_utime: i64,
+    si_stime: i64,
+    _rest: [u8; 80],
+}
+
+/// Wait for a child matching `target` to have an event in `events`.
+///
+/// Returns `None` if `WNOHANG` is set and no child has such an event yet.
+/// Unless `WNOWAIT` is set, an exited child is reaped, and the other events
+/// are reported only once.
+fn wait_child(
+    target: WaitTarget,
+    events: WaitEvents,
+    options: WaitOptions,
+) -> LinuxResult<Option<(Arc<Process>, ChildEvent)>> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    loop {
+        let seq = process.child_exit_seq();
+        if let Some((child, event)) = process.find_child_event(target, events)? {
+            // Another thread may have reaped the child or taken the event in
+            // the meantime.
+            let taken = match event {
+                _ if options.contains(WaitOptions::WNOWAIT) => true,
+                ChildEvent::Exited => process.reap_child(&child),
+                _ => child.take_event(event),
+            };
+            if taken {
+                return Ok(Some((child, event)));
+            }
+            continue;
+        }
+        if options.contains(WaitOptions::WNOHANG) {
+            return Ok(None);
+        }
+        process.wait_child_exit(seq)?;
+    }
+}
+
+/// Wait for a child process to exit and reap it, or to stop or continue
+/// with `WUNTRACED` or `WCONTINUED`.
+///
+/// # Arguments
+/// * `pid` - The child to wait for: -1 for any child, 0 for any child in the
+///   same process group, `< -1` for any child in the process group `-pid`,
+///   or `> 0` for the child with the given PID
+/// * `wstatus` - Where to store the status of the child, can be NULL
+/// * `options` - A combination of `WNOHANG`, `WUNTRACED` and `WCONTINUED`
+/// * `rusage` - Where to store the resource usage of the child, can be NULL
+pub(crate) fn sys_wait4(
+    pid: i32,
+    wstatus: UserPtr<i32>,
+    options: u32,
+    rusage: UserPtr<Rusage>,
+) -> isize {
+    syscall_body!(sys_wait4, {
+        let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+        if options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT) {
+            return Err(LinuxError::EINVAL);
+        }
+        let target = match pid {
+            -1 => WaitTarget::Any,
+            0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+            pid if pid > 0 => WaitTarget::Pid(pid as _),
+            pid => WaitTarget::Pgid(-(pid as i64) as _),
+        };
+        let events = WaitEvents {
+            exited: true,
+            stopped: options.contains(WaitOptions::WUNTRACED),
+            continued: options.contains(WaitOptions::WCONTINUED),
+        };
+        let Some((child, event)) = wait_child(target, events, options)? else {
+            return Ok(0);
+        };
+        if !wstatus.is_null() {
+            let status = match event {
+                ChildEvent::Exited => child.exit_code(),
+                ChildEvent::Stopped(signo) => wstatus_stopped(signo),
+                ChildEvent::Continued => WSTATUS_CONTINUED,
+            };
+            wstatus.write(status)?;
+        }
+        if !rusage.is_null() {
+            rusage.write(Rusage::from(&child.total_cpu_time()))?;
+        }
+        Ok(child.pid() as isize)
+    })
+}
+
+/// Wait for a child process to change state, with finer control than
+/// sys_wait4.
+///
+/// # Arguments
+/// * `idtype` - `P_ALL`, `P_PID` or `P_PGID`
+/// * `id` - The PID or process group ID to wait for, depending on `idtype`
+/// * `infop` - Where to store the information about the child
+/// * `options` - At least one of `WEXITED`, `WSTOPPED` and `WCONTINUED`,
+///   and optionally `WNOHANG` and `WNOWAIT`
+/// * `rusage` - Where to store the resource usage of the child, can be NULL
+pub(crate) fn sys_waitid(
+    idtype: u32,
+    id: i32,
+    infop: UserPtr<WaitIdInfo>,
+    options: u32,
+    rusage: UserPtr<Rusage>,
+) -> isize {
+    syscall_body!(sys_waitid, {
+        let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+        let events = WaitEvents {
+            exited: options.contains(WaitOptions::WEXITED),
+            // `WSTOPPED` is the same as `WUNTRACED`.
+            stopped: options.contains(WaitOptions::WUNTRACED),
+            continued: options.contains(WaitOptions::WCONTINUED),
+        };
+        if !events.exited && !events.stopped && !events.continued {
+            return Err(LinuxError::EINVAL);
+        }
+        let target = match WaitIdType::try_from(idtype).map_err(|_| LinuxError::EINVAL)? {
+            WaitIdType::All => WaitTarget::Any,
+            WaitIdType::Pid => WaitTarget::Pid(id as _),
+            WaitIdType::Pgid if id == 0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+            WaitIdType::Pgid => WaitTarget::Pgid(id as _),
+        };
+        let child = wait_child(target, events, options)?;
+        if !infop.is_null() {
+            let mut info: WaitIdInfo = unsafe { core::mem::zeroed() };
+            if let Some((child, event)) = &child {
+                let wstatus = child.exit_code();
+                let time = child.total_cpu_time();
+                info.si_signo = SIGCHLD;
+                info.si_pid = child.pid() as _;
+                (info.si_code, info.si_status) = match *event {
+                    ChildEvent::Stopped(signo) => (CLD_STOPPED, signo as i32),
+                    ChildEvent::Continued => (CLD_CONTINUED, SIGCONT),
+                    ChildEvent::Exited if wstatus & 0x7f == 0 => {
+                        (CLD_EXITED, (wstatus >> 8) & 0xff)
+                    }
+                    ChildEvent::Exited => (CLD_KILLED, wstatus & 0x7f),
+                };
+                info.si_utime = (time.utime().as_millis() * USER_HZ / 1000) as _;
+                info.si_stime = (time.stime().as_millis() * USER_HZ / 1000) as _;
+            }
+            infop.write(info)?;
+        }
+        if !rusage.is_null() {
+            let usage = child.map_or_else(Rusage::default, |(child, _)| {
+                Rusage::from(&child.total_cpu_time())
+            });
+            rusage.write(usage)?;
+        }
+        Ok(0)
+    })
+}
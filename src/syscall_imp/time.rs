@@ -1,5 +1,17 @@
 use arceos_posix_api as api;
 
-pub(crate) fn sys_clock_gettime(clock_id: i32, tp: *mut api::ctypes::timespec) -> i32 {
-    unsafe { api::sys_clock_gettime(clock_id, tp) }
+use crate::{ptr::UserPtr, syscall_body};
+
+pub(crate) fn sys_clock_gettime(clock_id: i32, tp: UserPtr<api::ctypes::timespec>) -> i32 {
+    syscall_body!(sys_clock_gettime, {
+        let mut ts = api::ctypes::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let ret = unsafe { api::sys_clock_gettime(clock_id, &mut ts) };
+        if ret == 0 {
+            tp.write(ts)?;
+        }
+        Ok(ret)
+    })
 }
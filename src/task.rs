@@ -1,37 +1,755 @@
-use alloc::sync::Arc;
-use core::sync::atomic::AtomicU64;
+use alloc::{
+    collections::btree_map::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
 
+use axerrno::{LinuxError, LinuxResult};
 use axhal::arch::UspaceContext;
-use axmm::AddrSpace;
 use axsync::Mutex;
-use axtask::{AxTaskRef, TaskExtRef, TaskInner};
+use axtask::{AxTaskRef, TaskExtRef, TaskInner, WaitQueue};
+
+use crate::{
+    fs::{self, FdTable},
+    futex,
+    mm::UserSpace,
+    ptr::UserPtr,
+    signal::{
+        send_signal_to_process, PendingSignals, SigActionFlags, SigInfo, SignalActions, SignalSet,
+        SIGCHLD, SIGCONT, SI_KERNEL,
+    },
+};
+
+/// The type of process IDs and thread IDs.
+///
+/// Like Linux, the PID of a process is the TID of its first thread, so both
+/// are allocated from the task ID space of `axtask`.
+pub type Pid = u64;
+
+/// All live processes (including zombies), indexed by PID.
+static PROCESS_TABLE: Mutex<BTreeMap<Pid, Weak<Process>>> = Mutex::new(BTreeMap::new());
+
+/// Encode the `wstatus` of a process that exited normally with `exit_code`.
+pub const fn wstatus_exited(exit_code: i32) -> i32 {
+    (exit_code & 0xff) << 8
+}
+
+/// Encode the `wstatus` of a process that was terminated by signal `signo`.
+pub const fn wstatus_signaled(signo: u32) -> i32 {
+    (signo & 0x7f) as i32
+}
+
+/// Encode the `wstatus` of a process that was stopped by signal `signo`.
+pub const fn wstatus_stopped(signo: u32) -> i32 {
+    ((signo & 0xff) << 8) as i32 | 0x7f
+}
+
+/// The `wstatus` of a stopped process resumed by SIGCONT.
+pub const WSTATUS_CONTINUED: i32 = 0xffff;
+
+/// The `si_code` of SIGCHLD when the child has stopped.
+pub const CLD_STOPPED: i32 = 5;
+/// The `si_code` of SIGCHLD when the stopped child has continued.
+pub const CLD_CONTINUED: i32 = 6;
+
+/// The CPU time consumed in user and kernel mode.
+pub struct CpuTime {
+    utime_ns: AtomicU64,
+    stime_ns: AtomicU64,
+}
+
+impl CpuTime {
+    const fn new() -> Self {
+        Self {
+            utime_ns: AtomicU64::new(0),
+            stime_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// The time spent in user mode.
+    pub fn utime(&self) -> Duration {
+        Duration::from_nanos(self.utime_ns.load(Ordering::Relaxed))
+    }
+
+    /// The time spent in kernel mode.
+    pub fn stime(&self) -> Duration {
+        Duration::from_nanos(self.stime_ns.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, other: &CpuTime) {
+        self.utime_ns
+            .fetch_add(other.utime_ns.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.stime_ns
+            .fetch_add(other.stime_ns.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+/// A change of the state of a child process that `wait4` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildEvent {
+    /// The child has exited, and can be reaped.
+    Exited,
+    /// The child has been stopped by the signal.
+    Stopped(u32),
+    /// The stopped child has been resumed by SIGCONT.
+    Continued,
+}
+
+/// Which kinds of [`ChildEvent`] a thread waits for.
+#[derive(Debug, Clone, Copy)]
+pub struct WaitEvents {
+    pub exited: bool,
+    pub stopped: bool,
+    pub continued: bool,
+}
+
+/// Which children a thread waits for.
+#[derive(Debug, Clone, Copy)]
+pub enum WaitTarget {
+    /// Any child process.
+    Any,
+    /// The child process with the given PID.
+    Pid(Pid),
+    /// Any child process in the given process group.
+    Pgid(Pid),
+}
+
+/// The program that a process runs, as given to `execve`.
+#[derive(Clone, Default)]
+pub struct ExecInfo {
+    /// The absolute path of the program.
+    pub path: String,
+    /// The arguments, including `argv[0]`.
+    pub args: Vec<Vec<u8>>,
+    /// The environment variables.
+    pub envs: Vec<Vec<u8>>,
+}
+
+/// A process, i.e. a group of threads sharing the same address space.
+pub struct Process {
+    /// The process ID.
+    pid: Pid,
+    /// The process group ID.
+    pgid: AtomicU64,
+    /// The parent process. It is empty for processes spawned by the kernel,
+    /// and for the orphans of such processes.
+    parent: Mutex<Weak<Process>>,
+    /// Whether the process is an orphan adopted by its reaper, which
+    /// releases it as soon as it exits. Accessed with `parent` locked.
+    adopted: AtomicBool,
+    /// The child processes.
+    children: Mutex<BTreeMap<Pid, Arc<Process>>>,
+    /// The threads of this process, indexed by TID.
+    threads: Mutex<BTreeMap<Pid, AxTaskRef>>,
+    /// The virtual memory address space.
+    pub aspace: Arc<Mutex<UserSpace>>,
+    /// The signal actions, which may be shared with other processes.
+    pub signal_actions: Arc<Mutex<SignalActions>>,
+    /// The file descriptor table, which may be shared with other processes.
+    pub fd_table: Arc<Mutex<FdTable>>,
+    /// The absolute path of the current directory, which may be shared with
+    /// other processes.
+    pub cwd: Arc<Mutex<String>>,
+    /// The program that the process runs.
+    exec_info: Mutex<ExecInfo>,
+    /// The time when the process was created, since boot.
+    start_time: Duration,
+    /// The signals sent to the whole process.
+    pub pending_signals: PendingSignals,
+    /// The signal sent to the parent when the process exits, or 0 for none.
+    exit_signal: AtomicU32,
+    /// The exit status of the process, encoded as the `wstatus` of `wait4`.
+    exit_code: AtomicI32,
+    /// Whether the exit status has been set by `exit_group`.
+    group_exiting: AtomicBool,
+    /// Whether all threads of the process have exited.
+    zombie: AtomicBool,
+    /// Whether the process is stopped by a signal.
+    stopped: AtomicBool,
+    /// The signal that stopped the process, until it is reported to the
+    /// parent by `wait4`, or 0.
+    stop_report: AtomicU32,
+    /// Whether the process has been resumed by SIGCONT and it has not been
+    /// reported to the parent yet.
+    continue_report: AtomicBool,
+    /// The wait queue of the threads of the stopped process.
+    stop_wq: WaitQueue,
+    /// Whether the process has called `execve` or exited, which wakes up
+    /// the parent suspended by `vfork`.
+    vfork_done: AtomicBool,
+    /// The wait queue of the parent suspended by `vfork`.
+    vfork_wq: WaitQueue,
+    /// The CPU time consumed by the exited threads.
+    cpu_time: CpuTime,
+    /// The CPU time consumed by the reaped children and their descendants.
+    children_cpu_time: CpuTime,
+    /// Increased every time a child exits, stops or continues, so that
+    /// waiters can tell whether they have missed an event.
+    child_exit_seq: AtomicUsize,
+    /// The wait queue of the threads waiting for children to exit, stop or
+    /// continue.
+    child_exit_wq: WaitQueue,
+    /// The wait queue notified every time a thread of the process exits.
+    thread_exit_wq: WaitQueue,
+}
+
+impl Process {
+    /// Create a new process with the given PID.
+    ///
+    /// The process is a child of `parent`, or has no parent if it is created
+    /// by the kernel. SIGCHLD is sent to the parent when the process exits,
+    /// unless changed by [`Process::set_exit_signal`].
+    pub fn new(
+        pid: Pid,
+        parent: Option<&Arc<Process>>,
+        aspace: Arc<Mutex<UserSpace>>,
+        signal_actions: Arc<Mutex<SignalActions>>,
+        fd_table: Arc<Mutex<FdTable>>,
+        cwd: Arc<Mutex<String>>,
+    ) -> Arc<Self> {
+        let process = Arc::new(Self {
+            pid,
+            pgid: AtomicU64::new(parent.map_or(pid, |p| p.pgid())),
+            parent: Mutex::new(parent.map_or(Weak::new(), Arc::downgrade)),
+            adopted: AtomicBool::new(false),
+            children: Mutex::new(BTreeMap::new()),
+            threads: Mutex::new(BTreeMap::new()),
+            aspace,
+            signal_actions,
+            fd_table,
+            cwd,
+            exec_info: Mutex::new(ExecInfo::default()),
+            start_time: axhal::time::monotonic_time(),
+            pending_signals: PendingSignals::new(),
+            exit_signal: AtomicU32::new(SIGCHLD),
+            exit_code: AtomicI32::new(0),
+            group_exiting: AtomicBool::new(false),
+            zombie: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            stop_report: AtomicU32::new(0),
+            continue_report: AtomicBool::new(false),
+            stop_wq: WaitQueue::new(),
+            vfork_done: AtomicBool::new(false),
+            vfork_wq: WaitQueue::new(),
+            cpu_time: CpuTime::new(),
+            children_cpu_time: CpuTime::new(),
+            child_exit_seq: AtomicUsize::new(0),
+            child_exit_wq: WaitQueue::new(),
+            thread_exit_wq: WaitQueue::new(),
+        });
+        PROCESS_TABLE.lock().insert(pid, Arc::downgrade(&process));
+        process
+    }
+
+    /// The process ID.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// The parent process, if it is still alive.
+    pub fn parent(&self) -> Option<Arc<Process>> {
+        self.parent.lock().upgrade()
+    }
+
+    /// The process that adopts the orphans of this process: the process
+    /// spawned by the kernel that it descends from, like `init` on Linux, or
+    /// `None` if it is that process.
+    fn reaper(&self) -> Option<Arc<Process>> {
+        let mut reaper = self.parent()?;
+        while let Some(parent) = reaper.parent() {
+            reaper = parent;
+        }
+        Some(reaper)
+    }
+
+    /// Hand the children of the exiting process over to its reaper, which
+    /// releases the zombies among them right away. They have no parent
+    /// afterwards if the process has no reaper.
+    fn reparent_children(&self) {
+        let children = core::mem::take(&mut *self.children.lock());
+        let reaper = self.reaper();
+        for child in children.into_values() {
+            let mut parent = child.parent.lock();
+            let Some(reaper) = &reaper else {
+                *parent = Weak::new();
+                continue;
+            };
+            *parent = Arc::downgrade(reaper);
+            child.adopted.store(true, Ordering::Relaxed);
+            if child.is_zombie() {
+                reaper.children_cpu_time.add(&child.total_cpu_time());
+            } else {
+                reaper.children.lock().insert(child.pid, child.clone());
+            }
+        }
+    }
+
+    /// The PID of the parent process, or 0 if the process has no parent.
+    pub fn ppid(&self) -> Pid {
+        self.parent().map_or(0, |p| p.pid)
+    }
+
+    /// The process group ID.
+    pub fn pgid(&self) -> Pid {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    /// Move the process to the process group `pgid`.
+    pub fn set_pgid(&self, pgid: Pid) {
+        self.pgid.store(pgid, Ordering::Release);
+    }
+
+    /// The program that the process runs.
+    pub fn exec_info(&self) -> ExecInfo {
+        self.exec_info.lock().clone()
+    }
+
+    /// Record the program that the process runs, e.g. after `execve`.
+    pub fn set_exec_info(&self, info: ExecInfo) {
+        *self.exec_info.lock() = info;
+    }
+
+    /// The name of the process, which is the file name of the program
+    /// truncated to 15 bytes, as `comm` on Linux.
+    pub fn name(&self) -> String {
+        let info = self.exec_info.lock();
+        let name = info.path.rsplit('/').next().unwrap_or_default();
+        let mut len = name.len().min(15);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        name[..len].into()
+    }
+
+    /// The time when the process was created, since boot.
+    pub fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    /// The signal sent to the parent when the process exits, or 0 for none.
+    pub fn exit_signal(&self) -> u32 {
+        self.exit_signal.load(Ordering::Acquire)
+    }
+
+    /// Set the signal sent to the parent when the process exits, or 0 for
+    /// none.
+    pub fn set_exit_signal(&self, signo: u32) {
+        self.exit_signal.store(signo, Ordering::Release);
+    }
+
+    /// Whether all threads of the process have exited.
+    pub fn is_zombie(&self) -> bool {
+        self.zombie.load(Ordering::Acquire)
+    }
+
+    /// The exit status of the process, encoded as the `wstatus` of `wait4`.
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.load(Ordering::Acquire)
+    }
+
+    /// Terminate the whole process with the given status, as `exit_group`
+    /// does.
+    ///
+    /// `wstatus` is encoded as the `wstatus` of `wait4`. The status set here
+    /// takes precedence over the one of the last exiting thread. All threads
+    /// except the current one are asked to exit, see [`exit_if_killed`].
+    pub fn group_exit(&self, wstatus: i32) {
+        if !self.group_exiting.swap(true, Ordering::AcqRel) {
+            self.exit_code.store(wstatus, Ordering::Release);
+        }
+        self.kill_other_threads();
+    }
+
+    /// Ask all threads except the current one to exit, and wake up those
+    /// waiting for children or futexes so that they can notice it.
+    ///
+    /// The threads exit the next time they return from a syscall or a page
+    /// fault.
+    fn kill_other_threads(&self) {
+        let curr_tid = axtask::current().id().as_u64();
+        for (&tid, task) in self.threads.lock().iter() {
+            if tid != curr_tid {
+                task.task_ext().killed.store(true, Ordering::Release);
+            }
+        }
+        self.interrupt_waiters();
+    }
+
+    /// Wake up the threads of the process waiting for children or futexes,
+    /// so that they can check whether they are killed or have signals to
+    /// handle. The threads of a stopped process check whether they are
+    /// killed.
+    pub fn interrupt_waiters(&self) {
+        self.child_exit_wq.notify_all(false);
+        self.stop_wq.notify_all(false);
+        futex::interrupt_waiters();
+    }
+
+    /// Whether the process is stopped by a signal.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stop the process by the signal `signo`, and notify the parent.
+    ///
+    /// The threads stop the next time they return from a syscall or a page
+    /// fault, see [`Process::wait_while_stopped`].
+    pub fn stop(&self, signo: u32) {
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.continue_report.store(false, Ordering::Release);
+        self.stop_report.store(signo, Ordering::Release);
+        self.notify_parent_job_control(CLD_STOPPED, signo);
+    }
+
+    /// Resume the process stopped by a signal, and notify the parent.
+    pub fn resume(&self) {
+        if !self.stopped.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        self.stop_report.store(0, Ordering::Release);
+        self.continue_report.store(true, Ordering::Release);
+        self.stop_wq.notify_all(false);
+        self.notify_parent_job_control(CLD_CONTINUED, SIGCONT);
+    }
+
+    /// Block the current thread while the process is stopped, unless the
+    /// thread is killed.
+    pub fn wait_while_stopped(&self) {
+        let curr = axtask::current();
+        self.stop_wq
+            .wait_until(|| !self.is_stopped() || curr.task_ext().is_killed());
+    }
+
+    /// Wake up the parent waiting for the process to stop or continue, and
+    /// send it SIGCHLD with `code` and `status` unless the parent has set
+    /// `SA_NOCLDSTOP`.
+    fn notify_parent_job_control(&self, code: i32, status: u32) {
+        let Some(parent) = self.parent() else {
+            return;
+        };
+        parent.child_exit_seq.fetch_add(1, Ordering::AcqRel);
+        parent.child_exit_wq.notify_all(false);
+        let nocldstop = parent
+            .signal_actions
+            .lock()
+            .get(SIGCHLD)
+            .flags
+            .contains(SigActionFlags::SA_NOCLDSTOP);
+        if !nocldstop {
+            let mut info = SigInfo::from_process(SIGCHLD, code, self.pid);
+            info.set_status(status as i32);
+            send_signal_to_process(&parent, info);
+        }
+    }
+
+    /// Terminate all other threads of the process for `execve`, and wait for
+    /// them to exit.
+    ///
+    /// Returns `false` if the current thread itself has been killed in the
+    /// meantime, e.g. by `exit_group` of another thread.
+    pub fn exec_kill_other_threads(&self) -> bool {
+        self.kill_other_threads();
+        let curr = axtask::current();
+        self.thread_exit_wq
+            .wait_until(|| self.thread_count() == 1 || curr.task_ext().is_killed());
+        !curr.task_ext().is_killed()
+    }
+
+    /// Wait until all threads of the process have exited, and return the
+    /// exit status encoded as the `wstatus` of `wait4`.
+    ///
+    /// It is used by the kernel to wait for the processes it spawned.
+    pub fn wait_exit(&self) -> i32 {
+        self.thread_exit_wq.wait_until(|| self.is_zombie());
+        self.exit_code()
+    }
+
+    /// The CPU time consumed by the threads of the process, both exited
+    /// and running.
+    pub fn cpu_time(&self) -> CpuTime {
+        let time = CpuTime::new();
+        time.add(&self.cpu_time);
+        for task in self.threads.lock().values() {
+            time.add(&task.task_ext().cpu_time);
+        }
+        time
+    }
+
+    /// The CPU time consumed by the reaped children and their descendants.
+    pub fn children_cpu_time(&self) -> &CpuTime {
+        &self.children_cpu_time
+    }
+
+    /// The CPU time consumed by the process itself and its reaped children.
+    pub fn total_cpu_time(&self) -> CpuTime {
+        let time = CpuTime::new();
+        time.add(&self.cpu_time);
+        time.add(&self.children_cpu_time);
+        time
+    }
+
+    /// Find a child process matching `target` with an event in `events` to
+    /// report.
+    ///
+    /// Returns `Ok(None)` if the matching children have no such event, or
+    /// `ECHILD` if no child matches.
+    pub fn find_child_event(
+        &self,
+        target: WaitTarget,
+        events: WaitEvents,
+    ) -> LinuxResult<Option<(Arc<Process>, ChildEvent)>> {
+        let children = self.children.lock();
+        let matched = children.values().filter(|child| match target {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(pid) => child.pid == pid,
+            WaitTarget::Pgid(pgid) => child.pgid() == pgid,
+        });
+        let mut found = false;
+        for child in matched {
+            let stop_signal = child.stop_report.load(Ordering::Acquire);
+            let event = if child.is_zombie() {
+                events.exited.then_some(ChildEvent::Exited)
+            } else if stop_signal != 0 {
+                events.stopped.then_some(ChildEvent::Stopped(stop_signal))
+            } else if child.continue_report.load(Ordering::Acquire) {
+                events.continued.then_some(ChildEvent::Continued)
+            } else {
+                None
+            };
+            if let Some(event) = event {
+                return Ok(Some((child.clone(), event)));
+            }
+            found = true;
+        }
+        if found {
+            Ok(None)
+        } else {
+            Err(LinuxError::ECHILD)
+        }
+    }
+
+    /// Mark the stop or continue event of the process as reported to the
+    /// parent. Returns `false` if it has been reported in the meantime.
+    pub fn take_event(&self, event: ChildEvent) -> bool {
+        match event {
+            ChildEvent::Exited => false,
+            ChildEvent::Stopped(signo) => self
+                .stop_report
+                .compare_exchange(signo, 0, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok(),
+            ChildEvent::Continued => self.continue_report.swap(false, Ordering::AcqRel),
+        }
+    }
+
+    /// Release a zombie child, adding its CPU time to the children's total.
+    pub fn reap_child(&self, child: &Process) -> bool {
+        if self.children.lock().remove(&child.pid).is_none() {
+            return false;
+        }
+        self.children_cpu_time.add(&child.total_cpu_time());
+        true
+    }
+
+    /// The number of child exits, stops and continues so far, used together
+    /// with [`Process::wait_child_exit`].
+    pub fn child_exit_seq(&self) -> usize {
+        self.child_exit_seq.load(Ordering::Acquire)
+    }
+
+    /// Wait until any child exits, stops or continues after
+    /// [`Process::child_exit_seq`] returned `seq`.
+    ///
+    /// Returns `EINTR` if the current thread is killed or receives a signal
+    /// while waiting.
+    pub fn wait_child_exit(&self, seq: usize) -> LinuxResult {
+        let curr = axtask::current();
+        self.child_exit_wq.wait_until(|| {
+            self.child_exit_seq.load(Ordering::Acquire) != seq || curr.task_ext().is_interrupted()
+        });
+        if self.child_exit_seq.load(Ordering::Acquire) == seq {
+            return Err(LinuxError::EINTR);
+        }
+        Ok(())
+    }
+
+    /// Wait until the process calls `execve` or exits.
+    pub fn wait_vfork_done(&self) {
+        self.vfork_wq
+            .wait_until(|| self.vfork_done.load(Ordering::Acquire));
+    }
+
+    /// Wake up the parent suspended by `vfork`, if any.
+    pub fn notify_vfork_done(&self) {
+        if !self.vfork_done.swap(true, Ordering::AcqRel) {
+            self.vfork_wq.notify_all(false);
+        }
+    }
+
+    /// Add a thread to the process.
+    ///
+    /// The process becomes visible to its parent when its first thread is
+    /// added.
+    fn add_thread(self: &Arc<Self>, task: AxTaskRef) {
+        let mut threads = self.threads.lock();
+        if threads.is_empty() {
+            if let Some(parent) = self.parent() {
+                parent.children.lock().insert(self.pid, self.clone());
+            }
+        }
+        threads.insert(task.id().as_u64(), task);
+    }
+
+    /// Remove a thread from the process.
+    ///
+    /// Returns `true` if it was the last thread of the process.
+    fn remove_thread(&self, tid: Pid) -> bool {
+        let mut threads = self.threads.lock();
+        threads.remove(&tid);
+        threads.is_empty()
+    }
+
+    /// The number of threads still running in the process.
+    pub fn thread_count(&self) -> usize {
+        self.threads.lock().len()
+    }
+
+    /// The threads still running in the process.
+    pub fn threads(&self) -> Vec<AxTaskRef> {
+        self.threads.lock().values().cloned().collect()
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        PROCESS_TABLE.lock().remove(&self.pid);
+    }
+}
+
+/// Find a live or zombie process by its PID.
+pub fn find_process(pid: Pid) -> Option<Arc<Process>> {
+    PROCESS_TABLE.lock().get(&pid).and_then(Weak::upgrade)
+}
+
+/// All live and zombie processes, sorted by PID.
+pub fn processes() -> Vec<Arc<Process>> {
+    PROCESS_TABLE
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Find a running thread by its TID.
+pub fn find_thread(tid: Pid) -> Option<AxTaskRef> {
+    processes()
+        .iter()
+        .find_map(|process| process.threads.lock().get(&tid).cloned())
+}
 
 /// Task extended data for the monolithic kernel.
 pub struct TaskExt {
-    /// The process ID.
-    pub proc_id: usize,
+    /// The process that the task belongs to.
+    pub process: Arc<Process>,
     /// The clear thread tid field
     ///
     /// See <https://manpages.debian.org/unstable/manpages-dev/set_tid_address.2.en.html#clear_child_tid>
     ///
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     clear_child_tid: AtomicU64,
+    /// The address that the thread writes its TID to when it starts, set by
+    /// `CLONE_CHILD_SETTID`, or 0.
+    set_child_tid: AtomicU64,
     /// The user space context.
     pub uctx: UspaceContext,
-    /// The virtual memory address space.
-    pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The CPU time consumed by the thread.
+    cpu_time: CpuTime,
+    /// The time of the last switch between user and kernel mode, in nanoseconds.
+    last_switch_ns: AtomicU64,
+    /// Whether the thread has been asked to exit by another thread of the
+    /// process.
+    killed: AtomicBool,
+    /// The signals blocked by the thread.
+    signal_mask: AtomicU64,
+    /// The signals sent to the thread.
+    pub pending_signals: PendingSignals,
+    /// The wait queue notified when a signal is sent to the thread.
+    pub signal_wq: WaitQueue,
 }
 
 impl TaskExt {
-    pub const fn new(uctx: UspaceContext, aspace: Arc<Mutex<AddrSpace>>) -> Self {
+    pub fn new(uctx: UspaceContext, process: Arc<Process>) -> Self {
         Self {
-            proc_id: 233,
+            process,
             uctx,
             clear_child_tid: AtomicU64::new(0),
-            aspace,
+            set_child_tid: AtomicU64::new(0),
+            cpu_time: CpuTime::new(),
+            last_switch_ns: AtomicU64::new(axhal::time::monotonic_time_nanos()),
+            killed: AtomicBool::new(false),
+            signal_mask: AtomicU64::new(0),
+            pending_signals: PendingSignals::new(),
+            signal_wq: WaitQueue::new(),
         }
     }
 
+    /// The signals blocked by the thread.
+    pub(crate) fn signal_mask(&self) -> SignalSet {
+        SignalSet(self.signal_mask.load(Ordering::Acquire))
+    }
+
+    /// Set the signals blocked by the thread.
+    pub(crate) fn set_signal_mask(&self, mask: SignalSet) {
+        self.signal_mask.store(mask.0, Ordering::Release);
+    }
+
+    /// Whether the thread has a pending signal that it does not block.
+    pub(crate) fn has_deliverable_signal(&self) -> bool {
+        let pending = self.pending_signals.set().0 | self.process.pending_signals.set().0;
+        pending & !self.signal_mask().0 != 0
+    }
+
+    /// Whether a blocking syscall of the thread should be interrupted,
+    /// because the thread is killed or has a signal to handle.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.is_killed() || self.has_deliverable_signal()
+    }
+
+    /// Whether the thread has been asked to exit by another thread of the
+    /// process.
+    pub(crate) fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Acquire)
+    }
+
+    /// The nanoseconds elapsed since the last switch between user and kernel mode.
+    fn switch_mode(&self) -> u64 {
+        let now = axhal::time::monotonic_time_nanos();
+        now.saturating_sub(self.last_switch_ns.swap(now, Ordering::Relaxed))
+    }
+
+    /// Account the time spent in user mode, when the thread enters the kernel.
+    pub(crate) fn enter_kernel(&self) {
+        self.cpu_time
+            .utime_ns
+            .fetch_add(self.switch_mode(), Ordering::Relaxed);
+    }
+
+    /// Account the time spent in kernel mode, when the thread returns to user
+    /// mode.
+    ///
+    /// The time blocked in the kernel is also counted as system time.
+    pub(crate) fn leave_kernel(&self) {
+        self.cpu_time
+            .stime_ns
+            .fetch_add(self.switch_mode(), Ordering::Relaxed);
+    }
+
     pub(crate) fn clear_child_tid(&self) -> u64 {
         self.clear_child_tid
             .load(core::sync::atomic::Ordering::Relaxed)
@@ -41,14 +759,35 @@ impl TaskExt {
         self.clear_child_tid
             .store(clear_child_tid, core::sync::atomic::Ordering::Relaxed);
     }
+
+    pub(crate) fn set_set_child_tid(&self, set_child_tid: u64) {
+        self.set_child_tid.store(set_child_tid, Ordering::Relaxed);
+    }
 }
 
 axtask::def_task_ext!(TaskExt);
 
-pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> AxTaskRef {
+/// Create a task that enters user space with `uctx`, without spawning it.
+///
+/// The thread pointer of the new task is set to `tls`. The task belongs to
+/// the process returned by `process`, which receives the ID of the new task
+/// so that it can be used as the PID of a new process.
+pub fn new_user_task(
+    name: &str,
+    mut uctx: UspaceContext,
+    tls: usize,
+    process: impl FnOnce(Pid) -> Arc<Process>,
+) -> TaskInner {
     let mut task = TaskInner::new(
         || {
             let curr = axtask::current();
+            let set_child_tid = curr.task_ext().set_child_tid.load(Ordering::Relaxed);
+            if set_child_tid != 0 {
+                // Like Linux, the new thread writes its TID into its own
+                // address space, and failures are ignored.
+                let _ =
+                    UserPtr::<u32>::from(set_child_tid as usize).write(curr.id().as_u64() as u32);
+            }
             let kstack_top = curr.kernel_stack_top().unwrap();
             info!(
                 "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
@@ -58,11 +797,108 @@ pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> Ax
             );
             unsafe { curr.task_ext().uctx.enter_uspace(kstack_top) };
         },
-        "userboot".into(),
+        name.into(),
         crate::config::KERNEL_STACK_SIZE,
     );
+    set_thread_pointer(&mut task, &mut uctx, tls);
+    let process = process(task.id().as_u64());
     task.ctx_mut()
-        .set_page_table_root(aspace.lock().page_table_root());
-    task.init_task_ext(TaskExt::new(uctx, aspace));
-    axtask::spawn_task(task)
+        .set_page_table_root(process.aspace.lock().page_table_root());
+    task.init_task_ext(TaskExt::new(uctx, process));
+    task
+}
+
+/// Set the thread pointer that the task uses in user space.
+#[cfg(target_arch = "x86_64")]
+fn set_thread_pointer(task: &mut TaskInner, _uctx: &mut UspaceContext, tls: usize) {
+    task.ctx_mut().fs_base = tls;
+}
+
+/// Set the thread pointer that the task uses in user space.
+#[cfg(target_arch = "aarch64")]
+fn set_thread_pointer(task: &mut TaskInner, _uctx: &mut UspaceContext, tls: usize) {
+    task.ctx_mut().tpidr_el0 = tls as u64;
+}
+
+/// Set the thread pointer that the task uses in user space.
+#[cfg(target_arch = "riscv64")]
+fn set_thread_pointer(_task: &mut TaskInner, uctx: &mut UspaceContext, tls: usize) {
+    uctx.regs.tp = tls;
+}
+
+/// Spawn a task created by [`new_user_task`] and add it to its process.
+pub fn spawn_user_task(task: TaskInner) -> AxTaskRef {
+    let task = axtask::spawn_task(task);
+    task.task_ext().process.add_thread(task.clone());
+    task
+}
+
+/// Exit the current thread if it has been killed by another thread of its
+/// process, e.g. by `exit_group` or `execve`.
+pub fn exit_if_killed() {
+    if axtask::current().task_ext().is_killed() {
+        exit_current(0);
+    }
+}
+
+/// Exit the current thread with the given status.
+///
+/// The word at `clear_child_tid` is cleared first, and the threads waiting
+/// on it are woken up. If it is the last thread of its process, the process
+/// becomes a zombie and keeps `exit_code` as its exit status unless
+/// `exit_group` has set one. Its memory is released, its children are
+/// orphaned and its parent is notified.
+pub fn exit_current(exit_code: i32) -> ! {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    ext.leave_kernel();
+    let process = &ext.process;
+    let clear_child_tid = ext.clear_child_tid();
+    if clear_child_tid != 0 {
+        // The address may be unmapped or read-only, which is just ignored.
+        let _ = UserPtr::<u32>::from(clear_child_tid as usize).write(0);
+        futex::wake_clear_child_tid(process, clear_child_tid as usize);
+    }
+    process.cpu_time.add(&ext.cpu_time);
+    let last_thread = process.remove_thread(curr.id().as_u64());
+    process.thread_exit_wq.notify_all(false);
+    if last_thread {
+        if !process.group_exiting.load(Ordering::Acquire) {
+            process
+                .exit_code
+                .store(wstatus_exited(exit_code), Ordering::Release);
+        }
+        // The address space may still be used by other processes created
+        // with `CLONE_VM`.
+        if Arc::strong_count(&process.aspace) == 1 {
+            process.aspace.lock().clear();
+        }
+        if Arc::strong_count(&process.fd_table) == 1 {
+            process.fd_table.lock().clear();
+        }
+        fs::lock::unlock_all(process.pid);
+        process.reparent_children();
+        // The parent is locked so that the process cannot be adopted in the
+        // meantime without the reaper seeing it as a zombie.
+        let (parent, adopted) = {
+            let parent = process.parent.lock();
+            process.zombie.store(true, Ordering::Release);
+            (parent.upgrade(), process.adopted.load(Ordering::Relaxed))
+        };
+        process.thread_exit_wq.notify_all(false);
+        process.notify_vfork_done();
+        if let Some(parent) = parent {
+            if adopted {
+                parent.reap_child(process);
+            }
+            parent.child_exit_seq.fetch_add(1, Ordering::AcqRel);
+            parent.child_exit_wq.notify_all(false);
+            let exit_signal = process.exit_signal.load(Ordering::Acquire);
+            if exit_signal != 0 && !adopted {
+                let info = SigInfo::from_process(exit_signal, SI_KERNEL, process.pid);
+                send_signal_to_process(&parent, info);
+            }
+        }
+    }
+    axtask::exit(exit_code);
 }